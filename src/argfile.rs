@@ -0,0 +1,137 @@
+use anyhow::{Context, Result};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+
+/// Returns the command line with the arguments from PACCAT_OPTS inserted
+/// before the user's own arguments so the latter take precedence.
+pub fn args_with_env() -> Vec<OsString> {
+    let mut args = env::args_os();
+    let mut with_env = args.next().into_iter().collect::<Vec<_>>();
+
+    if let Ok(opts) = env::var("PACCAT_OPTS") {
+        with_env.extend(split(&opts).into_iter().map(OsString::from));
+    }
+
+    with_env.extend(args);
+    with_env
+}
+
+/// Splits a string into words the way a shell would, honouring quotes and
+/// backslash escapes.
+fn split(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', q) if q != Some('\'') => {
+                if let Some(c) = chars.next() {
+                    word.push(c);
+                }
+                in_word = true;
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (c, Some(_)) => word.push(c),
+            ('"', None) | ('\'', None) => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (c, None) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (c, None) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+/// Expands `@file` arguments into the arguments listed in file, one per line.
+/// Empty lines and lines starting with # are ignored and an argument may be
+/// wrapped in single or double quotes to keep surrounding whitespace.
+pub fn expand_args<I: IntoIterator<Item = OsString>>(args: I) -> Result<Vec<OsString>> {
+    let mut expanded = Vec::new();
+    let mut raw = false;
+
+    for arg in args {
+        if raw || expanded.is_empty() {
+            expanded.push(arg);
+            continue;
+        }
+
+        match arg.to_str() {
+            Some("--") => {
+                raw = true;
+                expanded.push(arg);
+            }
+            Some(s) if s.starts_with('@') && s.len() > 1 => {
+                let path = &s[1..];
+                let content = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read argument file {}", path))?;
+                expanded.extend(parse(&content).into_iter().map(OsString::from));
+            }
+            _ => expanded.push(arg),
+        }
+    }
+
+    Ok(expanded)
+}
+
+fn parse(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(unquote)
+        .collect()
+}
+
+fn unquote(arg: &str) -> String {
+    for quote in ['"', '\''] {
+        if arg.len() >= 2 && arg.starts_with(quote) && arg.ends_with(quote) {
+            return arg[1..arg.len() - 1].to_string();
+        }
+    }
+    arg.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_words() {
+        assert_eq!(split("  --binary   -a\t-x "), ["--binary", "-a", "-x"]);
+        assert!(split("   ").is_empty());
+    }
+
+    #[test]
+    fn split_quotes_and_escapes() {
+        assert_eq!(
+            split(r#"--rewrite 'usr/a b=c' --contains "it's \"here\"""#),
+            ["--rewrite", "usr/a b=c", "--contains", r#"it's "here""#]
+        );
+        assert_eq!(split(r"a\ b 'c\d' '' x"), ["a b", r"c\d", "", "x"]);
+        assert_eq!(split("--a=\"b c\"d"), ["--a=b cd"]);
+    }
+
+    #[test]
+    fn parse_arg_files() {
+        let content = "# a comment\n--binary\n\n  ' spaced '  \n\"-x\"\n";
+        assert_eq!(parse(content), ["--binary", " spaced ", "-x"]);
+    }
+}
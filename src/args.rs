@@ -1,21 +1,121 @@
-use clap::{AppSettings, Parser};
+use clap::{AppSettings, ArgEnum, Parser};
 
 const ABOUT: &str = "Print pacman package files";
 
 const TEMPLATE: &str = "usage:
     paccat [options] -<Q|F> [targets] -- <files>
     paccat [options] <targets> -- <files>
+    paccat @argfile
 
 a target can be specified as:
-    <pkgname>, <repo>/<pkgname>, <url> or <file>.
+    <pkgname>, <repo>/<pkgname>, <repo>/, <url> or <file>.
+package names may contain globs such as 'python-*'.
 
 files can be specified as just the filename or the full path.
+a leading / anchors the file to the root of the package.
+braces such as 'etc/{foo,bar}.conf' are expanded unless --regex is used.
 
 {about}
 
 {options}";
 
-#[derive(Parser, Debug)]
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortBy {
+    Name,
+    Size,
+    Path,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeOrder {
+    Newest,
+    Oldest,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DumpOrder {
+    Archive,
+    Pattern,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Auto,
+    Tar,
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorWhen {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Checksum {
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl Checksum {
+    pub fn name(self) -> &'static str {
+        match self {
+            Checksum::Sha256 => "sha256",
+            Checksum::Sha512 => "sha512",
+            Checksum::Blake3 => "blake3",
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Auto,
+    Utf8,
+    Utf16le,
+    Utf16be,
+    Latin1,
+    Windows1252,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    Elf,
+    Script,
+    Image,
+    Archive,
+    Pdf,
+    Text,
+    Binary,
+}
+
+impl FileType {
+    pub fn name(self) -> &'static str {
+        match self {
+            FileType::Elf => "elf",
+            FileType::Script => "script",
+            FileType::Image => "image",
+            FileType::Archive => "archive",
+            FileType::Pdf => "pdf",
+            FileType::Text => "text",
+            FileType::Binary => "binary",
+        }
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
 #[clap(about = ABOUT,
     help_template = TEMPLATE,
     version = concat!("v", clap::crate_version!()),
@@ -29,12 +129,51 @@ pub struct Args {
         about = "Enable searching using regular expressions"
     )]
     pub regex: bool,
+    #[clap(
+        long,
+        conflicts_with = "no-basename",
+        about = "Match files against the file name even if they contain a /"
+    )]
+    pub basename: bool,
+    #[clap(
+        long,
+        about = "Match files against the full path even if they contain no /"
+    )]
+    pub no_basename: bool,
+    #[clap(
+        long,
+        about = "Treat bin, sbin and lib as the same as usr/bin, usr/sbin and usr/lib when matching full paths"
+    )]
+    pub usrmerge: bool,
     #[clap(
         short,
         long,
         about = "print all matches of files instead of just the first"
     )]
     pub all: bool,
+    #[clap(
+        long,
+        alias = "dereference-split-packages",
+        about = "Also read the other packages split from the same pkgbase as each target"
+    )]
+    pub split_siblings: bool,
+    #[clap(
+        long,
+        about = "Check which targets are already downloaded on a thread per cpu, for long lists of targets"
+    )]
+    pub concurrent_target_resolution: bool,
+    #[clap(
+        long,
+        alias = "prefer-local-cache-version",
+        about = "Read the newest version of each package in the cache instead of downloading the one in the database"
+    )]
+    pub prefer_cache: bool,
+    #[clap(
+        long,
+        conflicts_with = "first",
+        about = "Exit with 1 unless every package has a file matching every pattern, implies --all"
+    )]
+    pub strict_exit: bool,
 
     #[clap(
         short = 'e',
@@ -42,10 +181,640 @@ pub struct Args {
         about = "Extract matched files to the current directory"
     )]
     pub extract: bool,
+    #[clap(
+        long,
+        requires = "extract",
+        about = "Check each extracted file against the sha256sum in the .MTREE of its package"
+    )]
+    pub verify_extracted: bool,
+    #[clap(
+        long,
+        value_name = "dir",
+        conflicts_with_all = &["extract", "quiet", "contains", "is", "sort-by", "text-only", "count-bytes"],
+        about = "Write each matched file to <dir>/<pkg>__<path> with the path flattened"
+    )]
+    pub split_output: Option<String>,
+    #[clap(
+        long,
+        value_name = "template",
+        conflicts_with_all = &["extract", "split-output", "quiet", "contains", "is", "sort-by", "text-only", "count-bytes"],
+        about = "Write each matched file to the path made from <template> with {pkg}, {path}, {dir} and {basename}"
+    )]
+    pub output_template: Option<String>,
+    #[clap(
+        long,
+        conflicts_with_all = &["extract", "split-output", "output-template", "quiet", "checksum", "expect-hash", "grep", "count-matches", "count-bytes", "json-lines", "json", "sort-by", "compare-installed", "summary-only", "pager"],
+        about = "Write the matched files to stdout as a tar, with their mode, owner and mtime from the .MTREE"
+    )]
+    pub tar: bool,
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "format",
+        requires = "tar",
+        about = "Compress the tar of --tar with gzip, bzip2, xz or zstd"
+    )]
+    pub compress: Option<Compression>,
+    #[clap(
+        long,
+        value_name = "n",
+        requires = "compress",
+        about = "Set the level of --compress"
+    )]
+    pub compress_level: Option<u32>,
+    #[clap(
+        long,
+        about = "Give files written by --extract, --split-output or --output-template the mode and mtime from the .MTREE"
+    )]
+    pub preserve_permissions: bool,
+    #[clap(
+        long,
+        about = "Print the file a matched symlink points to in the same package instead"
+    )]
+    pub follow_links: bool,
+    #[clap(
+        long,
+        about = "Resolve . and .. and repeated slashes in the paths in packages before matching them"
+    )]
+    pub normalize_paths: bool,
+    #[clap(
+        long,
+        about = "Leave hardlinks empty instead of printing the content of the file they link to"
+    )]
+    pub no_resolve_hardlinks: bool,
     #[clap(short, long, about = "Print file names instead of file content")]
     pub quiet: bool,
+    #[clap(
+        long,
+        overrides_with = "name-only",
+        about = "When printing file names, print the full path (default)"
+    )]
+    pub full_path: bool,
+    #[clap(
+        long,
+        overrides_with = "full-path",
+        about = "When printing file names, print only the base name"
+    )]
+    pub name_only: bool,
+    #[clap(
+        short = 'c',
+        long,
+        value_name = "regex",
+        conflicts_with = "extract",
+        about = "Only print files whose content matches regex"
+    )]
+    pub contains: Option<String>,
+    #[clap(
+        long,
+        value_name = "regex",
+        conflicts_with_all = &["extract", "split-output", "output-template", "sort-by", "count-bytes", "compare-installed", "checksum", "json-lines"],
+        about = "Print the lines matching regex with the file name and line number like grep -rn"
+    )]
+    pub grep: Option<String>,
+    #[clap(
+        long,
+        conflicts_with_all = &["extract", "split-output", "output-template", "sort-by", "count-bytes", "compare-installed", "checksum", "json-lines"],
+        about = "Print the number of lines matching --grep or --contains in each file as pkg:path: n"
+    )]
+    pub count_matches: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &["extract", "split-output", "output-template", "count-bytes", "compare-installed", "checksum", "grep", "count-matches", "json-lines", "manifest"],
+        about = "Only print how many files matched in each package and in total, from the file lists of -F and -Q when there are no targets"
+    )]
+    pub summary_only: bool,
+    #[clap(
+        long,
+        value_name = "regex",
+        conflicts_with_all = &["grep", "count-matches", "json-lines", "raw"],
+        about = "Only print the lines of text files that match regex"
+    )]
+    pub line_filter: Option<String>,
+    #[clap(
+        long,
+        value_name = "regex",
+        conflicts_with_all = &["grep", "count-matches", "json-lines", "raw"],
+        about = "Leave out the lines of text files that match regex"
+    )]
+    pub line_reject: Option<String>,
+    #[clap(
+        short = 'o',
+        long,
+        requires = "contains",
+        about = "Only print the parts of the content that match --contains"
+    )]
+    pub only_matching: bool,
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "type",
+        conflicts_with = "extract",
+        about = "Only print files of the given type, matching every file if no files are given"
+    )]
+    pub is: Option<FileType>,
+    #[clap(
+        short = 'n',
+        long,
+        about = "Prefix printed lines with their line number"
+    )]
+    pub line_number: bool,
+    #[clap(long, about = "Print a header with the file name before each file")]
+    pub show_name: bool,
+    #[clap(
+        long,
+        about = "Print the repo, name and version of each package to stderr before its files"
+    )]
+    pub context_package: bool,
+    #[clap(
+        long,
+        alias = "group-output-by-package",
+        about = "Hold back the output of each package until it is read so it is never mixed with another's, as it is with --jobs"
+    )]
+    pub group_by_package: bool,
+    #[clap(
+        long,
+        value_name = "width",
+        require_equals = true,
+        conflicts_with_all = &["raw", "extract", "split-output", "output-template", "compare-installed"],
+        about = "Wrap long lines at the terminal width or --wrap=<width>"
+    )]
+    pub wrap: Option<Option<usize>>,
+    #[clap(
+        long,
+        alias = "color-content",
+        conflicts_with_all = &["raw", "extract", "split-output", "output-template", "compare-installed", "count-bytes"],
+        about = "Highlight toml, ini, json and shell files when printing to a terminal"
+    )]
+    pub highlight: bool,
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "when",
+        default_value = "auto",
+        about = "When to use colours for --highlight and --color-diff, auto means on a terminal without NO_COLOR"
+    )]
+    pub color: ColorWhen,
+    #[clap(
+        long,
+        conflicts_with_all = &["extract", "split-output", "output-template", "sort-by", "count-bytes"],
+        about = "Print the name, version, build date and packager before each package's files"
+    )]
+    pub include_pkg_meta: bool,
+    #[clap(
+        long,
+        about = "Let regex patterns match .PKGINFO, .MTREE, .BUILDINFO and .INSTALL"
+    )]
+    pub include_meta_members: bool,
+    #[clap(
+        long,
+        value_name = "sep",
+        default_value = "none",
+        about = "Print sep between the content of each file (none, nul or a string)"
+    )]
+    pub content_separator: String,
+    #[clap(
+        long,
+        value_name = "file",
+        about = "Also write everything that is printed to <file>"
+    )]
+    pub tee: Option<String>,
+    #[clap(
+        long,
+        value_name = "file",
+        alias = "emit-sha256sums-file",
+        conflicts_with_all = &["quiet", "checksum", "expect-hash", "count-bytes", "json-lines", "grep", "compare-installed", "filter-cmd", "encoding"],
+        about = "Write the sha256sum of every printed or extracted file to <file> for sha256sum -c"
+    )]
+    pub sha256sums: Option<String>,
+    #[clap(
+        long,
+        value_name = "cmd",
+        require_equals = true,
+        conflicts_with = "pick",
+        about = "Show what is printed on a terminal in $PAGER, less or --pager=<cmd>"
+    )]
+    pub pager: Option<Option<String>>,
+    #[clap(
+        long,
+        value_name = "fd",
+        conflicts_with = "report-file",
+        about = "Write a JSON report of the targets, matches and bytes read to <fd> at the end"
+    )]
+    pub report_fd: Option<i32>,
+    #[clap(
+        long,
+        value_name = "file",
+        about = "Write a JSON report of the targets, matches and bytes read to <file> at the end"
+    )]
+    pub report_file: Option<String>,
+    #[clap(
+        long,
+        about = "Write progress events as lines of JSON to --progress-fd for frontends"
+    )]
+    pub progress_json: bool,
+    #[clap(
+        long,
+        value_name = "fd",
+        default_value = "2",
+        about = "The file descriptor --progress-json writes to"
+    )]
+    pub progress_fd: i32,
+    #[clap(
+        long,
+        value_name = "statefile",
+        about = "Record the packages that were read in <statefile> and skip them when run again"
+    )]
+    pub resume: Option<String>,
+    #[clap(
+        long,
+        value_name = "cmd",
+        conflicts_with_all = &["extract", "split-output", "output-template", "raw"],
+        about = "Pipe each matched file through a shell command before printing it"
+    )]
+    pub filter_cmd: Option<String>,
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "encoding",
+        conflicts_with_all = &["extract", "split-output", "output-template", "raw"],
+        about = "Convert text files from <encoding> to UTF-8, or guess it with auto"
+    )]
+    pub encoding: Option<Encoding>,
+    #[clap(
+        long,
+        value_name = "n",
+        about = "Strip n leading components from printed and extracted paths"
+    )]
+    pub strip_components: Option<usize>,
+    #[clap(
+        long,
+        value_name = "from=to",
+        multiple_occurrences = true,
+        number_of_values = 1,
+        parse(try_from_str = parse_rewrite),
+        about = "Replace the leading directories from with to in printed and extracted paths, can be given more than once"
+    )]
+    pub rewrite: Vec<Rewrite>,
+    #[clap(
+        long,
+        value_name = "n",
+        about = "Skip files more than <n> directories deep (only --manifest adds their size to the directory above)"
+    )]
+    pub max_depth: Option<usize>,
     #[clap(long, about = "Print binary files")]
     pub binary: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &["binary", "extract", "split-output", "output-template", "compare-installed"],
+        about = "Fail when a matched file is binary instead of skipping it, with --keep-going only exit with 1"
+    )]
+    pub fail_on_binary: bool,
+    #[clap(
+        long,
+        value_name = "bytes",
+        default_value = "512",
+        about = "Look for a NUL in this many bytes at the start of a file to decide if it is binary"
+    )]
+    pub binary_threshold: usize,
+    #[clap(
+        long,
+        conflicts_with_all = &["binary", "raw", "extract"],
+        about = "Skip files that are not valid UTF-8 (holds each file in memory)"
+    )]
+    pub text_only: bool,
+    #[clap(
+        long,
+        overrides_with = "skip-empty",
+        about = "Count empty files as matches and list them with --quiet (default)"
+    )]
+    pub include_empty: bool,
+    #[clap(
+        long,
+        overrides_with = "include-empty",
+        conflicts_with_all = &["checksum", "extract", "split-output", "output-template"],
+        about = "Do not count empty files as matches or list them with --quiet (holds each file in memory)"
+    )]
+    pub skip_empty: bool,
+    #[clap(
+        long,
+        about = "Only print files that pacman would not install because of NoExtract"
+    )]
+    pub noextract_only: bool,
+    #[clap(
+        long,
+        about = "Note on stderr when a printed file is excluded by NoExtract"
+    )]
+    pub report_noextract: bool,
+    #[clap(
+        long,
+        about = "Print files byte for byte, overriding any detection or transform"
+    )]
+    pub raw: bool,
+    #[clap(long, about = "Allow reading .deb and .rpm package files")]
+    pub foreign: bool,
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "format",
+        default_value = "auto",
+        about = "Decompress packages with this compressor instead of detecting it"
+    )]
+    pub archive_format: ArchiveFormat,
+    #[clap(long, about = "Interactively pick which files to print")]
+    pub pick: bool,
+    #[clap(long, about = "List every file in the package with its size and mode")]
+    pub manifest: bool,
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "order",
+        conflicts_with = "manifest",
+        about = "List every file in the package with its .MTREE mtime, newest or oldest first"
+    )]
+    pub timestamp_sort: Option<TimeOrder>,
+    #[clap(
+        long,
+        value_name = "user",
+        about = "List the files the .MTREE says are owned by <user> (a name or uid, !<user> for any other) with their owner"
+    )]
+    pub owned_by_user: Option<String>,
+    #[clap(
+        long,
+        value_name = "group",
+        about = "List the files the .MTREE says are owned by <group> (a name or gid, !<group> for any other) with their owner"
+    )]
+    pub owned_by_group: Option<String>,
+    #[clap(
+        long,
+        about = "List the files the .MTREE says are setuid with their mode and owner"
+    )]
+    pub setuid: bool,
+    #[clap(
+        long,
+        about = "List the files the .MTREE says are setgid with their mode and owner"
+    )]
+    pub setgid: bool,
+    #[clap(
+        long,
+        about = "List the files and directories the .MTREE says anyone can write to with their mode and owner"
+    )]
+    pub world_writable: bool,
+    #[clap(
+        long,
+        about = "Exit with 1 when --owned-by-user, --setuid or the other audits list anything and 0 otherwise"
+    )]
+    pub fail_on_match: bool,
+    #[clap(long, about = "Print how each package is compressed then exit")]
+    pub print_compression: bool,
+    #[clap(
+        long,
+        alias = "print-build-info",
+        about = "Print the .BUILDINFO of each package, parsed with --json, then exit"
+    )]
+    pub build_info: bool,
+    #[clap(
+        long,
+        about = "Print the offset of each entry and its data in the decompressed tar and its size then exit"
+    )]
+    pub print_entry_offsets: bool,
+    #[clap(
+        long,
+        about = "Download the packages and check them against the sha256sum from the database without printing any files"
+    )]
+    pub verify_only: bool,
+    #[clap(
+        long,
+        about = "List files for --quiet and --count-bytes from the package's .MTREE instead of reading every file"
+    )]
+    pub entries_from_mtree: bool,
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "key",
+        conflicts_with = "extract",
+        about = "Sort printed files by name, size or path (holds all output in memory)"
+    )]
+    pub sort_by: Option<SortBy>,
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "order",
+        default_value = "archive",
+        conflicts_with_all = &["sort-by", "extract", "split-output", "output-template", "grep", "count-matches", "json-lines", "count-bytes", "compare-installed", "checksum"],
+        about = "Print the files of each package in archive order or grouped by the pattern they matched (holds each package's output in memory)"
+    )]
+    pub dump_order: DumpOrder,
+    #[clap(
+        long,
+        conflicts_with_all = &["extract", "quiet", "sort-by", "only-matching"],
+        about = "Print the number of bytes that would be printed instead of the files"
+    )]
+    pub count_bytes: bool,
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "algo",
+        conflicts_with_all = &["extract", "split-output", "output-template", "quiet", "contains", "is", "sort-by", "text-only", "count-bytes", "compare-installed", "wrap", "highlight", "from-disk", "filter-cmd"],
+        about = "Print a checksum of each matched file instead of its content"
+    )]
+    pub checksum: Option<Checksum>,
+    #[clap(
+        long,
+        conflicts_with = "expect-hash",
+        about = "Hash the files for --checksum or --sha256sums on a thread per cpu, still listing them in order"
+    )]
+    pub parallel_hash: bool,
+    #[clap(
+        long,
+        value_name = "algo:hex",
+        parse(try_from_str = parse_expect_hash),
+        conflicts_with_all = &["checksum", "extract", "split-output", "output-template", "quiet", "contains", "is", "sort-by", "text-only", "count-bytes", "compare-installed", "wrap", "highlight", "from-disk", "filter-cmd"],
+        about = "Check that the one matched file has this hash, exiting with 4 if it does not"
+    )]
+    pub expect_hash: Option<ExpectHash>,
+    #[clap(
+        long,
+        conflicts_with_all = &["extract", "split-output", "output-template", "quiet", "sort-by", "count-bytes", "only-matching"],
+        about = "Show a diff between the packaged files and the installed files under --root"
+    )]
+    pub compare_installed: bool,
+    #[clap(
+        long,
+        about = "Colour the added and removed lines of --compare-installed like git diff"
+    )]
+    pub color_diff: bool,
+    #[clap(long, about = "Print how each target was resolved as JSON and exit")]
+    pub resolve_only: bool,
+    #[clap(
+        long,
+        alias = "print-servers",
+        about = "Print how each target was resolved and the servers it would be downloaded from then exit"
+    )]
+    pub trace_resolve: bool,
+    #[clap(long, about = "Print the configured repos and their servers then exit")]
+    pub list_repos: bool,
+    #[clap(
+        long,
+        alias = "print-db-version",
+        about = "Print when the database and files database of each repo were last updated then exit"
+    )]
+    pub db_info: bool,
+    #[clap(
+        long,
+        about = "Print the files that are in more than one package of the files databases (or -Q) then exit"
+    )]
+    pub list_duplicates: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &["filedb", "localdb", "list-duplicates", "compare-installed"],
+        about = "List the files in etc/ of the installed packages (or the targets) that differ from under --root then exit, using the .MTREE of the installed version in the cache"
+    )]
+    pub list_changed_configs: bool,
+    #[clap(
+        long,
+        alias = "backup-files",
+        conflicts_with = "filedb",
+        about = "List the matched files that pacman keeps as backup files with their sha256sum from the .MTREE then exit, or with -Q and no targets the backup files of the installed packages with their md5sum"
+    )]
+    pub list_backup_files: bool,
+    #[clap(
+        long,
+        conflicts_with = "pick",
+        about = "Read more targets from stdin, one per line"
+    )]
+    pub stdin_targets: bool,
+    #[clap(
+        long,
+        value_name = "file",
+        conflicts_with_all = &["stdin-targets", "pick", "scan-cache"],
+        about = "Read `<target> <file>` lines like pacman -Fl prints and print each file from its own target (- for stdin)"
+    )]
+    pub query_list: Option<String>,
+    #[clap(
+        long,
+        value_name = "file",
+        about = "Read the files to print from a file, one per line, as regexes with --regex (- for stdin)"
+    )]
+    pub files_from: Option<String>,
+    #[clap(
+        long,
+        conflicts_with_all = &["stdin-targets", "query-list", "pick"],
+        about = "Read the files to print from stdin, one per line, when none are given after the targets and stdin is not a terminal"
+    )]
+    pub patternless_stdin: bool,
+    #[clap(
+        long,
+        about = "Print the targets whose packages were read but had no matching files to stderr"
+    )]
+    pub show_misses: bool,
+    #[clap(
+        long,
+        about = "Print the files given that matched nothing in any package to stderr"
+    )]
+    pub match_report: bool,
+    #[clap(
+        long,
+        alias = "no-warnings",
+        about = "Don't print warnings and notes to stderr, such as for skipped binary files. Errors are still printed"
+    )]
+    pub quiet_errors: bool,
+    #[clap(
+        long,
+        alias = "no-trailing-newline",
+        conflicts_with = "raw",
+        about = "Leave out the newline at the end of each file, for $(paccat ...)"
+    )]
+    pub chomp: bool,
+    #[clap(
+        long,
+        about = "Skip downloading the packages whose file list in the files databases does not have every file given"
+    )]
+    pub db_check: bool,
+    #[clap(
+        long,
+        conflicts_with = "no-files-db-check",
+        about = "Fail unless every sync database has file lists, even when what was asked for doesn't read them"
+    )]
+    pub require_files_db: bool,
+    #[clap(
+        long,
+        about = "Go on when -F, --db-check or --list-duplicates would fail for sync databases without file lists"
+    )]
+    pub no_files_db_check: bool,
+    #[clap(
+        long,
+        value_name = "repos",
+        about = "Look for packages in these comma separated repos first, then in the order of pacman.conf"
+    )]
+    pub repo_priority: Option<String>,
+    #[clap(
+        long,
+        about = "Match whole paths without regard to case, as on a case insensitive filesystem"
+    )]
+    pub case_fold_paths: bool,
+    #[clap(
+        long,
+        about = "Match any component of the path against the files given without regard to case"
+    )]
+    pub match_any_component_ci: bool,
+    #[clap(
+        long,
+        alias = "report-unreadable",
+        about = "Report packages and files that can't be read or are corrupt and carry on with the rest"
+    )]
+    pub keep_going: bool,
+    #[clap(
+        long,
+        about = "Make a file ending in / match everything in that directory as well as the directory"
+    )]
+    pub recursive_dir: bool,
+    #[clap(
+        long,
+        about = "Read entries whose names are not valid UTF-8, with U+FFFD in place of the invalid bytes"
+    )]
+    pub allow_nonutf8_names: bool,
+    #[clap(
+        short,
+        long,
+        value_name = "n",
+        default_value = "1",
+        conflicts_with_all = &["first", "pick", "include-pkg-meta", "max-matches"],
+        about = "Read n packages at once, holding back the output of each until the ones before it are printed"
+    )]
+    pub jobs: usize,
+    #[clap(
+        long,
+        value_name = "n",
+        about = "Decompress at most n packages at once with --jobs to bound memory use [default: the smaller of --jobs and 4]"
+    )]
+    pub max_concurrent_archives: Option<usize>,
+    #[clap(
+        long,
+        value_name = "name",
+        multiple_occurrences = true,
+        number_of_values = 1,
+        about = "Skip the packages with this name or glob, can be given more than once"
+    )]
+    pub exclude_pkg: Vec<String>,
+    #[clap(
+        long,
+        value_name = "dir",
+        require_equals = true,
+        about = "Search every package file in /var/cache/pacman/pkg or --scan-cache=<dir>"
+    )]
+    pub scan_cache: Option<Option<String>>,
+    #[clap(long, hidden = true)]
+    pub benchmark: bool,
+    #[clap(long, about = "Print machine readable output as JSON")]
+    pub json: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &["json", "quiet", "extract", "split-output", "output-template", "checksum", "compare-installed", "count-bytes", "sort-by"],
+        about = "Print a JSON object for each matched file as soon as it is read"
+    )]
+    pub json_lines: bool,
+    #[clap(short, long, about = "Print timing information to stderr")]
+    pub verbose: bool,
     #[clap(
         short = 'F',
         long = "files",
@@ -59,6 +828,96 @@ pub struct Args {
         about = "Use local database to search for files before deciding to download"
     )]
     pub localdb: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &["extract", "split-output", "output-template", "compare-installed", "pick", "manifest"],
+        about = "Read matched files of installed packages from disk, if they are unchanged, instead of downloading them"
+    )]
+    pub from_disk: bool,
+    #[clap(
+        long,
+        requires = "localdb",
+        conflicts_with_all = &["filedb", "updated", "deps", "required-by", "split-siblings", "only-new-files", "testing", "repo-priority", "db-check", "verify-only", "list-repos", "db-info", "list-duplicates", "require-files-db"],
+        about = "Read the installed files of --localdb from the root without the sync databases or downloading"
+    )]
+    pub no_sync_db: bool,
+    #[clap(
+        long,
+        about = "Ignore case when a package name does not match any package exactly"
+    )]
+    pub fuzzy_names: bool,
+    #[clap(
+        long,
+        about = "Look packages up in the testing repos before the others"
+    )]
+    pub testing: bool,
+    #[clap(
+        long,
+        alias = "since-upgrade",
+        about = "When scanning, only use packages with a newer version than the one installed"
+    )]
+    pub updated: bool,
+    #[clap(
+        long,
+        conflicts_with = "manifest",
+        about = "Stop after the first package with a match, downloading packages one at a time"
+    )]
+    pub first: bool,
+    #[clap(long, about = "Also search every package the targets depend on")]
+    pub deps: bool,
+    #[clap(
+        long,
+        value_name = "depth",
+        requires = "deps",
+        about = "Only follow dependencies this many levels deep"
+    )]
+    pub deps_depth: Option<usize>,
+    #[clap(
+        long,
+        requires = "deps",
+        about = "With --deps, also follow optional dependencies"
+    )]
+    pub optdeps: bool,
+    #[clap(long, about = "Also search every package that depends on the targets")]
+    pub required_by: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &["from-disk", "pick"],
+        about = "Only match files that the installed version of the package does not have"
+    )]
+    pub only_new_files: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &["select-oldest", "first", "manifest", "from-disk", "pick"],
+        about = "Of the matched files with the same name, only print the newest by .MTREE mtime"
+    )]
+    pub select_newest: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &["first", "manifest", "from-disk", "pick"],
+        about = "Of the matched files with the same name, only print the oldest by .MTREE mtime"
+    )]
+    pub select_oldest: bool,
+    #[clap(
+        long,
+        value_name = "count",
+        conflicts_with_all = &["manifest", "first", "expect-one"],
+        about = "Exit with status 3 unless exactly <count> files match"
+    )]
+    pub expect: Option<usize>,
+    #[clap(
+        long,
+        conflicts_with_all = &["manifest", "first"],
+        about = "Exit with status 3 unless exactly one file matches"
+    )]
+    pub expect_one: bool,
+    #[clap(
+        long,
+        alias = "no-exit-code",
+        conflicts_with_all = &["expect", "expect-one", "expect-hash", "verify-extracted", "fail-on-binary", "compare-installed"],
+        about = "Exit with 0 even if no files matched, only failing on errors"
+    )]
+    pub exit_zero: bool,
 
     #[clap(
         short,
@@ -82,19 +941,211 @@ pub struct Args {
         about = "Set an alternative cache directory"
     )]
     pub cachedir: Option<String>,
+    #[clap(
+        long,
+        value_name = "path",
+        about = "Download packages to this directory instead of the cache directory"
+    )]
+    pub download_dir: Option<String>,
+    #[clap(
+        long,
+        about = "Remove the packages that were downloaded before exiting"
+    )]
+    pub cleanup: bool,
+    #[clap(long, about = "Do not lock the download directory while downloading")]
+    pub no_lock: bool,
+
+    #[clap(
+        long,
+        value_name = "url",
+        about = "Download packages from url instead of the configured servers"
+    )]
+    pub repo_url: Option<String>,
+    #[clap(
+        long,
+        value_name = "url",
+        about = "Download the repo database at url, such as https://example.com/repo/x86_64/foo.db, and use it as the repo foo for this run"
+    )]
+    pub repo_db: Option<String>,
+    #[clap(
+        long,
+        value_name = "rate",
+        parse(try_from_str = parse_size),
+        about = "Limit the download rate in bytes per second (accepts K, M and G suffixes)"
+    )]
+    pub limit_rate: Option<u64>,
+    #[clap(
+        long,
+        value_name = "n",
+        about = "Follow at most n redirects when downloading, using curl [default: 10]"
+    )]
+    pub max_redirects: Option<u32>,
+    #[clap(
+        long,
+        conflicts_with = "max-redirects",
+        about = "Fail downloads that redirect instead of following them, using curl"
+    )]
+    pub no_redirects: bool,
+    #[clap(
+        long,
+        value_name = "file",
+        about = "Trust the CA certificates in file for downloads, using curl"
+    )]
+    pub ca_cert: Option<String>,
+    #[clap(
+        long,
+        about = "Don't verify TLS certificates for downloads, using curl. Anyone in between can change the packages"
+    )]
+    pub insecure: bool,
+    #[clap(
+        long,
+        about = "Refuse to read any package whose .sig can't be verified against the pacman keyring with gpg. Downloads use curl so the .sig is fetched too"
+    )]
+    pub require_signature: bool,
+    #[clap(
+        long,
+        requires = "require-signature",
+        about = "With --require-signature, read packages that have no .sig with a warning instead of refusing them. Bad signatures are still refused"
+    )]
+    pub allow_unsigned: bool,
+    #[clap(
+        long,
+        value_name = "path",
+        about = "Set the keyring --require-signature checks against, <root>/etc/pacman.d/gnupg by default"
+    )]
+    pub gpgdir: Option<String>,
+    #[clap(
+        long,
+        value_name = "size",
+        parse(try_from_str = parse_size),
+        about = "Only match files of at least <size> bytes (accepts K, M and G suffixes)"
+    )]
+    pub min_size: Option<u64>,
+    #[clap(
+        long,
+        value_name = "size",
+        parse(try_from_str = parse_size),
+        about = "Only match files of at most <size> bytes (accepts K, M and G suffixes)"
+    )]
+    pub max_size: Option<u64>,
+    #[clap(
+        long,
+        value_name = "n",
+        about = "Stop with an error when a package has more than <n> entries"
+    )]
+    pub entry_limit: Option<usize>,
+    #[clap(
+        long,
+        value_name = "n",
+        about = "Stop with an error when more than <n> files match in all packages"
+    )]
+    pub max_matches: Option<usize>,
+    #[clap(
+        long,
+        value_name = "size",
+        parse(try_from_str = parse_size),
+        about = "Stop with an error when a package decompresses to more than <size> bytes (accepts K, M and G suffixes)"
+    )]
+    pub max_total_size: Option<u64>,
+    #[clap(
+        long,
+        about = "When a download fails, try the other servers of the package's repo"
+    )]
+    pub mirror_failover: bool,
+    #[clap(
+        long,
+        value_name = "bytes",
+        default_value = "65536",
+        about = "Set the size of the output buffer"
+    )]
+    pub buffer_size: usize,
 
     #[clap(
-        required_unless_present_any = ["localdb", "filedb"],
+        required_unless_present_any = ["localdb", "filedb", "list-repos", "db-info", "query-list", "list-duplicates", "list-changed-configs", "stdin-targets", "scan-cache"],
         value_name = "target",
         about = "List of packages, package files, or package urls"
     )]
     pub targets: Vec<String>,
 
     #[clap(
-        required = true,
+        required_unless_present_any = ["pick", "manifest", "timestamp-sort", "query-list", "files-from", "owned-by-user", "owned-by-group", "setuid", "setgid", "world-writable", "print-compression", "build-info", "print-entry-offsets", "verify-only", "is", "grep", "resolve-only", "trace-resolve", "noextract-only", "list-repos", "db-info", "list-duplicates", "list-changed-configs", "list-backup-files", "benchmark", "summary-only", "patternless-stdin"],
         raw = true,
         value_name = "file",
         about = "Files to search for"
     )]
     pub files: Vec<String>,
 }
+
+#[derive(Clone, Debug)]
+pub struct ExpectHash {
+    pub algo: Checksum,
+    pub hex: String,
+}
+
+pub fn parse_expect_hash(s: &str) -> Result<ExpectHash, String> {
+    let (algo, hex) = s
+        .split_once(':')
+        .ok_or_else(|| format!("'{}' is not <algo>:<hex>", s))?;
+    let (algo, len) = match algo {
+        "sha256" => (Checksum::Sha256, 64),
+        "sha512" => (Checksum::Sha512, 128),
+        "blake3" => (Checksum::Blake3, 64),
+        _ => {
+            return Err(format!(
+                "unknown hash '{}', expected sha256, sha512 or blake3",
+                algo
+            ))
+        }
+    };
+    if hex.len() != len || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("'{}' is not a {} hash", hex, algo.name()));
+    }
+
+    Ok(ExpectHash {
+        algo,
+        hex: hex.to_ascii_lowercase(),
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct Rewrite {
+    pub from: String,
+    pub to: String,
+}
+
+pub fn parse_rewrite(s: &str) -> Result<Rewrite, String> {
+    let (from, to) = s
+        .split_once('=')
+        .ok_or_else(|| format!("'{}' is not <from>=<to>", s))?;
+    let (from, to) = (from.trim_matches('/'), to.trim_end_matches('/'));
+    if from.is_empty() {
+        return Err(format!("'{}' has nothing to replace", s));
+    }
+    if to.starts_with('/') || to.split('/').any(|c| c == "..") {
+        return Err(format!(
+            "'{}' would write outside of the current directory",
+            to
+        ));
+    }
+
+    Ok(Rewrite {
+        from: from.to_string(),
+        to: to.to_string(),
+    })
+}
+
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let (num, mult) = match s.char_indices().last() {
+        Some((i, 'k')) | Some((i, 'K')) => (&s[..i], 1 << 10),
+        Some((i, 'm')) | Some((i, 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'g')) | Some((i, 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+
+    let n = num
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| format!("invalid size '{}'", s))?;
+    n.checked_mul(mult)
+        .ok_or_else(|| format!("size '{}' is too large", s))
+}
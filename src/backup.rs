@@ -0,0 +1,95 @@
+use crate::args::Args;
+use crate::json;
+use crate::manifest::pkg_name;
+use crate::mtree::read_mtree;
+use crate::pacman::PkgDb;
+use crate::pkginfo::read_pkginfo;
+use crate::Match;
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Lists the matched files that the .PKGINFO of each package marks as
+/// backup, for --list-backup-files. The sha256sum comes from the .MTREE when
+/// the package has one.
+pub fn print_backup_files(pkgs: &[String], matcher: &mut Match, args: &Args) -> Result<i32> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut found = 0;
+
+    for pkg in pkgs {
+        let info = match read_pkginfo(pkg)? {
+            Some(info) => info,
+            None => bail!("{} does not contain a .PKGINFO", pkg),
+        };
+        let sums = read_mtree(pkg)?
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|e| Some((e.path, e.sha256?)))
+            .collect::<HashMap<_, _>>();
+
+        let name = pkg_name(pkg);
+        for path in &info.backup {
+            if args.files.is_empty() || matcher.is_match(path, false) {
+                print_backup(&mut stdout, name, path, "sha256", sums.get(path), args)?;
+                found += 1;
+            }
+        }
+    }
+
+    Ok(if found == 0 { 1 } else { 0 })
+}
+
+/// Lists the matched backup files of the installed packages with the md5sum
+/// pacman recorded for them, for --list-backup-files with -Q and no targets.
+pub fn print_installed_backup_files<'a, D: PkgDb<'a>>(
+    db: &'a D,
+    args: &Args,
+    matcher: &mut Match,
+) -> Result<i32> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut found = 0;
+
+    for pkg in db.local_pkgs() {
+        let (name, _) = db.name_version(pkg);
+        for (path, md5) in db.backup(pkg) {
+            if args.files.is_empty() || matcher.is_match(&path, false) {
+                let md5 = Some(md5).filter(|md5| !md5.is_empty());
+                print_backup(&mut stdout, &name, &path, "md5", md5.as_ref(), args)?;
+                found += 1;
+            }
+        }
+    }
+
+    Ok(if found == 0 { 1 } else { 0 })
+}
+
+fn print_backup<W: Write>(
+    out: &mut W,
+    pkg: &str,
+    path: &str,
+    algo: &str,
+    hash: Option<&String>,
+    args: &Args,
+) -> Result<()> {
+    if args.json {
+        writeln!(
+            out,
+            "{{\"package\": {}, \"path\": {}, \"{}\": {}}}",
+            json::string(pkg),
+            json::string(path),
+            algo,
+            hash.map_or("null".to_string(), |h| json::string(h))
+        )?;
+    } else {
+        writeln!(
+            out,
+            "{}\t{}\t{}",
+            pkg,
+            hash.map_or("-", |h| h.as_str()),
+            path
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use crate::args::Args;
+use crate::manifest::pkg_name;
+use crate::Match;
+use anyhow::{Context, Result};
+use compress_tools::{ArchiveContents, ArchiveIterator};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+const BUFFER_SIZES: &[usize] = &[8 * 1024, 64 * 1024, 256 * 1024, 1024 * 1024];
+
+/// Times decompressing, matching and writing each package and prints a
+/// table to compare the stages and buffer sizes.
+pub fn benchmark(pkgs: &[String], matcher: &mut Match, args: &Args) -> Result<i32> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    writeln!(
+        stdout,
+        "{:<40} {:<12} {:>10} {:>12} {:>10}",
+        "package", "stage", "setting", "time", "MiB/s"
+    )?;
+
+    for pkg in pkgs {
+        let name = pkg_name(pkg);
+        let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+
+        let start = Instant::now();
+        let mut names = Vec::new();
+        let mut data = Vec::new();
+        for content in ArchiveIterator::from_read(file)? {
+            match content {
+                ArchiveContents::StartOfEntry(name) => names.push(name),
+                ArchiveContents::DataChunk(chunk) => data.extend_from_slice(&chunk),
+                ArchiveContents::EndOfEntry => (),
+                ArchiveContents::Err(e) => return Err(e.into()),
+            }
+        }
+        let size = data.len() as u64;
+        row(&mut stdout, name, "decompress", "-", start.elapsed(), size)?;
+
+        let start = Instant::now();
+        let matched = names.iter().filter(|n| matcher.is_match(n, false)).count();
+        let setting = format!("{} of {}", matched, names.len());
+        row(&mut stdout, name, "match", &setting, start.elapsed(), 0)?;
+
+        for &buffer_size in BUFFER_SIZES {
+            let null = OpenOptions::new().write(true).open("/dev/null")?;
+            let mut out = BufWriter::with_capacity(buffer_size, null);
+            let start = Instant::now();
+            for chunk in data.chunks(args.buffer_size.max(1)) {
+                out.write_all(chunk)?;
+            }
+            out.flush()?;
+            let setting = format!("{}K", buffer_size / 1024);
+            row(&mut stdout, name, "output", &setting, start.elapsed(), size)?;
+        }
+    }
+
+    Ok(0)
+}
+
+fn row<W: Write>(
+    out: &mut W,
+    pkg: &str,
+    stage: &str,
+    setting: &str,
+    time: Duration,
+    bytes: u64,
+) -> io::Result<()> {
+    let rate = if bytes == 0 {
+        "-".to_string()
+    } else {
+        format!(
+            "{:.1}",
+            bytes as f64 / time.as_secs_f64() / (1024.0 * 1024.0)
+        )
+    };
+
+    writeln!(
+        out,
+        "{:<40} {:<12} {:>10} {:>12} {:>10}",
+        pkg,
+        stage,
+        setting,
+        format!("{:.2?}", time),
+        rate
+    )
+}
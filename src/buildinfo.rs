@@ -0,0 +1,103 @@
+use crate::args::Args;
+use crate::json;
+use crate::manifest::pkg_name;
+use anyhow::{Context, Result};
+use compress_tools::uncompress_archive_file;
+use std::fs::File;
+use std::io::{self, Write};
+
+/// The keys of a .BUILDINFO that can be given more than once.
+const LISTS: &[&str] = &["buildenv", "options", "installed"];
+
+/// Prints the .BUILDINFO of each package for --build-info, as it is or
+/// parsed under --json.
+pub fn print_build_info(pkgs: &[String], args: &Args) -> Result<i32> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut ret = 0;
+
+    for pkg in pkgs {
+        let name = pkg_name(pkg);
+        let buildinfo = match read_buildinfo(pkg)? {
+            Some(buildinfo) => buildinfo,
+            None => {
+                eprintln!("{} does not contain a .BUILDINFO", name);
+                ret = 1;
+                continue;
+            }
+        };
+
+        if args.json {
+            writeln!(stdout, "{}", to_json(name, &buildinfo))?;
+        } else {
+            if pkgs.len() > 1 {
+                writeln!(stdout, "==> {} <==", name)?;
+            }
+            stdout.write_all(buildinfo.as_bytes())?;
+        }
+    }
+
+    Ok(ret)
+}
+
+fn read_buildinfo(pkg: &str) -> Result<Option<String>> {
+    let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+    let mut data = Vec::new();
+    if uncompress_archive_file(file, &mut data, ".BUILDINFO").is_err() {
+        return Ok(None);
+    }
+
+    Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+}
+
+/// The keys of buildinfo as a JSON object. The keys in LISTS are arrays and
+/// the installed packages are split into their name, version and arch.
+fn to_json(pkg: &str, buildinfo: &str) -> String {
+    let mut fields = vec![("package".to_string(), json::string(pkg))];
+    let mut lists = LISTS
+        .iter()
+        .map(|&key| (key, Vec::new()))
+        .collect::<Vec<_>>();
+
+    for line in buildinfo.lines() {
+        let (key, val) = match line.split_once(" = ") {
+            Some(kv) if !line.starts_with('#') => kv,
+            _ => continue,
+        };
+
+        match lists.iter_mut().find(|(list, _)| *list == key) {
+            Some((_, values)) if key == "installed" => values.push(installed(val)),
+            Some((_, values)) => values.push(json::string(val)),
+            None => fields.push((key.to_string(), json::string(val))),
+        }
+    }
+
+    let fields = fields
+        .into_iter()
+        .map(|(key, val)| format!("{}: {}", json::string(&key), val))
+        .chain(
+            lists
+                .into_iter()
+                .map(|(key, values)| format!("\"{}\": [{}]", key, values.join(", "))),
+        )
+        .collect::<Vec<_>>();
+    format!("{{{}}}", fields.join(", "))
+}
+
+/// Splits an installed package like `acl-2.3.1-3-x86_64` into its name,
+/// version and arch.
+fn installed(pkg: &str) -> String {
+    let mut parts = pkg.rsplitn(4, '-');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(arch), Some(rel), Some(ver), Some(name)) => format!(
+            "{{\"name\": {}, \"version\": {}, \"arch\": {}}}",
+            json::string(name),
+            json::string(&format!("{}-{}", ver, rel)),
+            json::string(arch)
+        ),
+        _ => format!(
+            "{{\"name\": {}, \"version\": null, \"arch\": null}}",
+            json::string(pkg)
+        ),
+    }
+}
@@ -0,0 +1,76 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::path::Path;
+
+/// A package file found in a cache dir.
+pub struct Cached {
+    pub path: String,
+    pub version: String,
+}
+
+/// Splits a package file name like `acl-2.3.1-3-x86_64.pkg.tar.zst` into
+/// its name and its version with the pkgrel.
+pub fn parse_file_name(file: &str) -> Option<(&str, String)> {
+    let stem = &file[..file.find(".pkg.tar")?];
+    let mut parts = stem.rsplitn(4, '-');
+    let (_arch, rel, ver, name) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    Some((name, format!("{}-{}", ver, rel)))
+}
+
+/// The package file of exactly version of the package called name in any of
+/// dirs.
+pub fn find(dirs: &[String], name: &str, version: &str) -> Option<String> {
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let file = entry.file_name();
+            let file = match file.to_str() {
+                Some(file) if !file.ends_with(".sig") && !file.ends_with(".part") => file,
+                _ => continue,
+            };
+            if matches!(parse_file_name(file), Some((n, v)) if n == name && v == version) {
+                return Some(Path::new(dir).join(file).to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    None
+}
+
+/// The newest version of the package called name in any of dirs.
+pub fn newest<F>(dirs: &[String], name: &str, vercmp: F) -> Option<Cached>
+where
+    F: Fn(&str, &str) -> Ordering,
+{
+    let mut newest: Option<Cached> = None;
+
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let file = entry.file_name();
+            let file = match file.to_str() {
+                Some(file) if !file.ends_with(".sig") && !file.ends_with(".part") => file,
+                _ => continue,
+            };
+            let version = match parse_file_name(file) {
+                Some((n, version)) if n == name => version,
+                _ => continue,
+            };
+            if matches!(&newest, Some(n) if vercmp(&version, &n.version) != Ordering::Greater) {
+                continue;
+            }
+            newest = Some(Cached {
+                path: Path::new(dir).join(file).to_string_lossy().into_owned(),
+                version,
+            });
+        }
+    }
+
+    newest
+}
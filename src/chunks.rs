@@ -0,0 +1,61 @@
+use compress_tools::ArchiveContents;
+
+/// Joins the first chunks of every entry until there are at least min
+/// bytes, so the checks on the first chunk don't depend on how much
+/// libarchive happened to read at once.
+pub struct FirstChunk<I> {
+    inner: I,
+    min: usize,
+    head: Option<Vec<u8>>,
+    pending: Option<ArchiveContents>,
+}
+
+impl<I> FirstChunk<I> {
+    pub fn new(inner: I, min: usize) -> Self {
+        FirstChunk {
+            inner,
+            min,
+            head: None,
+            pending: None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = ArchiveContents>> Iterator for FirstChunk<I> {
+    type Item = ArchiveContents;
+
+    fn next(&mut self) -> Option<ArchiveContents> {
+        if let Some(pending) = self.pending.take() {
+            return Some(pending);
+        }
+
+        loop {
+            let content = match self.inner.next() {
+                Some(content) => content,
+                None => return self.head.take().map(ArchiveContents::DataChunk),
+            };
+
+            match (content, &mut self.head) {
+                (ArchiveContents::StartOfEntry(name), head) => {
+                    *head = Some(Vec::new());
+                    return Some(ArchiveContents::StartOfEntry(name));
+                }
+                (ArchiveContents::DataChunk(v), Some(head)) => {
+                    head.extend_from_slice(&v);
+                    if head.len() >= self.min {
+                        return self.head.take().map(ArchiveContents::DataChunk);
+                    }
+                }
+                (content @ ArchiveContents::EndOfEntry, head)
+                | (content @ ArchiveContents::Err(_), head) => match head.take() {
+                    Some(head) if !head.is_empty() => {
+                        self.pending = Some(content);
+                        return Some(ArchiveContents::DataChunk(head));
+                    }
+                    _ => return Some(content),
+                },
+                (content, None) => return Some(content),
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::args::Args;
+use crate::cache;
+use crate::hash;
+use crate::json;
+use crate::mtree::{read_mtree, Kind};
+use crate::noextract::NoExtract;
+use crate::pacman::PkgDb;
+use crate::Match;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Lists the files under etc/ of each installed package that differ from
+/// under --root, for --list-changed-configs. The .MTREE of the installed
+/// version in the cache gives the size and sha256sum each file was
+/// packaged with. Files NoExtract keeps off the disk are left out.
+pub fn list_changed_configs<'a, D: PkgDb<'a>>(
+    db: &'a D,
+    args: &Args,
+    matcher: &mut Match,
+    noextract: &NoExtract,
+) -> Result<i32> {
+    let root = Path::new(args.root.as_deref().unwrap_or("/"));
+    let cachedirs = db.cachedirs();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut unchecked = Vec::new();
+    let mut changed = 0;
+
+    for pkg in db.local_pkgs() {
+        let (name, version) = db.name_version(pkg);
+        if !args.targets.is_empty() && !args.targets.contains(&name) {
+            continue;
+        }
+        if !db.any_file(pkg, &mut |file| is_config(file)) {
+            continue;
+        }
+
+        let entries = match cache::find(&cachedirs, &name, &version) {
+            Some(archive) => read_mtree(&archive)?,
+            None => None,
+        };
+        let entries = match entries {
+            Some(entries) => entries,
+            None => {
+                unchecked.push(name);
+                continue;
+            }
+        };
+        let backup = db
+            .backup(pkg)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<HashSet<_>>();
+
+        for entry in entries {
+            if entry.kind != Kind::File
+                || !is_config(&entry.path)
+                || noextract.is_match(&entry.path)
+                || !(args.files.is_empty() || matcher.is_match(&entry.path, false))
+            {
+                continue;
+            }
+            let sha256 = match &entry.sha256 {
+                Some(sha256) => sha256,
+                None => continue,
+            };
+
+            let path = root.join(&entry.path);
+            let status = match fs::metadata(&path) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => "missing",
+                Err(e) => {
+                    return Err(e).with_context(|| format!("failed to read {}", path.display()))
+                }
+                Ok(meta) if meta.len() != entry.size => "modified",
+                Ok(_) if &hash::sha256_file(&path)? != sha256 => "modified",
+                Ok(_) => continue,
+            };
+
+            let is_backup = backup.contains(&entry.path);
+            if args.json {
+                writeln!(
+                    stdout,
+                    "{{\"package\": {}, \"path\": {}, \"status\": \"{}\", \"backup\": {}}}",
+                    json::string(&name),
+                    json::string(&entry.path),
+                    status,
+                    is_backup
+                )?;
+            } else {
+                let kind = if is_backup { "backup" } else { "file" };
+                writeln!(stdout, "{}\t{}\t{}\t{}", name, status, kind, entry.path)?;
+            }
+            changed += 1;
+        }
+    }
+
+    if !unchecked.is_empty() {
+        warning!(
+            "warning: {} installed packages with files in etc/ are not in the cache and were not checked",
+            unchecked.len()
+        );
+        if args.verbose {
+            eprintln!("not checked: {}", unchecked.join(" "));
+        }
+    }
+
+    Ok(if changed == 0 { 0 } else { 1 })
+}
+
+fn is_config(path: &str) -> bool {
+    path.starts_with("etc/") && !path.ends_with('/')
+}
@@ -0,0 +1,129 @@
+use std::io::{self, Write};
+
+const RESET: &str = "\x1b[0m";
+const HEADER: &str = "\x1b[1m";
+const HUNK: &str = "\x1b[36m";
+const DEL: &str = "\x1b[31m";
+const ADD: &str = "\x1b[32m";
+
+const CONTEXT: usize = 3;
+const MAX_CELLS: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Same,
+    Del,
+    Add,
+}
+
+/// Writes a unified diff of two texts, coloured like git diff if colour is
+/// set. Returns false if the texts are too large to diff line by line.
+pub fn unified<W: Write>(
+    out: &mut W,
+    old_name: &str,
+    new_name: &str,
+    old: &str,
+    new: &str,
+    colour: bool,
+) -> io::Result<bool> {
+    let a = old.lines().collect::<Vec<_>>();
+    let b = new.lines().collect::<Vec<_>>();
+
+    if (a.len() + 1).saturating_mul(b.len() + 1) > MAX_CELLS {
+        return Ok(false);
+    }
+
+    let ops = diff(&a, &b);
+    let paint = |c: &'static str| if colour { (c, RESET) } else { ("", "") };
+    let (header, header_end) = paint(HEADER);
+    let (hunk_start, hunk_end) = paint(HUNK);
+    let (del, del_end) = paint(DEL);
+    let (add, add_end) = paint(ADD);
+
+    writeln!(out, "{}--- {}{}", header, old_name, header_end)?;
+    writeln!(out, "{}+++ {}{}", header, new_name, header_end)?;
+
+    let changes = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, _, _))| *op != Op::Same)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
+    let mut i = 0;
+    while i < changes.len() {
+        let start = changes[i].saturating_sub(CONTEXT);
+        let mut end = changes[i];
+        while i < changes.len() && changes[i] <= end + 2 * CONTEXT + 1 {
+            end = changes[i];
+            i += 1;
+        }
+        let end = (end + CONTEXT + 1).min(ops.len());
+        let hunk = &ops[start..end];
+
+        let (old_start, new_start) = (hunk[0].1, hunk[0].2);
+        let old_len = hunk.iter().filter(|(op, _, _)| *op != Op::Add).count();
+        let new_len = hunk.iter().filter(|(op, _, _)| *op != Op::Del).count();
+        writeln!(
+            out,
+            "{}@@ -{} +{} @@{}",
+            hunk_start,
+            range(old_start, old_len),
+            range(new_start, new_len),
+            hunk_end
+        )?;
+
+        for &(op, ai, bi) in hunk {
+            match op {
+                Op::Same => writeln!(out, " {}", a[ai])?,
+                Op::Del => writeln!(out, "{}-{}{}", del, a[ai], del_end)?,
+                Op::Add => writeln!(out, "{}+{}{}", add, b[bi], add_end)?,
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        len => format!("{},{}", start + 1, len),
+    }
+}
+
+// a longest common subsequence table over the line suffixes, walked forward
+// to produce the edit script with the line index into each side
+fn diff(a: &[&str], b: &[&str]) -> Vec<(Op, usize, usize)> {
+    let w = b.len() + 1;
+    let mut lcs = vec![0u32; (a.len() + 1) * w];
+
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i * w + j] = if a[i] == b[j] {
+                lcs[(i + 1) * w + j + 1] + 1
+            } else {
+                lcs[(i + 1) * w + j].max(lcs[i * w + j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            ops.push((Op::Same, i, j));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[(i + 1) * w + j] >= lcs[i * w + j + 1]) {
+            ops.push((Op::Del, i, j));
+            i += 1;
+        } else {
+            ops.push((Op::Add, i, j));
+            j += 1;
+        }
+    }
+
+    ops
+}
@@ -0,0 +1,253 @@
+use crate::args::Args;
+use crate::error::PaccatError;
+use crate::interrupt;
+use crate::json;
+use crate::progress;
+use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::fcntl::{flock, FlockArg};
+use nix::unistd::{access, AccessFlags};
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+static CLEANUP: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// An advisory lock on a download directory so concurrent paccat processes
+/// do not download into it at the same time. Released when dropped.
+pub struct CacheLock {
+    _file: File,
+}
+
+/// Locks dir, waiting for any other paccat holding it unless paccat is
+/// interrupted. Returns None if the lock file can not be created, in which
+/// case the download will most likely fail with a better error anyway.
+pub fn lock(dir: &str) -> Result<Option<CacheLock>> {
+    let path = Path::new(dir).join(".paccat.lck");
+    let _ = fs::create_dir_all(dir);
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path);
+    let file = match file {
+        Ok(file) => file,
+        Err(_) => return Ok(None),
+    };
+
+    let mut waiting = false;
+    loop {
+        match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => return Ok(Some(CacheLock { _file: file })),
+            Err(Errno::EWOULDBLOCK) if !waiting => {
+                warning!("waiting for lock on {}...", path.display());
+                waiting = true;
+            }
+            Err(Errno::EWOULDBLOCK) | Err(Errno::EINTR) => (),
+            Err(e) => return Err(e).with_context(|| format!("failed to lock {}", path.display())),
+        }
+        interrupt::check()?;
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Marks a file to be removed by cleanup() when paccat exits.
+pub fn remove_on_exit(path: PathBuf) {
+    CLEANUP.lock().unwrap().push(path);
+}
+
+pub fn cleanup() {
+    for path in CLEANUP.lock().unwrap().drain(..) {
+        let _ = if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        };
+    }
+}
+
+/// Whether one of the options that only curl supports was given.
+pub fn needs_curl(args: &Args) -> bool {
+    args.limit_rate.is_some()
+        || args.download_dir.is_some()
+        || args.max_redirects.is_some()
+        || args.no_redirects
+        || args.ca_cert.is_some()
+        || args.insecure
+        || args.require_signature
+}
+
+/// Whether the files downloaded by curl can be written to dir.
+pub fn writable(dir: &str) -> bool {
+    access(dir, AccessFlags::W_OK | AccessFlags::X_OK).is_ok()
+}
+
+/// Downloads urls into dir one at a time with curl. This is used instead of
+/// libalpm's downloader for options libalpm does not support.
+pub fn curl(urls: &[String], dir: &str, args: &Args) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir))?;
+    if let Some(ca_cert) = &args.ca_cert {
+        fs::metadata(ca_cert).with_context(|| format!("failed to read {}", ca_cert))?;
+    }
+    if args.insecure && !urls.is_empty() {
+        warning!("warning: --insecure: TLS certificates are not verified, the downloads could have been tampered with");
+    }
+
+    for (i, url) in urls.iter().enumerate() {
+        let existing = Path::new(dir).join(file_name(url));
+        if existing.is_file() {
+            fetch_signature(url, &existing, dir, args);
+            files.push(existing.to_string_lossy().into_owned());
+            continue;
+        }
+
+        let part = Path::new(dir).join(format!("paccat-{}-{}.part", std::process::id(), i));
+        warning!("downloading {}...", url);
+        progress::event("download_started", &[("file", json::string(url))]);
+
+        let mut cmd = Command::new("curl");
+        cmd.arg("--fail")
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--write-out")
+            .arg("%{url_effective}\t%{redirect_url}")
+            .arg("--output")
+            .arg(&part)
+            .stderr(Stdio::inherit());
+
+        if !args.no_redirects {
+            cmd.arg("--location")
+                .arg("--max-redirs")
+                .arg(args.max_redirects.unwrap_or(10).to_string());
+        }
+
+        if let Some(ca_cert) = &args.ca_cert {
+            cmd.arg("--cacert").arg(ca_cert);
+        }
+        if args.insecure {
+            cmd.arg("--insecure");
+        }
+
+        if let Some(rate) = args.limit_rate {
+            cmd.arg("--limit-rate").arg(rate.to_string());
+        }
+
+        let output = cmd.arg(url).output().context("failed to run curl")?;
+
+        let result = if output.status.success() {
+            "success"
+        } else {
+            "failed"
+        };
+        progress::event(
+            "download_finished",
+            &[
+                ("file", json::string(url)),
+                ("result", json::string(result)),
+            ],
+        );
+
+        if !output.status.success() {
+            let _ = fs::remove_file(&part);
+            return Err(PaccatError::Download {
+                url: url.clone(),
+                reason: format!("curl exited with {}", output.status),
+            }
+            .into());
+        }
+
+        let written = String::from_utf8_lossy(&output.stdout);
+        let (effective, redirect) = written.split_once('\t').unwrap_or((&written, ""));
+        // without --location curl saves the body of the redirect instead
+        if !redirect.is_empty() {
+            let _ = fs::remove_file(&part);
+            return Err(PaccatError::Download {
+                url: url.clone(),
+                reason: format!("redirected to {} with --no-redirects", redirect),
+            }
+            .into());
+        }
+        if args.verbose && effective != url {
+            eprintln!("{} redirected to {}", url, effective);
+        }
+        // named after the url asked for so a redirect can't pick which file
+        // in the cache is written and the next run finds it as existing
+        fs::rename(&part, &existing)
+            .with_context(|| format!("failed to move download to {}", existing.display()))?;
+        fetch_signature(url, &existing, dir, args);
+        files.push(existing.to_string_lossy().into_owned());
+    }
+
+    Ok(files)
+}
+
+/// Downloads the .sig of a package for --require-signature if it isn't
+/// already next to it. A missing signature is reported when it is checked.
+fn fetch_signature(url: &str, pkg: &Path, dir: &str, args: &Args) {
+    let sig = format!("{}.sig", pkg.display());
+    if !args.require_signature || url.ends_with(".sig") || Path::new(&sig).is_file() {
+        return;
+    }
+    if let Err(e) = curl(&[format!("{}.sig", url)], dir, args) {
+        if args.verbose {
+            warning!("warning: {}", e);
+        }
+    }
+}
+
+fn file_name(url: &str) -> &str {
+    url.split(['?', '#'])
+        .next()
+        .and_then(|u| u.rsplit('/').next())
+        .filter(|n| !n.is_empty())
+        .unwrap_or("download")
+}
+
+/// Downloads each package with fetch. Normally all packages are fetched at
+/// once from their first server. With --mirror-failover each package is
+/// fetched on its own, moving on to the next server when one fails.
+pub fn with_failover<F>(download: &[Vec<String>], args: &Args, mut fetch: F) -> Result<Vec<String>>
+where
+    F: FnMut(Vec<String>) -> Result<Vec<String>>,
+{
+    if !args.mirror_failover {
+        return fetch(download.iter().map(|urls| urls[0].clone()).collect());
+    }
+
+    let mut files = Vec::new();
+
+    for urls in download {
+        let mut err = None;
+
+        for (i, url) in urls.iter().enumerate() {
+            match fetch(vec![url.clone()]) {
+                Ok(fetched) => {
+                    if args.verbose {
+                        eprintln!("downloaded {}", url);
+                    }
+                    files.extend(fetched);
+                    err = None;
+                    break;
+                }
+                Err(e) => {
+                    interrupt::check()?;
+                    if i + 1 < urls.len() {
+                        warning!("warning: {} -- trying the next server", e);
+                    }
+                    err = Some(e);
+                }
+            }
+        }
+
+        if let Some(err) = err {
+            return Err(err);
+        }
+    }
+
+    Ok(files)
+}
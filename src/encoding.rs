@@ -0,0 +1,85 @@
+use crate::args::Encoding;
+
+/// What windows-1252 maps 0x80 to 0x9f to. The bytes it leaves undefined
+/// are kept as the control characters of the same value.
+const WINDOWS_1252: [u16; 32] = [
+    0x20ac, 0x0081, 0x201a, 0x0192, 0x201e, 0x2026, 0x2020, 0x2021, 0x02c6, 0x2030, 0x0160, 0x2039,
+    0x0152, 0x008d, 0x017d, 0x008f, 0x0090, 0x2018, 0x2019, 0x201c, 0x201d, 0x2022, 0x2013, 0x2014,
+    0x02dc, 0x2122, 0x0161, 0x203a, 0x0153, 0x009d, 0x017e, 0x0178,
+];
+
+/// Converts data from encoding to UTF-8 for --encoding. Returns None when
+/// data should be printed as it is, which is when it is already UTF-8 or
+/// `auto` thinks it is binary.
+pub fn to_utf8(data: &[u8], encoding: Encoding) -> Option<String> {
+    let encoding = match encoding {
+        Encoding::Auto => detect(data)?,
+        encoding => encoding,
+    };
+
+    let text = match encoding {
+        Encoding::Auto | Encoding::Utf8 => {
+            let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+            String::from_utf8_lossy(data).into_owned()
+        }
+        Encoding::Utf16le => utf16(data.strip_prefix(b"\xff\xfe").unwrap_or(data), false),
+        Encoding::Utf16be => utf16(data.strip_prefix(b"\xfe\xff").unwrap_or(data), true),
+        Encoding::Latin1 => data.iter().map(|&b| b as char).collect(),
+        Encoding::Windows1252 => data.iter().map(|&b| windows_1252(b)).collect(),
+    };
+
+    Some(text)
+}
+
+fn detect(data: &[u8]) -> Option<Encoding> {
+    if data.starts_with(b"\xef\xbb\xbf") {
+        return Some(Encoding::Utf8);
+    }
+    if data.starts_with(b"\xff\xfe") {
+        return Some(Encoding::Utf16le);
+    }
+    if data.starts_with(b"\xfe\xff") {
+        return Some(Encoding::Utf16be);
+    }
+
+    // ascii text in UTF-16 has every other byte zero while binary files
+    // tend to have zeros everywhere
+    let sample = &data[..data.len().min(512) & !1];
+    let pairs = sample.len() / 2;
+    let even = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|&&b| b == 0)
+        .count();
+
+    if pairs != 0 && odd > pairs * 2 / 5 && even == 0 {
+        Some(Encoding::Utf16le)
+    } else if pairs != 0 && even > pairs * 2 / 5 && odd == 0 {
+        Some(Encoding::Utf16be)
+    } else if even + odd != 0 || std::str::from_utf8(data).is_ok() {
+        None
+    } else {
+        Some(Encoding::Windows1252)
+    }
+}
+
+fn utf16(data: &[u8], big_endian: bool) -> String {
+    let units = data.chunks(2).map(|pair| match (pair, big_endian) {
+        ([a, b], false) => u16::from_le_bytes([*a, *b]),
+        ([a, b], true) => u16::from_be_bytes([*a, *b]),
+        _ => 0xfffd,
+    });
+
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+fn windows_1252(b: u8) -> char {
+    match b {
+        0x80..=0x9f => char::from_u32(WINDOWS_1252[(b - 0x80) as usize] as u32).unwrap(),
+        b => b as char,
+    }
+}
@@ -0,0 +1,73 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// The errors paccat reports, each carrying the target or path it is about.
+/// They are passed around as anyhow::Error and can be downcast to match on.
+#[derive(Debug)]
+pub enum PaccatError {
+    /// A target could not be turned into a package.
+    Resolve { target: String, reason: String },
+    /// A package could not be downloaded.
+    Download { url: String, reason: String },
+    /// A package could not be read as an archive.
+    Archive {
+        path: String,
+        source: compress_tools::Error,
+    },
+    /// A file could not be opened, read or written.
+    Io {
+        path: String,
+        action: &'static str,
+        source: io::Error,
+    },
+}
+
+impl PaccatError {
+    pub fn resolve<T: Into<String>, R: Into<String>>(target: T, reason: R) -> Self {
+        PaccatError::Resolve {
+            target: target.into(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn io<P: Into<String>>(action: &'static str, path: P, source: io::Error) -> Self {
+        PaccatError::Io {
+            path: path.into(),
+            action,
+            source,
+        }
+    }
+}
+
+impl fmt::Display for PaccatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaccatError::Resolve { target, reason } => write!(f, "'{}' {}", target, reason),
+            PaccatError::Download { url, reason } => {
+                write!(f, "failed to download {}: {}", url, reason)
+            }
+            PaccatError::Archive { path, source } => write!(
+                f,
+                "failed to read {}: {}\n\
+                 the package may be truncated or corrupt, remove it from the cache to download it again",
+                path, source
+            ),
+            PaccatError::Io {
+                path,
+                action,
+                source,
+            } => write!(f, "failed to {} {}: {}", action, path, source),
+        }
+    }
+}
+
+impl Error for PaccatError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PaccatError::Archive { source, .. } => Some(source),
+            PaccatError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
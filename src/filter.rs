@@ -0,0 +1,33 @@
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Runs data through `sh -c cmd` for --filter-cmd and returns what the
+/// command printed. The name of the file is passed in $PACCAT_FILE.
+pub fn filter(cmd: &str, name: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .env("PACCAT_FILE", name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("failed to run '{}'", cmd))?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    // the command may exit without reading everything, which is not an error
+    let writer = thread::spawn(move || stdin.write_all(&data));
+
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("failed to run '{}'", cmd))?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        bail!("'{}' failed on {} with {}", cmd, name, output.status);
+    }
+
+    Ok(output.stdout)
+}
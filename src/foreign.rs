@@ -0,0 +1,29 @@
+use anyhow::{Context, Result};
+use compress_tools::{list_archive_files, uncompress_archive_file};
+use std::fs::File;
+use std::io::{Read, Seek};
+
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+
+/// Returns the data payload of a .deb package, or None if the file is not an ar archive.
+/// .rpm packages need no unwrapping as libarchive reads their cpio payload directly.
+pub fn deb_data(path: &str) -> Result<Option<Vec<u8>>> {
+    let mut file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+    let mut magic = [0; AR_MAGIC.len()];
+
+    if file.read_exact(&mut magic).is_err() || magic != AR_MAGIC {
+        return Ok(None);
+    }
+
+    file.rewind()?;
+    let names = list_archive_files(&file)?;
+    let member = names
+        .iter()
+        .find(|n| n.starts_with("data.tar"))
+        .with_context(|| format!("{} has no data.tar member", path))?;
+
+    file.rewind()?;
+    let mut data = Vec::new();
+    uncompress_archive_file(&file, &mut data, member)?;
+    Ok(Some(data))
+}
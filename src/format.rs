@@ -0,0 +1,162 @@
+use crate::args::ArchiveFormat;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+/// What libarchive reads a package from once --archive-format has been
+/// applied.
+pub enum Stream {
+    Data(Cursor<Vec<u8>>),
+    File(File),
+    Decompressor(Decompressor),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Data(data) => data.read(buf),
+            Stream::File(file) => file.read(buf),
+            Stream::Decompressor(child) => child.read(buf),
+        }
+    }
+}
+
+impl Seek for Stream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Stream::Data(data) => data.seek(pos),
+            Stream::File(file) => file.seek(pos),
+            Stream::Decompressor(child) => child.seek(pos),
+        }
+    }
+}
+
+/// The output of a decompressor, read as it is written. It can only be
+/// skipped forward, which is all libarchive needs for a tar.
+pub struct Decompressor {
+    child: Child,
+    stdout: ChildStdout,
+    writer: Option<JoinHandle<io::Result<()>>>,
+    path: String,
+    program: &'static str,
+    pos: u64,
+    done: bool,
+}
+
+impl Read for Decompressor {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() && !self.done {
+            self.done = true;
+            if let Some(writer) = self.writer.take() {
+                // a write error is reported by the decompressor failing
+                let _ = writer.join();
+            }
+            if !self.child.wait()?.success() {
+                return Err(io::Error::other(format!(
+                    "failed to decompress {} as {}",
+                    self.path, self.program
+                )));
+            }
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Decompressor {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Current(n) if n >= 0 => {
+                io::copy(&mut self.by_ref().take(n as u64), &mut io::sink())?;
+                Ok(self.pos)
+            }
+            _ => Err(io::Error::other(format!(
+                "can't seek in the output of {}",
+                self.program
+            ))),
+        }
+    }
+}
+
+impl Drop for Decompressor {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Decompresses the package with the compressor chosen by --archive-format
+/// instead of leaving it to libarchive's detection. data is the package
+/// contents if they were already read, such as the payload of a .deb.
+pub fn decompress(
+    path: &str,
+    data: Option<Vec<u8>>,
+    format: ArchiveFormat,
+) -> Result<Option<Stream>> {
+    let program = match format {
+        ArchiveFormat::Auto => return Ok(data.map(|data| Stream::Data(Cursor::new(data)))),
+        ArchiveFormat::Tar => {
+            return match data {
+                Some(data) => Ok(Some(Stream::Data(Cursor::new(data)))),
+                None => File::open(path)
+                    .map(|file| Some(Stream::File(file)))
+                    .with_context(|| format!("failed to read {}", path)),
+            }
+        }
+        ArchiveFormat::Gzip => "gzip",
+        ArchiveFormat::Bzip2 => "bzip2",
+        ArchiveFormat::Xz => "xz",
+        ArchiveFormat::Zstd => "zstd",
+    };
+
+    let mut cmd = Command::new(program);
+    cmd.arg("-dc")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+
+    match &data {
+        Some(_) => cmd.stdin(Stdio::piped()),
+        None => cmd.arg("--").arg(path).stdin(Stdio::null()),
+    };
+
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("failed to run {}", program))?;
+
+    let writer = match (data, child.stdin.take()) {
+        (Some(data), Some(mut stdin)) => Some(thread::spawn(move || stdin.write_all(&data))),
+        _ => None,
+    };
+    let stdout = child.stdout.take().unwrap();
+
+    Ok(Some(Stream::Decompressor(Decompressor {
+        child,
+        stdout,
+        writer,
+        path: path.to_string(),
+        program,
+        pos: 0,
+        done: false,
+    })))
+}
+
+/// Detects how data is compressed from its magic bytes. None means it is
+/// not compressed.
+pub fn compression(data: &[u8]) -> Option<&'static str> {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x1f\x8b", "gzip"),
+        (b"\x28\xb5\x2f\xfd", "zstd"),
+        (b"\xfd7zXZ\x00", "xz"),
+        (b"BZh", "bzip2"),
+    ];
+
+    MAGIC
+        .iter()
+        .find(|(magic, _)| data.starts_with(magic))
+        .map(|&(_, format)| format)
+}
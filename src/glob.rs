@@ -0,0 +1,183 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+
+pub fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+/// Converts a shell style glob into an anchored regex. `*` and `?` match any
+/// characters and `[...]` matches a character class.
+pub fn to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                let mut class = String::new();
+                let mut closed = false;
+                if let Some(&c) = chars.peek() {
+                    if c == '!' || c == '^' {
+                        class.push('^');
+                        chars.next();
+                    }
+                }
+                for c in chars.by_ref() {
+                    if c == ']' && !class.is_empty() && class != "^" {
+                        closed = true;
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        class.push('\\');
+                    }
+                    class.push(c);
+                }
+                if closed {
+                    regex.push('[');
+                    regex.push_str(&class);
+                    regex.push(']');
+                } else {
+                    regex.push_str(&regex::escape(&format!("[{}", class)));
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    regex.push('$');
+    regex
+}
+
+/// Compiles a glob from to_regex. A class like `[z-a]` can still make a
+/// regex that doesn't compile.
+pub fn compile(glob: &str) -> Result<Regex> {
+    Regex::new(&to_regex(glob)).with_context(|| format!("invalid glob '{}'", glob))
+}
+
+/// Expands shell style braces, `etc/{foo,bar}.conf` becomes `etc/foo.conf`
+/// and `etc/bar.conf`. Braces may be nested and `\{`, `\}`, `\,` and `\\`
+/// are taken literally. Braces without a comma are left as they are.
+pub fn expand_braces(s: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    expand(s.to_string(), &mut out);
+    out.iter().map(|s| unescape_braces(s)).collect()
+}
+
+fn expand(s: String, out: &mut Vec<String>) {
+    let (open, close, commas) = match find_braces(&s) {
+        Some(group) => group,
+        None => return out.push(s),
+    };
+
+    let mut start = open + 1;
+    for end in commas.into_iter().chain(Some(close)) {
+        expand(
+            format!("{}{}{}", &s[..open], &s[start..end], &s[close + 1..]),
+            out,
+        );
+        start = end + 1;
+    }
+}
+
+/// Finds the first brace group with a comma at its own level, returning the
+/// positions of its braces and commas.
+fn find_braces(s: &str) -> Option<(usize, usize, Vec<usize>)> {
+    let bytes = s.as_bytes();
+    let mut open = 0;
+
+    while let Some(i) = next_unescaped(bytes, open, b'{') {
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut j = i;
+
+        while j < bytes.len() {
+            match bytes[j] {
+                b'\\' => j += 1,
+                b'{' => depth += 1,
+                b'}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        if !commas.is_empty() {
+                            return Some((i, j, commas));
+                        }
+                        break;
+                    }
+                }
+                b',' if depth == 1 => commas.push(j),
+                _ => (),
+            }
+            j += 1;
+        }
+
+        open = i + 1;
+    }
+
+    None
+}
+
+fn next_unescaped(bytes: &[u8], mut i: usize, want: u8) -> Option<usize> {
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b if b == want => return Some(i),
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+fn unescape_braces(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ ('{' | '}' | ',' | '\\')) => out.push(c),
+                Some(c) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_match() {
+        let glob = compile("linux-*-[0-9]?").unwrap();
+        assert!(glob.is_match("linux-lts-5a"));
+        assert!(!glob.is_match("linux-lts-a5"));
+        assert!(compile("[!a]b").unwrap().is_match("cb"));
+        assert!(compile("a.b").unwrap().is_match("a.b"));
+        assert!(!compile("a.b").unwrap().is_match("axb"));
+        // an unclosed class is taken literally
+        assert!(compile("[ab").unwrap().is_match("[ab"));
+    }
+
+    #[test]
+    fn invalid_globs_are_errors() {
+        let err = compile("linux-[z-a]*").unwrap_err();
+        assert_eq!(err.to_string(), "invalid glob 'linux-[z-a]*'");
+    }
+
+    #[test]
+    fn braces() {
+        assert_eq!(
+            expand_braces("etc/{a,b{1,2}}.conf"),
+            ["etc/a.conf", "etc/b1.conf", "etc/b2.conf"]
+        );
+        assert_eq!(expand_braces(r"{x}\{a,b\}"), [r"{x}{a,b}"]);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::mtree::{Entry, Kind};
+use crate::Match;
+use compress_tools::ArchiveContents;
+use std::collections::HashMap;
+use std::mem;
+
+/// Gives hardlinks in an archive the content of the file they link to.
+/// Only the first path of a hardlinked file carries its data in a tar, so
+/// the other paths are found from the files with the same sha256 in the
+/// .MTREE.
+pub struct Hardlinks<I> {
+    inner: I,
+    /// The sha256 of each file that shares its content with a matched file.
+    groups: HashMap<String, String>,
+    /// The content of each sha256 in groups once it was read.
+    data: HashMap<String, Vec<u8>>,
+    current: Option<String>,
+    capture: Vec<u8>,
+    got_data: bool,
+    pending: Option<ArchiveContents>,
+}
+
+impl<I: Iterator<Item = ArchiveContents>> Hardlinks<I> {
+    pub fn new(inner: I, entries: &[Entry], matcher: &mut Match) -> Self {
+        let mut by_sum = HashMap::<&str, Vec<&str>>::new();
+        for entry in entries {
+            if let (Kind::File, Some(sum)) = (entry.kind, &entry.sha256) {
+                if entry.size != 0 {
+                    by_sum.entry(sum).or_default().push(&entry.path);
+                }
+            }
+        }
+
+        let mut groups = HashMap::new();
+        for (sum, paths) in by_sum {
+            if paths.len() > 1 && paths.iter().any(|p| matcher.is_match(p, false)) {
+                for path in paths {
+                    groups.insert(path.to_string(), sum.to_string());
+                }
+            }
+        }
+
+        Hardlinks {
+            inner,
+            groups,
+            data: HashMap::new(),
+            current: None,
+            capture: Vec::new(),
+            got_data: false,
+            pending: None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = ArchiveContents>> Iterator for Hardlinks<I> {
+    type Item = ArchiveContents;
+
+    fn next(&mut self) -> Option<ArchiveContents> {
+        if let Some(pending) = self.pending.take() {
+            return Some(pending);
+        }
+
+        let content = self.inner.next()?;
+        match &content {
+            ArchiveContents::StartOfEntry(name) => {
+                self.current = self.groups.get(name).cloned();
+                self.got_data = false;
+            }
+            ArchiveContents::DataChunk(v) => {
+                self.got_data = true;
+                if self.current.is_some() {
+                    self.capture.extend_from_slice(v);
+                }
+            }
+            ArchiveContents::EndOfEntry => {
+                if let Some(sum) = self.current.take() {
+                    let capture = mem::take(&mut self.capture);
+                    if self.got_data {
+                        self.data.entry(sum).or_insert(capture);
+                    } else if let Some(data) = self.data.get(&sum) {
+                        self.pending = Some(content);
+                        return Some(ArchiveContents::DataChunk(data.clone()));
+                    }
+                }
+            }
+            ArchiveContents::Err(_) => (),
+        }
+
+        Some(content)
+    }
+}
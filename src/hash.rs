@@ -0,0 +1,401 @@
+use crate::args::Checksum;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+
+/// An incremental hash of a file for --checksum.
+pub enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+    /// Hashed on one of the threads of --parallel-hash.
+    Pooled(Pooled),
+}
+
+impl Hasher {
+    pub fn new(algo: Checksum) -> Self {
+        match algo {
+            Checksum::Sha256 => Hasher::Sha256(Sha256::new()),
+            Checksum::Sha512 => Hasher::Sha512(Sha512::new()),
+            Checksum::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    /// A hasher whose data is sent to one of the threads of --parallel-hash
+    /// as it comes, which are started the first time this is called.
+    pub fn pooled(algo: Checksum) -> Self {
+        let mut workers = WORKERS.lock().unwrap();
+        if workers.is_empty() {
+            let threads = thread::available_parallelism().map_or(4, |n| n.get());
+            for _ in 0..threads {
+                // bounded so only a few chunks per thread are held in memory
+                let (worker, queue) = mpsc::sync_channel(16);
+                thread::spawn(move || work(queue));
+                workers.push(worker);
+            }
+        }
+
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        let worker = workers[id % workers.len()].clone();
+        let _ = worker.send(Job::Start(id, algo));
+        Hasher::Pooled(Pooled {
+            id,
+            worker,
+            done: false,
+        })
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Sha512(h) => h.update(data),
+            Hasher::Blake3(h) => {
+                h.update(data);
+            }
+            Hasher::Pooled(h) => {
+                let _ = h.worker.send(Job::Chunk(h.id, data.to_vec()));
+            }
+        }
+    }
+
+    /// The digest as lower case hex.
+    pub fn finish(self) -> String {
+        let digest = match self {
+            Hasher::Sha256(h) => h.finalize().to_vec(),
+            Hasher::Sha512(h) => h.finalize().to_vec(),
+            Hasher::Blake3(h) => h.finalize().as_bytes().to_vec(),
+            Hasher::Pooled(_) => {
+                let (done, digest) = mpsc::channel();
+                self.finish_with(move |hex| {
+                    let _ = done.send(hex);
+                });
+                return digest.recv().expect("a --parallel-hash thread stopped");
+            }
+        };
+
+        let mut hex = String::with_capacity(digest.len() * 2);
+        for b in digest {
+            write!(hex, "{:02x}", b).unwrap();
+        }
+        hex
+    }
+
+    /// Gives the digest to f once it is done, on the thread that hashed it
+    /// with --parallel-hash.
+    pub fn finish_with<F: FnOnce(String) + Send + 'static>(self, f: F) {
+        match self {
+            Hasher::Pooled(mut h) => {
+                h.done = true;
+                let _ = h.worker.send(Job::End(h.id, Box::new(f)));
+            }
+            h => f(h.finish()),
+        }
+    }
+}
+
+/// The sha256sum of the file at path as lower case hex.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut hasher = Hasher::new(Checksum::Sha256);
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buf)? {
+            0 => break,
+            n => hasher.update(&buf[..n]),
+        }
+    }
+    Ok(hasher.finish())
+}
+
+enum Job {
+    Start(usize, Checksum),
+    Chunk(usize, Vec<u8>),
+    End(usize, Box<dyn FnOnce(String) + Send>),
+    Cancel(usize),
+}
+
+/// The threads of --parallel-hash, shared by every package of the run. The
+/// data of a file is always sent to the same one.
+static WORKERS: Mutex<Vec<SyncSender<Job>>> = Mutex::new(Vec::new());
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+fn work(queue: Receiver<Job>) {
+    let mut hashers = HashMap::new();
+    for job in queue {
+        match job {
+            Job::Start(id, algo) => {
+                hashers.insert(id, Hasher::new(algo));
+            }
+            Job::Chunk(id, data) => {
+                if let Some(hasher) = hashers.get_mut(&id) {
+                    hasher.update(&data);
+                }
+            }
+            Job::End(id, f) => {
+                if let Some(hasher) = hashers.remove(&id) {
+                    f(hasher.finish());
+                }
+            }
+            Job::Cancel(id) => {
+                hashers.remove(&id);
+            }
+        }
+    }
+}
+
+pub struct Pooled {
+    id: usize,
+    worker: SyncSender<Job>,
+    done: bool,
+}
+
+impl Drop for Pooled {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.worker.send(Job::Cancel(self.id));
+        }
+    }
+}
+
+/// The digests of the files of a package with --parallel-hash, in the
+/// order the files were given with a tag.
+pub struct Digests<T> {
+    done: Sender<(usize, String)>,
+    digests: Receiver<(usize, String)>,
+    /// The tags given and their digest once it is done, from the first one
+    /// that wasn't returned yet.
+    pending: VecDeque<(T, Option<String>)>,
+    returned: usize,
+}
+
+impl<T> Digests<T> {
+    pub fn new() -> Self {
+        let (done, digests) = mpsc::channel();
+        Digests {
+            done,
+            digests,
+            pending: VecDeque::new(),
+            returned: 0,
+        }
+    }
+
+    pub fn submit(&mut self, tag: T, hasher: Hasher) {
+        let i = self.returned + self.pending.len();
+        self.pending.push_back((tag, None));
+        let done = self.done.clone();
+        hasher.finish_with(move |hex| {
+            let _ = done.send((i, hex));
+        });
+    }
+
+    /// The digests that are done so far without a gap before them, without
+    /// waiting for the rest.
+    pub fn ready(&mut self) -> Vec<(T, String)> {
+        while let Ok((i, hex)) = self.digests.try_recv() {
+            self.pending[i - self.returned].1 = Some(hex);
+        }
+        self.take_in_order()
+    }
+
+    /// Waits for every hasher given and returns the digests left.
+    pub fn finish(mut self) -> Vec<(T, String)> {
+        while self.pending.iter().any(|(_, hex)| hex.is_none()) {
+            let (i, hex) = self.digests.recv().unwrap();
+            self.pending[i - self.returned].1 = Some(hex);
+        }
+        self.take_in_order()
+    }
+
+    fn take_in_order(&mut self) -> Vec<(T, String)> {
+        let mut digests = Vec::new();
+        while matches!(self.pending.front(), Some((_, Some(_)))) {
+            let (tag, hex) = self.pending.pop_front().unwrap();
+            digests.push((tag, hex.unwrap()));
+            self.returned += 1;
+        }
+        digests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(algo: Checksum, chunks: &[&[u8]]) -> String {
+        let mut hasher = Hasher::new(algo);
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finish()
+    }
+
+    fn a(n: usize) -> Vec<u8> {
+        vec![b'a'; n]
+    }
+
+    // the examples of FIPS 180-2 and the padding around the block size
+    #[test]
+    fn sha256_vectors() {
+        let vectors: &[(&[u8], &str)] = &[
+            (
+                b"",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                b"abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+            (
+                &a(55),
+                "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318",
+            ),
+            (
+                &a(56),
+                "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a",
+            ),
+            (
+                &a(64),
+                "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb",
+            ),
+            (
+                &a(1_000_000),
+                "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0",
+            ),
+        ];
+        for (data, hex) in vectors {
+            assert_eq!(
+                digest(Checksum::Sha256, &[data]),
+                *hex,
+                "{} bytes",
+                data.len()
+            );
+        }
+    }
+
+    #[test]
+    fn sha512_vectors() {
+        let vectors: &[(&[u8], &str)] = &[
+            (
+                b"",
+                "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce\
+                 47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e",
+            ),
+            (
+                b"abc",
+                "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                 2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+            ),
+            (
+                b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmn\
+                  hijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu",
+                "8e959b75dae313da8cf4f72814fc143f8f7779c6eb9f7fa17299aeadb6889018\
+                 501d289e4900f7e4331b99dec4b5433ac7d329eeb6dd26545e96e55b874be909",
+            ),
+            (
+                &a(111),
+                "fa9121c7b32b9e01733d034cfc78cbf67f926c7ed83e82200ef86818196921760\
+                 b4beff48404df811b953828274461673c68d04e297b0eb7b2b4d60fc6b566a2",
+            ),
+            (
+                &a(112),
+                "c01d080efd492776a1c43bd23dd99d0a2e626d481e16782e75d54c2503b5dc32\
+                 bd05f0f1ba33e568b88fd2d970929b719ecbb152f58f130a407c8830604b70ca",
+            ),
+            (
+                &a(128),
+                "b73d1929aa615934e61a871596b3f3b33359f42b8175602e89f7e06e5f658a24\
+                 3667807ed300314b95cacdd579f3e33abdfbe351909519a846d465c59582f321",
+            ),
+            (
+                &a(1_000_000),
+                "e718483d0ce769644e2e42c7bc15b4638e1f98b13b2044285632a803afa973eb\
+                 de0ff244877ea60a4cb0432ce577c31beb009c5c2c49aa2e4eadb217ad8cc09b",
+            ),
+        ];
+        for (data, hex) in vectors {
+            assert_eq!(
+                digest(Checksum::Sha512, &[data]),
+                *hex,
+                "{} bytes",
+                data.len()
+            );
+        }
+    }
+
+    #[test]
+    fn blake3_vectors() {
+        let vectors: &[(&[u8], &str)] = &[
+            (
+                b"",
+                "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+            ),
+            (
+                b"abc",
+                "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+            ),
+        ];
+        for (data, hex) in vectors {
+            assert_eq!(digest(Checksum::Blake3, &[data]), *hex, "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn digests_come_back_in_order() {
+        let mut digests = Digests::new();
+        let mut expected = Vec::new();
+        for i in 0..20 {
+            // the first files are the slowest to hash
+            let data = a((20 - i) * 50_000);
+            expected.push((i, digest(Checksum::Sha256, &[&data])));
+            let mut hasher = Hasher::pooled(Checksum::Sha256);
+            for chunk in data.chunks(64 * 1024) {
+                hasher.update(chunk);
+            }
+            digests.submit(i, hasher);
+        }
+        let mut got = digests.ready();
+        got.extend(digests.finish());
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn chunks_give_the_same_digest() {
+        let data = (0..300).map(|i| i as u8).collect::<Vec<_>>();
+        for algo in [Checksum::Sha256, Checksum::Sha512, Checksum::Blake3] {
+            for len in 0..data.len() {
+                let data = &data[..len];
+                let whole = digest(algo, &[data]);
+                let bytes = data.chunks(1).collect::<Vec<_>>();
+                assert_eq!(digest(algo, &bytes), whole, "{} bytes one at a time", len);
+                for at in [1, 55, 63, 64, 65, 111, 127, 128, 129] {
+                    let (head, tail) = data.split_at(at.min(len));
+                    assert_eq!(
+                        digest(algo, &[head, tail]),
+                        whole,
+                        "{} split at {}",
+                        len,
+                        at
+                    );
+                }
+                let mut pooled = Hasher::pooled(algo);
+                for chunk in data.chunks(7) {
+                    pooled.update(chunk);
+                }
+                assert_eq!(pooled.finish(), whole);
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+const RESET: &str = "\x1b[0m";
+const COMMENT: &str = "\x1b[90m";
+const SECTION: &str = "\x1b[1;34m";
+const KEY: &str = "\x1b[36m";
+const STRING: &str = "\x1b[32m";
+const LITERAL: &str = "\x1b[33m";
+const KEYWORD: &str = "\x1b[35m";
+
+#[derive(Clone, Copy)]
+enum Lang {
+    Ini,
+    Json,
+    Sh,
+}
+
+const SH_KEYWORDS: &[&str] = &[
+    "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if", "in",
+    "local", "return", "then", "until", "while",
+];
+
+/// Highlights text with ANSI colours based on the extension of name. Returns
+/// None for file types that aren't known.
+pub fn highlight(name: &str, text: &str) -> Option<String> {
+    let lang = match name.rsplit('.').next()? {
+        "toml" | "ini" | "conf" => Lang::Ini,
+        "json" => Lang::Json,
+        "sh" | "bash" | "zsh" => Lang::Sh,
+        _ => return None,
+    };
+
+    let mut out = String::with_capacity(text.len() * 2);
+    for line in text.split_inclusive('\n') {
+        let (line, end) = match line.strip_suffix('\n') {
+            Some(line) => (line, "\n"),
+            None => (line, ""),
+        };
+        match lang {
+            Lang::Ini => ini_line(&mut out, line),
+            Lang::Json => json_line(&mut out, line),
+            Lang::Sh => sh_line(&mut out, line),
+        }
+        out.push_str(end);
+    }
+
+    Some(out)
+}
+
+fn paint(out: &mut String, colour: &str, s: &str) {
+    if s.is_empty() {
+        return;
+    }
+    out.push_str(colour);
+    out.push_str(s);
+    out.push_str(RESET);
+}
+
+/// Splits off a quoted string at the start of s, including its quotes.
+fn quoted(s: &str) -> Option<(&str, &str)> {
+    let quote = s.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let mut escape = false;
+
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            '\\' if quote == '"' && !escape => escape = true,
+            c if c == quote && !escape => return Some(s.split_at(i + 1)),
+            _ => escape = false,
+        }
+    }
+
+    Some((s, ""))
+}
+
+fn ini_line(out: &mut String, line: &str) {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+    out.push_str(indent);
+
+    if trimmed.starts_with('#') || trimmed.starts_with(';') {
+        paint(out, COMMENT, trimmed);
+    } else if trimmed.starts_with('[') {
+        paint(out, SECTION, trimmed);
+    } else if let Some(i) = trimmed.find('=') {
+        paint(out, KEY, &trimmed[..i]);
+        out.push('=');
+        value(out, &trimmed[i + 1..]);
+    } else {
+        out.push_str(trimmed);
+    }
+}
+
+fn value(out: &mut String, mut rest: &str) {
+    while !rest.is_empty() {
+        if let Some((s, tail)) = quoted(rest) {
+            paint(out, STRING, s);
+            rest = tail;
+        } else if rest.starts_with(" #") || rest.starts_with("\t#") {
+            paint(out, COMMENT, rest);
+            return;
+        } else {
+            let first = rest.chars().next().unwrap().len_utf8();
+            let end = rest[first..]
+                .find(['"', '\'', ' ', '\t'])
+                .map_or(rest.len(), |i| i + first);
+            let word = &rest[..end];
+            if is_literal(word.trim()) {
+                paint(out, LITERAL, word);
+            } else {
+                out.push_str(word);
+            }
+            rest = &rest[end..];
+        }
+    }
+}
+
+fn is_literal(word: &str) -> bool {
+    matches!(word, "true" | "false" | "null") || (!word.is_empty() && word.parse::<f64>().is_ok())
+}
+
+fn json_line(out: &mut String, mut rest: &str) {
+    while let Some(c) = rest.chars().next() {
+        if c == '"' {
+            let (s, tail) = quoted(rest).unwrap();
+            let colour = if tail.trim_start().starts_with(':') {
+                KEY
+            } else {
+                STRING
+            };
+            paint(out, colour, s);
+            rest = tail;
+        } else if c.is_ascii_alphanumeric() || c == '-' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || "-+.".contains(c)))
+                .unwrap_or(rest.len());
+            let (word, tail) = rest.split_at(end);
+            if is_literal(word) {
+                paint(out, LITERAL, word);
+            } else {
+                out.push_str(word);
+            }
+            rest = tail;
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+}
+
+fn sh_line(out: &mut String, mut rest: &str) {
+    let mut word_start = true;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '#' && word_start {
+            paint(out, COMMENT, rest);
+            return;
+        } else if let Some((s, tail)) = quoted(rest) {
+            paint(out, STRING, s);
+            rest = tail;
+            word_start = false;
+        } else if c == '$' {
+            let name = &rest[1..];
+            let end = match name
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '{' || c == '}'))
+            {
+                Some(0) => 1 + name.chars().next().unwrap().len_utf8(),
+                Some(i) => 1 + i,
+                None => rest.len(),
+            };
+            paint(out, KEY, &rest[..end]);
+            rest = &rest[end..];
+            word_start = false;
+        } else if c.is_ascii_alphabetic() && word_start {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                .unwrap_or(rest.len());
+            let (word, tail) = rest.split_at(end);
+            if SH_KEYWORDS.contains(&word) {
+                paint(out, KEYWORD, word);
+            } else {
+                out.push_str(word);
+            }
+            rest = tail;
+            word_start = false;
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+            word_start = c.is_whitespace() || ";|&(){}".contains(c);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::Result;
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+extern "C" fn handle_sigint(_: i32) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Installs the SIGINT handler. Without SA_RESTART a blocking read or wait
+/// is interrupted so it can be given up, and SA_RESETHAND puts back the
+/// default so a second SIGINT kills paccat if it is stuck anyway.
+pub fn install() {
+    let action = SigAction::new(
+        SigHandler::Handler(handle_sigint),
+        SaFlags::SA_RESETHAND,
+        SigSet::empty(),
+    );
+    unsafe { sigaction(Signal::SIGINT, &action).unwrap() };
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+pub fn check() -> Result<()> {
+    if interrupted() {
+        Err(Interrupted.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Files present in the cache dirs.
+pub fn cache_files<'a, I: IntoIterator<Item = &'a str>>(dirs: I) -> Vec<PathBuf> {
+    dirs.into_iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .collect()
+}
+
+/// Partial downloads present in the cache dirs. Used to remove only the
+/// partial files we created when a download is interrupted.
+pub fn partial_files<'a, I: IntoIterator<Item = &'a str>>(dirs: I) -> Vec<PathBuf> {
+    cache_files(dirs)
+        .into_iter()
+        .filter(|p| p.extension() == Some(OsStr::new("part")))
+        .collect()
+}
+
+pub fn remove_new_partial_files<'a, I: IntoIterator<Item = &'a str>>(dirs: I, before: &[PathBuf]) {
+    for file in partial_files(dirs) {
+        if !before.contains(&file) {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
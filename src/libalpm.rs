@@ -0,0 +1,393 @@
+use crate::args::Args;
+use crate::download;
+use crate::error::PaccatError;
+use crate::json;
+use crate::pacman::{cachedir, PkgDb};
+use crate::progress;
+use crate::signature;
+use alpm::{
+    Alpm, AnyDownloadEvent, AnyEvent, DownloadEvent, DownloadResult, Event, LogLevel, Package,
+    SigLevel,
+};
+use alpm_utils::DbListExt;
+use alpm_utils::Targ;
+use anyhow::{bail, Context, Result};
+use nix::libc;
+use std::cmp::Ordering;
+use std::ffi::{CString, OsString};
+use std::fs::{self, File};
+use std::io;
+use std::iter;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+impl<'a> PkgDb<'a> for Alpm {
+    type Pkg = Package<'a>;
+
+    fn find_pkg(&'a self, target: &str) -> Result<Package<'a>> {
+        get_dbpkg(self, target)
+    }
+
+    fn sync_pkgs(&'a self) -> Vec<Package<'a>> {
+        self.syncdbs().iter().flat_map(|db| db.pkgs()).collect()
+    }
+
+    fn local_pkgs(&'a self) -> Vec<Package<'a>> {
+        self.localdb().pkgs().iter().collect()
+    }
+
+    fn sync_pkg(&'a self, pkg: Package<'a>) -> Option<Package<'a>> {
+        self.syncdbs().pkg(pkg.name()).ok()
+    }
+
+    fn local_pkg(&'a self, pkg: Package<'a>) -> Option<Package<'a>> {
+        self.localdb().pkg(pkg.name()).ok()
+    }
+
+    fn has_files(&'a self, pkg: Package<'a>) -> bool {
+        !pkg.files().files().is_empty()
+    }
+
+    fn any_file(&'a self, pkg: Package<'a>, f: &mut dyn FnMut(&str) -> bool) -> bool {
+        pkg.files().files().iter().any(|file| f(file.name()))
+    }
+
+    fn download_urls(&'a self, pkg: Package<'a>) -> Result<Vec<String>> {
+        get_download_urls(pkg)
+    }
+
+    fn sha256sum(&'a self, pkg: Package<'a>) -> Option<String> {
+        pkg.sha256sum().map(String::from)
+    }
+
+    fn name_version(&'a self, pkg: Package<'a>) -> (String, String) {
+        (pkg.name().to_string(), pkg.version().to_string())
+    }
+
+    fn depends(&'a self, pkg: Package<'a>) -> Vec<String> {
+        pkg.depends().iter().map(|dep| dep.to_string()).collect()
+    }
+
+    fn optdepends(&'a self, pkg: Package<'a>) -> Vec<String> {
+        pkg.optdepends()
+            .iter()
+            .map(|dep| dep.name().to_string())
+            .collect()
+    }
+
+    fn provides(&'a self, pkg: Package<'a>) -> Vec<String> {
+        pkg.provides()
+            .iter()
+            .map(|dep| dep.name().to_string())
+            .collect()
+    }
+
+    fn pkgbase(&'a self, pkg: Package<'a>) -> Option<String> {
+        pkg.base().map(str::to_string)
+    }
+
+    fn backup(&'a self, pkg: Package<'a>) -> Vec<(String, String)> {
+        pkg.backup()
+            .iter()
+            .map(|b| (b.name().to_string(), b.hash().to_string()))
+            .collect()
+    }
+
+    fn is_cached(&'a self, pkg: Package<'a>) -> bool {
+        self.cachedirs()
+            .iter()
+            .any(|dir| Path::new(dir).join(pkg.filename()).exists())
+    }
+
+    fn db_name(&'a self, pkg: Package<'a>) -> Option<&'a str> {
+        pkg.db().map(|db| db.name())
+    }
+
+    fn repos(&'a self) -> Vec<&'a str> {
+        self.syncdbs().iter().map(|db| db.name()).collect()
+    }
+
+    fn servers(&'a self, repo: &str) -> Vec<String> {
+        self.syncdbs()
+            .iter()
+            .filter(|db| db.name() == repo)
+            .flat_map(|db| db.servers())
+            .map(String::from)
+            .collect()
+    }
+
+    fn vercmp(&'a self, a: &str, b: &str) -> Ordering {
+        alpm::vercmp(a, b)
+    }
+
+    fn noextracts(&'a self) -> Vec<String> {
+        self.noextracts().iter().map(String::from).collect()
+    }
+
+    fn cachedirs(&'a self) -> Vec<String> {
+        self.cachedirs().iter().map(String::from).collect()
+    }
+
+    fn dbpath(&'a self) -> Option<String> {
+        Some(self.dbpath().to_string())
+    }
+
+    fn fetch(&'a self, urls: Vec<String>) -> Result<Vec<String>> {
+        let joined = urls.join(" ");
+        match self.fetch_pkgurl(urls.into_iter()) {
+            Ok(files) => Ok(files.into_iter().collect()),
+            Err(e) => Err(PaccatError::Download {
+                url: joined,
+                reason: e.to_string(),
+            }
+            .into()),
+        }
+    }
+}
+
+pub fn alpm_init(args: &Args) -> Result<Alpm> {
+    let config = args.config.as_deref().unwrap_or("/etc/pacman.conf");
+    check_readable(Path::new(config), "config", "--config", || {
+        File::open(config).map(drop)
+    })?;
+    let conf = pacmanconf::Config::with_opts(None, args.config.as_deref(), args.root.as_deref())
+        .with_context(|| format!("failed to read config {}", config))?;
+
+    let dbpath = args.dbpath.as_deref().unwrap_or(conf.db_path.as_str());
+    check_readable(Path::new(dbpath), "database path", "--dbpath", || {
+        fs::read_dir(dbpath).map(drop)
+    })?;
+    let repo_db = match args.repo_db.as_deref() {
+        Some(url) => Some(repo_db(args, dbpath, url)?),
+        None => None,
+    };
+    let dbpath = match &repo_db {
+        Some((path, _, _)) => path
+            .to_str()
+            .context("the temporary database path is not UTF-8")?,
+        None => dbpath,
+    };
+    let mut alpm = Alpm::new(conf.root_dir.as_str(), dbpath)
+        .with_context(|| format!("failed to open database path {}", dbpath))?;
+
+    if args.filedb || args.db_check || args.require_files_db {
+        alpm.set_dbext(".files");
+    }
+
+    alpm.set_dl_cb((), download_cb);
+    alpm.set_log_cb((), log_cb);
+    alpm.set_event_cb((), event_cb);
+
+    alpm_utils::configure_alpm(&mut alpm, &conf)?;
+    if args.no_sync_db {
+        alpm.unregister_all_syncdbs()?;
+    }
+
+    if let Some((_, name, server)) = &repo_db {
+        if alpm.syncdbs().iter().any(|db| db.name() == name) {
+            bail!("--repo-db: a repo named {} is already configured", name);
+        }
+        // the signature was checked when it was downloaded with --require-signature
+        let db = alpm.register_syncdb_mut(name.as_str(), SigLevel::NONE)?;
+        db.add_server(server.as_str())?;
+    }
+
+    if let Some(url) = args.repo_url.as_deref() {
+        set_repo_url(&mut alpm, &conf, url)?;
+    }
+
+    if let Some(dir) = args.cachedir.as_deref() {
+        alpm.set_cachedirs(iter::once(dir))?;
+    } else {
+        alpm.add_cachedir(cachedir(args).as_os_str().as_bytes())?;
+    }
+    Ok(alpm)
+}
+
+fn check_readable<F>(path: &Path, what: &str, flag: &str, read: F) -> Result<()>
+where
+    F: FnOnce() -> io::Result<()>,
+{
+    match read() {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => bail!(
+            "{} {} does not exist (use {} to choose another)",
+            what,
+            path.display(),
+            flag
+        ),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => bail!(
+            "permission denied reading {} {} (use {} to choose another)",
+            what,
+            path.display(),
+            flag
+        ),
+        Err(e) => Err(e).with_context(|| format!("failed to read {} {}", what, path.display())),
+    }
+}
+
+/// Creates a new directory only we can use from template, whose name ends
+/// in XXXXXX, which nix doesn't have a wrapper for.
+fn mkdtemp(template: &Path) -> io::Result<PathBuf> {
+    let mut path = CString::new(template.as_os_str().as_bytes())?.into_bytes_with_nul();
+    if unsafe { libc::mkdtemp(path.as_mut_ptr().cast()) }.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    path.pop();
+    Ok(PathBuf::from(OsString::from_vec(path)))
+}
+
+/// Downloads the database at url for --repo-db into a temporary database
+/// path next to links to the local and sync databases of dbpath, so it can
+/// be used without writing to the real one. Returns the temporary path,
+/// the name of the repo and the server its packages are downloaded from.
+fn repo_db(args: &Args, dbpath: &str, url: &str) -> Result<(PathBuf, String, String)> {
+    let (server, file) = match url.rsplit_once('/') {
+        Some((server, file)) if url.contains("://") => (server, file),
+        _ => bail!(
+            "invalid --repo-db url '{}', expected a url to <repo>.db",
+            url
+        ),
+    };
+    let name = match file.find(".db").or_else(|| file.find(".files")) {
+        Some(end) if end != 0 => &file[..end],
+        _ => bail!(
+            "invalid --repo-db url '{}', expected a url to <repo>.db",
+            url
+        ),
+    };
+    let ext = if args.filedb || args.db_check || args.require_files_db {
+        "files"
+    } else {
+        "db"
+    };
+
+    let template = std::env::temp_dir().join("paccat-repo-db-XXXXXX");
+    let path =
+        mkdtemp(&template).with_context(|| format!("failed to create {}", template.display()))?;
+    download::remove_on_exit(path.clone());
+    let sync = path.join("sync");
+    fs::create_dir(&sync).with_context(|| format!("failed to create {}", sync.display()))?;
+
+    let real = Path::new(dbpath);
+    symlink(real.join("local"), path.join("local"))
+        .with_context(|| format!("failed to link {}", path.join("local").display()))?;
+    if let Ok(entries) = fs::read_dir(real.join("sync")) {
+        for entry in entries.flatten() {
+            let _ = symlink(entry.path(), sync.join(entry.file_name()));
+        }
+    }
+
+    let dir = path.to_string_lossy();
+    let url = format!("{}/{}.{}", server, name, ext);
+    let downloaded = download::curl(&[url], &dir, args)?;
+    if args.require_signature {
+        signature::check(&downloaded, args)?;
+    }
+    let dest = sync.join(format!("{}.{}", name, ext));
+    let _ = fs::remove_file(&dest);
+    fs::rename(&downloaded[0], &dest)
+        .with_context(|| format!("failed to move the database to {}", dest.display()))?;
+
+    Ok((path, name.to_string(), server.to_string()))
+}
+
+fn set_repo_url(alpm: &mut Alpm, conf: &pacmanconf::Config, url: &str) -> Result<()> {
+    if !url.contains("://") {
+        bail!("invalid repo url '{}': missing scheme", url);
+    }
+
+    let arch = conf
+        .architecture
+        .first()
+        .map(|a| a.as_str())
+        .unwrap_or("x86_64");
+
+    for db in alpm.syncdbs_mut() {
+        let server = url.replace("$repo", db.name()).replace("$arch", arch);
+        db.set_servers(iter::once(server))
+            .with_context(|| format!("failed to set server for {}", db.name()))?;
+    }
+
+    Ok(())
+}
+
+pub fn get_dbpkg<'a>(alpm: &'a Alpm, target_str: &str) -> Result<Package<'a>> {
+    let target = Targ::from(target_str);
+    let pkg = alpm
+        .syncdbs()
+        .find_target_satisfier(target)
+        .ok_or_else(|| PaccatError::resolve(target_str, "is not in any database"))?;
+    Ok(pkg)
+}
+
+/// The urls of pkg on each server of its repo, in the configured order.
+pub fn get_download_urls(pkg: Package) -> Result<Vec<String>> {
+    let urls = pkg
+        .db()
+        .unwrap()
+        .servers()
+        .iter()
+        .map(|server| format!("{}/{}", server.trim_end_matches('/'), pkg.filename()))
+        .collect::<Vec<_>>();
+
+    if urls.is_empty() {
+        return Err(alpm::Error::ServerNone.into());
+    }
+    Ok(urls)
+}
+
+fn download_cb(file: &str, event: AnyDownloadEvent, _: &mut ()) {
+    let name = || ("file", json::string(file));
+    match event.event() {
+        DownloadEvent::Init(_) => progress::event("download_started", &[name()]),
+        DownloadEvent::Progress(e) => progress::event(
+            "download_progress",
+            &[
+                name(),
+                ("downloaded", e.downloaded.to_string()),
+                ("total", e.total.to_string()),
+            ],
+        ),
+        DownloadEvent::Completed(e) => {
+            let result = match e.result {
+                DownloadResult::Success => "success",
+                DownloadResult::UpToDate => "up_to_date",
+                DownloadResult::Failed => "failed",
+            };
+            progress::event(
+                "download_finished",
+                &[name(), ("result", json::string(result))],
+            );
+        }
+        DownloadEvent::Retry(_) => (),
+    }
+
+    match event.event() {
+        DownloadEvent::Init(_) => warning!("downloading {}...", file),
+        DownloadEvent::Completed(e) => match e.result {
+            DownloadResult::Failed => eprintln!("{} failed to download", file),
+            DownloadResult::UpToDate => warning!("{} is up to date", file),
+            _ => (),
+        },
+        _ => (),
+    }
+}
+
+fn log_cb(level: LogLevel, msg: &str, _: &mut ()) {
+    match level {
+        LogLevel::WARNING if crate::warning::enabled() => eprint!("warning: {}", msg),
+        LogLevel::ERROR => eprint!("error: {}", msg),
+        _ => (),
+    }
+}
+
+fn event_cb(event: AnyEvent, _: &mut ()) {
+    if let Event::DatabaseMissing(e) = event.event() {
+        warning!(
+            "database file for {} does not exist (use pacman to download)",
+            e.dbname()
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use crate::args::FileType;
+
+pub fn is_type(data: &[u8], file_type: FileType) -> bool {
+    match file_type {
+        FileType::Elf => data.starts_with(b"\x7fELF"),
+        FileType::Script => data.starts_with(b"#!"),
+        FileType::Image => is_image(data),
+        FileType::Archive => is_archive(data),
+        FileType::Pdf => data.starts_with(b"%PDF-"),
+        FileType::Text => !crate::is_binary(data),
+        FileType::Binary => crate::is_binary(data),
+    }
+}
+
+/// The first type of --is that data is, falling back to text or binary.
+pub fn detect(data: &[u8]) -> FileType {
+    [
+        FileType::Elf,
+        FileType::Script,
+        FileType::Image,
+        FileType::Archive,
+        FileType::Pdf,
+        FileType::Binary,
+    ]
+    .iter()
+    .copied()
+    .find(|&file_type| is_type(data, file_type))
+    .unwrap_or(FileType::Text)
+}
+
+fn is_image(data: &[u8]) -> bool {
+    data.starts_with(b"\x89PNG\r\n\x1a\n")
+        || data.starts_with(b"\xff\xd8\xff")
+        || data.starts_with(b"GIF87a")
+        || data.starts_with(b"GIF89a")
+        || data.starts_with(b"\x00\x00\x01\x00")
+        || data.starts_with(b"BM")
+        || (data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP"))
+        || data.starts_with(b"<svg")
+}
+
+fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(b"\x1f\x8b")
+        || data.starts_with(b"\xfd7zXZ\x00")
+        || data.starts_with(b"\x28\xb5\x2f\xfd")
+        || data.starts_with(b"BZh")
+        || data.starts_with(b"PK\x03\x04")
+        || data.starts_with(b"7z\xbc\xaf\x27\x1c")
+        || data.get(257..262) == Some(b"ustar")
+}
@@ -1,20 +1,91 @@
-use crate::args::Args;
-use crate::pacman::{alpm_init, get_dbpkg, get_download_url};
-use alpm::{Alpm, Package};
-use alpm_utils::DbListExt;
+use crate::args::{Args, Checksum, DumpOrder, Rewrite};
+use crate::chunks::FirstChunk;
+use crate::error::PaccatError;
+use crate::hardlink::Hardlinks;
+#[cfg(feature = "pacman")]
+use crate::libalpm::alpm_init;
+use crate::noextract::NoExtract;
+use crate::output::{Output, Tee};
+#[cfg(not(feature = "pacman"))]
+use crate::pacman::alpm_init;
+use crate::pacman::{file_url_path, split_target, target_kind, NoDb, PkgDb, TargetKind};
+use crate::semaphore::Semaphore;
+use crate::spinner::Spinner;
 use anyhow::{bail, Context, Result};
 use clap::Parser;
-use compress_tools::{ArchiveContents, ArchiveIterator};
+use compress_tools::{list_archive_files, ArchiveContents, ArchiveIterator};
+use nix::fcntl::{fcntl, FcntlArg};
 use nix::sys::signal::{signal, SigHandler, Signal};
+use nix::sys::stat::utimes;
+use nix::sys::time::{TimeVal, TimeValLike};
 use nix::unistd::isatty;
-use regex::RegexSet;
-use std::fs::{File, OpenOptions};
-use std::io::{self, Read, Seek, Write};
-use std::os::unix::io::AsRawFd;
-use std::path::Path;
+use regex::{Regex, RegexSet};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufWriter, Read, Seek, Write};
+use std::iter;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+/// Prints a warning or a note to stderr unless --quiet-errors was given.
+/// Errors and what --verbose asks for are printed with eprintln! instead.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        if $crate::warning::enabled() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+mod argfile;
 mod args;
+mod backup;
+mod bench;
+mod buildinfo;
+mod cache;
+mod chunks;
+mod configs;
+mod diff;
+mod download;
+mod encoding;
+mod error;
+mod filter;
+mod foreign;
+mod format;
+mod glob;
+mod hardlink;
+mod hash;
+mod highlight;
+mod interrupt;
+mod json;
+#[cfg(feature = "pacman")]
+mod libalpm;
+mod magic;
+mod manifest;
+mod mtree;
+mod noextract;
+mod offsets;
+mod output;
 mod pacman;
+mod pager;
+mod pick;
+mod pkginfo;
+mod progress;
+mod rescue;
+mod resume;
+mod semaphore;
+mod signature;
+mod spinner;
+mod suggest;
+mod summary;
+mod tar;
+mod verify;
+mod warning;
+mod wrap;
 
 #[derive(PartialEq, Eq)]
 enum EntryState {
@@ -23,19 +94,134 @@ enum EntryState {
     Reading,
 }
 
+#[derive(Default)]
+struct Stats {
+    bytes_read: u64,
+    found: usize,
+    /// The files matched in the packages before this one for --max-matches.
+    matched_before: usize,
+    hash_mismatch: bool,
+    /// The patterns that matched a file in this package for --strict-exit.
+    patterns: HashSet<usize>,
+    /// The lines for --sha256sums of the files of this package.
+    sums: Vec<String>,
+    /// Whether an extracted file differs from its .MTREE for
+    /// --verify-extracted.
+    extract_mismatch: bool,
+    /// The patterns that matched anything with --jobs, for --match-report.
+    matched_patterns: HashSet<String>,
+}
+
+#[derive(Clone)]
 struct Match {
     with: MatchWith,
     exact_file: bool,
+    usrmerge: bool,
+    /// Files of the installed version that are never matched for
+    /// --only-new-files.
+    old_files: HashSet<String>,
+    /// The only files that can match in the current package for
+    /// --select-newest and --select-oldest.
+    selected: Option<HashSet<String>>,
+    /// Whether regex patterns can match the members pacman adds to a
+    /// package.
+    meta_members: bool,
+    /// Whether whole paths are compared case insensitively for
+    /// --case-fold-paths.
+    case_fold: bool,
+    /// Whether the patterns are matched against each component of the path
+    /// for --match-any-component-ci.
+    component: bool,
+    /// Whether a directory pattern matches everything in it for
+    /// --recursive-dir.
+    recursive_dir: bool,
+    /// The index of the pattern the last matched file matched.
+    last: Option<usize>,
+    /// Every pattern, as patterns() can lose the ones that were used up.
+    given: Vec<String>,
+    /// The patterns that matched any file, for --match-report.
+    matched: HashSet<String>,
 }
 
 impl Match {
-    fn new(regex: bool, files: Vec<String>) -> Result<Self> {
-        let exact_file = files.iter().any(|f| f.contains('/'));
+    fn new(
+        regex: bool,
+        files: Vec<String>,
+        exact_file: Option<bool>,
+        usrmerge: bool,
+        case_fold: bool,
+    ) -> Result<Self> {
+        if regex {
+            check_regexes(&files)?;
+        }
+        let files = if regex {
+            files
+        } else {
+            files.iter().flat_map(|f| glob::expand_braces(f)).collect()
+        };
+        let exact_file = exact_file.unwrap_or_else(|| files.iter().any(|f| f.contains('/')));
+        let files = files
+            .into_iter()
+            .map(|f| anchor(regex, f))
+            .map(|f| match (case_fold, regex) {
+                (true, true) => format!("(?i){}", f),
+                (true, false) => f.to_lowercase(),
+                (false, _) => f,
+            })
+            .collect::<Vec<_>>();
+        let given = files.clone();
         let with = MatchWith::new(regex, files)?;
-        Ok(Self { exact_file, with })
+        Ok(Self {
+            exact_file,
+            with,
+            usrmerge,
+            old_files: HashSet::new(),
+            selected: None,
+            meta_members: false,
+            case_fold,
+            component: false,
+            recursive_dir: false,
+            last: None,
+            given,
+            matched: HashSet::new(),
+        })
+    }
+
+    /// The patterns, after brace expansion.
+    fn patterns(&self) -> Vec<&str> {
+        match &self.with {
+            MatchWith::Regex(r) => r.patterns().iter().map(String::as_str).collect(),
+            MatchWith::Files(f) => f.iter().map(String::as_str).collect(),
+        }
     }
 
     fn is_match(&mut self, file: &str, remove: bool) -> bool {
+        if self.old_files.contains(file) {
+            return false;
+        }
+        if matches!(&self.selected, Some(selected) if !selected.contains(file)) {
+            return false;
+        }
+        if !self.meta_members && matches!(self.with, MatchWith::Regex(_)) && is_meta_member(file) {
+            return false;
+        }
+
+        let folded;
+        let file = if self.case_fold && matches!(self.with, MatchWith::Files(_)) {
+            folded = file.to_lowercase();
+            &folded
+        } else {
+            file
+        };
+
+        if self.component {
+            let found = file
+                .split('/')
+                .filter(|c| !c.is_empty())
+                .find_map(|c| self.with.find(c, None, false, false));
+            return self.found(found);
+        }
+
         let file = if !self.exact_file {
             file.rsplit('/').next().unwrap()
         } else {
@@ -46,22 +232,107 @@ impl Match {
             return false;
         }
 
-        match self.with {
-            MatchWith::Regex(ref r) => r.is_match(file),
-            MatchWith::Files(ref mut f) => {
-                if let Some(pos) = f.iter().position(|t| t == file) {
-                    if remove {
-                        f.remove(pos);
-                    }
-                    true
-                } else {
-                    false
-                }
+        let alt = if self.usrmerge && self.exact_file {
+            usrmerge_alt(file)
+        } else {
+            None
+        };
+        let found = self
+            .with
+            .find(file, alt.as_deref(), remove, self.recursive_dir);
+        self.found(found)
+    }
+
+    fn found(&mut self, found: Option<(usize, String)>) -> bool {
+        self.last = found.as_ref().map(|&(i, _)| i);
+        match found {
+            Some((_, pattern)) => {
+                self.matched.insert(pattern);
+                true
             }
+            None => false,
+        }
+    }
+
+    /// The patterns that haven't matched any file.
+    fn unmatched(&self) -> Vec<&str> {
+        self.given
+            .iter()
+            .filter(|p| !self.matched.contains(*p))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// Compiles each pattern on its own so the one that is wrong can be named,
+/// which the error of RegexSet doesn't do.
+fn check_regexes(files: &[String]) -> Result<()> {
+    for (i, file) in files.iter().enumerate() {
+        if let Err(e) = Regex::new(file) {
+            bail!("pattern #{} '{}': {}", i + 1, file, regex_error(e));
+        }
+    }
+    Ok(())
+}
+
+/// The reason from a regex error without the copy of the pattern it starts
+/// with.
+fn regex_error(err: regex::Error) -> String {
+    match err {
+        regex::Error::Syntax(e) => {
+            let reason = e.lines().last().unwrap_or(&e);
+            reason.strip_prefix("error: ").unwrap_or(reason).to_string()
+        }
+        e => e.to_string(),
+    }
+}
+
+/// Whether file is one of the members pacman adds to the top of a package.
+fn is_meta_member(file: &str) -> bool {
+    matches!(
+        file,
+        ".PKGINFO" | ".MTREE" | ".BUILDINFO" | ".INSTALL" | ".CHANGELOG"
+    )
+}
+
+/// The other path of file on a usrmerged system, `bin/foo` for `usr/bin/foo`
+/// and the other way around.
+fn usrmerge_alt(file: &str) -> Option<String> {
+    for dir in ["bin/", "sbin/", "lib/"] {
+        if let Some(rest) = file.strip_prefix("usr/").and_then(|f| f.strip_prefix(dir)) {
+            return Some(format!("{}{}", dir, rest));
+        }
+        if let Some(rest) = file.strip_prefix(dir) {
+            return Some(format!("usr/{}{}", dir, rest));
         }
     }
+    None
+}
+
+fn exact_file(args: &Args) -> Option<bool> {
+    if args.basename {
+        Some(false)
+    } else if args.no_basename {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+fn anchor(regex: bool, file: String) -> String {
+    if !file.starts_with('/') {
+        return file;
+    }
+
+    let file = file.trim_start_matches('/');
+    if regex {
+        format!("^{}", file)
+    } else {
+        file.to_string()
+    }
 }
 
+#[derive(Clone)]
 enum MatchWith {
     Regex(RegexSet),
     Files(Vec<String>),
@@ -78,13 +349,55 @@ impl MatchWith {
 
         Ok(match_with)
     }
+
+    /// The index of the first pattern that matches file and the pattern. A
+    /// file pattern ending in `/` matches the directory with or without the
+    /// slash, and also everything in it when recursive.
+    fn find(
+        &mut self,
+        file: &str,
+        alt: Option<&str>,
+        remove: bool,
+        recursive: bool,
+    ) -> Option<(usize, String)> {
+        match self {
+            MatchWith::Regex(r) => r
+                .matches(file)
+                .iter()
+                .next()
+                .or_else(|| alt.and_then(|a| r.matches(a).iter().next()))
+                .map(|i| (i, r.patterns()[i].clone())),
+            MatchWith::Files(f) => {
+                let is_match = |t: &str, file: &str| {
+                    t == file
+                        || matches!(t.strip_suffix('/'), Some(dir) if dir == file)
+                        || (recursive && t.ends_with('/') && file.starts_with(t))
+                };
+                let pos = f
+                    .iter()
+                    .position(|t| is_match(t, file) || matches!(alt, Some(a) if is_match(t, a)));
+                pos.map(|pos| {
+                    if remove && !(recursive && f[pos].ends_with('/')) {
+                        (pos, f.remove(pos))
+                    } else {
+                        (pos, f[pos].clone())
+                    }
+                })
+            }
+        }
+    }
 }
 
 fn main() {
     unsafe { signal(Signal::SIGPIPE, SigHandler::SigDfl).unwrap() };
+    interrupt::install();
+
+    let res = run();
+    download::cleanup();
 
-    match run() {
+    match res {
         Ok(i) => std::process::exit(i),
+        Err(e) if e.is::<interrupt::Interrupted>() => std::process::exit(130),
         Err(e) => {
             eprintln!("error: {}", e);
             std::process::exit(1);
@@ -93,170 +406,3151 @@ fn main() {
 }
 
 fn run() -> Result<i32> {
-    let mut args = args::Args::parse();
-    let mut ret = 0;
-    let stdout = io::stdout();
+    let args = args::Args::parse_from(argfile::expand_args(argfile::args_with_env())?);
+    warning::init(&args);
 
-    args.binary |= !isatty(stdout.as_raw_fd()).unwrap_or(false);
-    args.binary |= args.extract;
+    let ret = match &args.query_list {
+        Some(path) => run_query_list(&args, path),
+        None => run_args(args.clone()),
+    };
 
-    let files = args
-        .files
-        .iter()
-        .map(|f| f.trim_start_matches('/').to_string())
-        .collect::<Vec<_>>();
+    if args.exit_zero {
+        ret.map(|_| 0)
+    } else {
+        ret
+    }
+}
+
+/// Reads the patterns for --files-from. Regexes are checked one at a time
+/// so a bad one can be reported with its line.
+fn files_from(path: &str, regex: bool) -> Result<Vec<String>> {
+    let list = if path == "-" {
+        let mut list = String::new();
+        io::stdin()
+            .read_to_string(&mut list)
+            .context("failed to read files from stdin")?;
+        list
+    } else {
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?
+    };
+
+    let mut files = Vec::new();
+    for (i, line) in list.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        if regex {
+            if let Err(e) = Regex::new(line) {
+                bail!(
+                    "{}:{}: invalid regex '{}': {}",
+                    path,
+                    i + 1,
+                    line,
+                    regex_error(e)
+                );
+            }
+        }
+        files.push(line.to_string());
+    }
+
+    Ok(files)
+}
 
-    let mut matcher = Match::new(args.regex, files)?;
-    let alpm = alpm_init(&args)?;
+/// Runs each target of --query-list with only the files listed for it.
+/// The exit codes are combined like those of the packages in one run.
+fn run_query_list(args: &Args, path: &str) -> Result<i32> {
+    let list = if path == "-" {
+        let mut list = String::new();
+        io::stdin()
+            .read_to_string(&mut list)
+            .context("failed to read the query list from stdin")?;
+        list
+    } else {
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?
+    };
+
+    let mut queries: Vec<(String, Vec<String>)> = Vec::new();
+    for (i, line) in list.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (target, file) = match line.split_once(char::is_whitespace) {
+            Some((target, file)) => (target, file.trim()),
+            None => bail!("{}:{}: expected `<target> <file>`", path, i + 1),
+        };
 
-    let pkgs = get_targets(&alpm, &args, &mut matcher)?;
+        match queries.iter_mut().find(|(t, _)| t == target) {
+            Some((_, files)) => files.push(file.to_string()),
+            None => queries.push((target.to_string(), vec![file.to_string()])),
+        }
+    }
 
-    for pkg in pkgs {
-        let file = File::open(&pkg).with_context(|| format!("failed to open {}", pkg))?;
-        let archive = ArchiveIterator::from_read(file)?;
-        ret |= dump_files(archive, &mut matcher, &args)?;
+    if queries.is_empty() {
+        bail!("{} does not contain any queries", path);
+    }
+
+    let mut ret = 0;
+    for (target, files) in queries {
+        let mut args = args.clone();
+        args.query_list = None;
+        args.targets = vec![target];
+        args.files = files;
+        ret |= run_args(args)?;
     }
 
     Ok(ret)
 }
 
-fn dump_files<R>(archive: ArchiveIterator<R>, matcher: &mut Match, args: &Args) -> Result<i32>
-where
-    R: Read + Seek,
-{
+fn run_args(mut args: Args) -> Result<i32> {
+    let mut ret = 0;
     let stdout = io::stdout();
-    let mut stdout = stdout.lock();
-    let mut state = EntryState::Skip;
-    let mut found = 0;
-    let mut cur_file = String::new();
-    let mut cur_extract_file: Option<File> = None;
-
-    for content in archive {
-        match content {
-            ArchiveContents::StartOfEntry(file) => {
-                if matcher.is_match(&file, !args.all) {
-                    found += 1;
 
-                    if args.quiet || args.extract {
-                        writeln!(stdout, "{}", file)?;
+    if args.stdin_targets {
+        for line in io::stdin().lock().lines() {
+            let line = line.context("failed to read targets from stdin")?;
+            let line = line.trim();
+            if !line.is_empty() {
+                args.targets.push(line.to_string());
+            }
+        }
+        if args.targets.is_empty() && !args.localdb && !args.filedb {
+            bail!("no targets were given on stdin");
+        }
+    }
 
-                        if args.extract {
-                            state = EntryState::FirstChunk;
-                            cur_file = file;
+    if let Some(path) = &args.files_from {
+        if path == "-" && args.stdin_targets {
+            bail!("--files-from - and --stdin-targets can't both read stdin");
+        }
+        let files = files_from(path, args.regex)?;
+        if files.is_empty() {
+            bail!("{} does not contain any files", path);
+        }
+        args.files.extend(files);
+    }
 
-                            let filename = cur_file.rsplit('/').next().unwrap();
-                            let extract_file = OpenOptions::new()
-                                .write(true)
-                                .create(true)
-                                .truncate(true)
-                                .open(filename)
-                                .with_context(|| format!("failed to open target {}", filename))?;
-                            cur_extract_file = Some(extract_file);
-                        }
-                    } else {
-                        state = EntryState::FirstChunk;
-                        cur_file = file;
-                    }
-                }
-            }
-            ArchiveContents::DataChunk(v) if state == EntryState::FirstChunk => {
-                if !args.binary && is_binary(&v) {
-                    state = EntryState::Skip;
-                    eprintln!("{} is a binary file -- use --binary to print", cur_file);
-                } else {
-                    state = EntryState::Reading;
+    // files given as arguments or with --files-from win over stdin
+    if args.patternless_stdin && args.files.is_empty() {
+        if isatty(io::stdin().as_raw_fd()).unwrap_or(false) {
+            bail!("--patternless-stdin needs files after the targets or piped to stdin");
+        }
+        args.files = files_from("-", args.regex)?;
+        if args.files.is_empty() {
+            bail!("no files were given on stdin");
+        }
+    }
 
-                    if let Some(extract_file) = &mut cur_extract_file {
-                        extract_file.write_all(&v)?;
-                    } else {
-                        stdout.write_all(&v)?;
-                    }
-                }
-            }
-            ArchiveContents::DataChunk(v) if state == EntryState::Reading => {
-                if let Some(extract_file) = &mut cur_extract_file {
-                    extract_file.write_all(&v)?;
-                } else {
-                    stdout.write_all(&v)?;
-                }
-            }
-            ArchiveContents::DataChunk(_) => (),
-            ArchiveContents::EndOfEntry => state = EntryState::Skip,
-            ArchiveContents::Err(e) => {
-                return Err(e.into());
-            }
+    if let Some(dir) = &args.scan_cache {
+        let dir = dir.as_deref().unwrap_or("/var/cache/pacman/pkg");
+        let pkgs = cache_packages(dir)?;
+        if args.verbose {
+            eprintln!("{} has {} packages", dir, pkgs.len());
         }
+        args.targets.extend(pkgs);
     }
 
-    let ret = match &matcher.with {
-        MatchWith::Files(f) if f.is_empty() => 0,
-        MatchWith::Regex(_) if found != 0 => 0,
-        _ => 1,
-    };
+    args.binary |=
+        args.grep.is_none() && !args.fail_on_binary && !isatty(stdout.as_raw_fd()).unwrap_or(false);
+    // the names are counted instead of printed
+    args.quiet |= args.summary_only;
+    args.binary |= args.extract
+        || args.split_output.is_some()
+        || args.output_template.is_some()
+        || args.tar
+        || args.compare_installed;
 
-    Ok(ret)
-}
+    if args.pick
+        && !(isatty(io::stdin().as_raw_fd()).unwrap_or(false)
+            && isatty(stdout.as_raw_fd()).unwrap_or(false))
+    {
+        bail!("--pick requires stdin and stdout to be a terminal");
+    }
 
-fn is_binary(data: &[u8]) -> bool {
-    data.iter().take(512).any(|&b| b == 0)
-}
+    if args.no_sync_db && !args.targets.is_empty() {
+        bail!("--no-sync-db only reads the installed packages of --localdb, targets need the sync databases");
+    }
 
-fn get_targets(alpm: &Alpm, args: &Args, matcher: &mut Match) -> Result<Vec<String>> {
-    let mut download = Vec::new();
-    let mut repo = Vec::new();
-    let mut files = Vec::new();
-    let dbs = alpm.syncdbs();
+    if args.parallel_hash && args.checksum.is_none() && args.sha256sums.is_none() {
+        bail!("--parallel-hash needs --checksum or --sha256sums to hash the files for");
+    }
 
-    if args.targets.is_empty() {
-        if args.localdb {
-            let pkgs = alpm.localdb().pkgs();
-            let pkgs = pkgs
-                .iter()
-                .filter(|pkg| want_pkg(alpm, *pkg, matcher))
-                .filter_map(|p| dbs.pkg(p.name()).ok());
-            repo.extend(pkgs);
-        } else if args.filedb {
-            let pkgs = dbs
-                .iter()
-                .flat_map(|db| db.pkgs())
-                .filter(|pkg| want_pkg(alpm, *pkg, matcher));
-            repo.extend(pkgs);
+    if args.count_matches && args.grep.is_none() && args.contains.is_none() {
+        bail!("--count-matches needs --grep or --contains to count the lines of");
+    }
+
+    // every package has to have every file so none are matched only once
+    args.all |= args.strict_exit;
+
+    if args.jobs > 1 && !args.regex && !args.all && !args.files.is_empty() {
+        bail!("--jobs needs --all or --regex, otherwise each file is only printed from the first package that has it");
+    }
+
+    progress::init(&args)?;
+
+    if pacman::needs_alpm(&args) && rescue::degraded(&args) {
+        if args.verbose {
+            eprintln!("the sync databases are missing under --root, reading its package cache");
         }
+        rescue::resolve(&mut args)?;
+    }
+
+    let mut matcher = Match::new(
+        args.regex,
+        args.files.clone(),
+        exact_file(&args),
+        args.usrmerge,
+        args.case_fold_paths || args.match_any_component_ci,
+    )?;
+    matcher.meta_members = args.include_meta_members;
+    matcher.component = args.match_any_component_ci;
+    matcher.recursive_dir = args.recursive_dir;
+    let db = if pacman::needs_alpm(&args) {
+        Some(alpm_init(&args)?)
     } else {
-        for targ in &args.targets {
-            if let Ok(pkg) = get_dbpkg(alpm, targ) {
-                if pkg.files().files().is_empty() || want_pkg(alpm, pkg, matcher) {
-                    repo.push(pkg);
-                }
-            } else if targ.contains("://") {
-                download.push(targ.clone());
-            } else if Path::new(&targ).exists() {
-                files.push(targ.to_string());
-            } else {
-                bail!("'{}' is not a package, file or url", targ);
+        None
+    };
+    if let (true, Some(db)) = (args.testing, &db) {
+        if !db.repos().into_iter().any(is_testing_repo) {
+            warning!("warning: --testing was given but no testing repos are configured");
+        }
+    }
+    if let (Some(repos), Some(db)) = (&args.repo_priority, &db) {
+        for repo in repos.split(',') {
+            if !db.repos().contains(&repo) {
+                warning!(
+                    "warning: {} from --repo-priority is not a configured repo",
+                    repo
+                );
             }
         }
     }
+    if let (true, Some(db)) = (pacman::needs_files_db(&args), &db) {
+        pacman::check_files_db(db)?;
+    }
+    let noextracts = db.as_ref().map(PkgDb::noextracts).unwrap_or_default();
+    let noextract = NoExtract::new(noextracts.iter().map(String::as_str))
+        .context("bad NoExtract in pacman.conf")?;
 
-    // todo filter repopkg files
+    if args.list_repos {
+        if let Some(db) = &db {
+            print_repos(db, &args)?;
+        }
+        return Ok(0);
+    }
 
-    for pkg in repo {
-        download.push(get_download_url(pkg)?);
+    if args.db_info {
+        if let Some(db) = &db {
+            print_db_info(db, &args)?;
+        }
+        return Ok(0);
     }
 
-    let downloaded = alpm.fetch_pkgurl(download.into_iter())?;
-    files.extend(downloaded);
+    if args.summary_only && args.targets.is_empty() && (args.filedb || args.localdb) {
+        return match &db {
+            Some(db) => print_db_summary(db, &args, &mut matcher),
+            None => print_db_summary(&NoDb, &args, &mut matcher),
+        };
+    }
 
-    Ok(files)
-}
+    if args.list_backup_files && args.targets.is_empty() && args.localdb {
+        return match &db {
+            Some(db) => backup::print_installed_backup_files(db, &args, &mut matcher),
+            None => backup::print_installed_backup_files(&NoDb, &args, &mut matcher),
+        };
+    }
 
-fn want_pkg(_alpm: &Alpm, pkg: Package, matcher: &mut Match) -> bool {
-    let files = pkg.files();
-    if matches!(matcher.with, MatchWith::Files(ref f) if f.is_empty()) {
-        return false;
+    if args.list_changed_configs {
+        return match &db {
+            Some(db) => configs::list_changed_configs(db, &args, &mut matcher, &noextract),
+            None => configs::list_changed_configs(&NoDb, &args, &mut matcher, &noextract),
+        };
+    }
+
+    if args.list_duplicates {
+        return match &db {
+            Some(db) => print_duplicates(db, &args, &mut matcher),
+            None => print_duplicates(&NoDb, &args, &mut matcher),
+        };
+    }
+
+    progress::event(
+        "resolve_started",
+        &[("targets", args.targets.len().to_string())],
+    );
+    let mut targets = match &db {
+        Some(db) => resolve_targets(db, &args, &mut matcher)?,
+        None => resolve_targets(&NoDb, &args, &mut matcher)?,
+    };
+    progress::event(
+        "resolve_finished",
+        &[("resolved", targets.resolved.len().to_string())],
+    );
+
+    if args.resolve_only {
+        print_resolutions(&targets.resolved)?;
+        return Ok(0);
+    }
+
+    if args.trace_resolve {
+        print_trace(&targets)?;
+        return Ok(0);
+    }
+
+    if manifest::auditing(&args) {
+        let pkgs = fetch_targets(db.as_ref(), &args, targets)?;
+        return manifest::print_audit(&pkgs, &mut matcher, &args);
+    }
+
+    if args.manifest {
+        let pkgs = fetch_targets(db.as_ref(), &args, targets)?;
+        return manifest::print_manifest(&pkgs, &mut matcher, &args);
+    }
+
+    if args.list_backup_files {
+        let pkgs = fetch_targets(db.as_ref(), &args, targets)?;
+        return backup::print_backup_files(&pkgs, &mut matcher, &args);
+    }
+
+    if args.timestamp_sort.is_some() {
+        let pkgs = fetch_targets(db.as_ref(), &args, targets)?;
+        return manifest::print_by_time(&pkgs, &mut matcher, &args);
+    }
+
+    if args.verify_only {
+        let sums = std::mem::take(&mut targets.sha256);
+        let pkgs = fetch_targets(db.as_ref(), &args, targets)?;
+        return verify::verify(&pkgs, &sums, &args);
+    }
+
+    if args.print_entry_offsets {
+        let pkgs = fetch_targets(db.as_ref(), &args, targets)?;
+        return offsets::print_offsets(&pkgs, &mut matcher, &args);
+    }
+
+    if args.print_compression {
+        let pkgs = fetch_targets(db.as_ref(), &args, targets)?;
+        print_compression(&pkgs, &args)?;
+        return Ok(0);
+    }
+
+    if args.build_info {
+        let pkgs = fetch_targets(db.as_ref(), &args, targets)?;
+        return buildinfo::print_build_info(&pkgs, &args);
+    }
+
+    if args.benchmark {
+        let pkgs = fetch_targets(db.as_ref(), &args, targets)?;
+        return bench::benchmark(&pkgs, &mut matcher, &args);
+    }
+
+    let mut compressor = tar::compressor(&args)?;
+    let mut pager = pager::spawn(&args)?;
+    let stdout: Box<dyn Write> = match pager
+        .as_mut()
+        .or(compressor.as_mut())
+        .and_then(|p| p.stdin.take())
+    {
+        _ if args.summary_only => Box::new(io::sink()),
+        Some(stdin) => Box::new(stdin),
+        None => Box::new(stdout.lock()),
+    };
+    let stdout = Tee::new(stdout, args.tee.as_deref())?;
+    let stdout = BufWriter::with_capacity(args.buffer_size, stdout);
+    let mut output = Output::new(stdout, &args)?;
+    let mut total_bytes = 0;
+    let mut bytes_read = 0;
+    let mut hash_mismatch = false;
+    let mut extract_mismatch = false;
+    let mut found_on_disk = false;
+    let mut matched = 0;
+    let mut summary = summary::Summary::new(&targets.resolved);
+    let mut summary_rows = Vec::new();
+    let context = if args.context_package {
+        package_context(&targets.resolved)
+    } else {
+        HashMap::new()
+    };
+
+    for (name, files) in std::mem::take(&mut targets.disk) {
+        interrupt::check()?;
+        if let Some(context) = context.get(&name) {
+            output.out().flush()?;
+            eprintln!("package: {}", context);
+        }
+        output.set_package(&name);
+        let found = dump_disk_files(&files, &args, &mut output)?;
+        if args.verbose {
+            eprintln!("{}: read {} files from disk", name, files.len());
+        }
+        summary.record_disk(&name, found);
+        if found != 0 {
+            summary_rows.push((name.clone(), found));
+        }
+        matched += found;
+        check_max_matches(&args, matched)?;
+        ret |= if found != 0 { 0 } else { 1 };
+        found_on_disk |= found != 0;
+    }
+
+    let mut sums = args
+        .sha256sums
+        .as_deref()
+        .map(|path| {
+            File::create(path)
+                .map(BufWriter::new)
+                .with_context(|| format!("failed to create {}", path))
+        })
+        .transpose()?;
+    let mut resume = args
+        .resume
+        .as_deref()
+        .map(resume::Resume::open)
+        .transpose()?;
+    let mut old_files = std::mem::take(&mut targets.old_files);
+    let mut selected = HashMap::new();
+
+    // with --first each package is only downloaded once the ones before it
+    // did not match
+    let batches = if args.first && found_on_disk {
+        ret = 0;
+        Vec::new()
+    } else if args.first {
+        targets.into_batches()
+    } else if args.select_newest || args.select_oldest {
+        let mut pkgs = Vec::new();
+        for (pkg, url) in fetch_sources(db.as_ref(), &args, targets)? {
+            if let Some(url) = url {
+                summary.downloaded(&url, &pkg);
+            }
+            pkgs.push(pkg);
+        }
+        selected = manifest::select_by_time(&pkgs, &mut matcher, args.select_newest)?;
+        vec![Targets::from_files(pkgs)]
+    } else {
+        vec![targets]
+    };
+    let pkgs =
+        batches
+            .into_iter()
+            .flat_map(|batch| match fetch_sources(db.as_ref(), &args, batch) {
+                Ok(pkgs) => pkgs.into_iter().map(Ok).collect::<Vec<_>>(),
+                Err(e) => vec![Err(e)],
+            });
+
+    let jobs = args.jobs.max(1);
+    // decompressing is what takes the memory, so fewer archives than
+    // threads may be open at once
+    let archives = Semaphore::new(args.max_concurrent_archives.unwrap_or(jobs.min(4)));
+    // the file lists looked at while resolving don't count as matches
+    matcher.matched.clear();
+    let mut pkgs = pkgs.peekable();
+    'batches: while pkgs.peek().is_some() {
+        let mut batch = Vec::with_capacity(jobs);
+        for pkg in pkgs.by_ref() {
+            let (pkg, url) = pkg?;
+            if let Some(url) = url {
+                summary.downloaded(&url, &pkg);
+            }
+            if matches!(&resume, Some(resume) if resume.is_done(manifest::pkg_name(&pkg))) {
+                if args.verbose {
+                    eprintln!("{}: already read, skipping", manifest::pkg_name(&pkg));
+                }
+                continue;
+            }
+            batch.push(pkg);
+            if batch.len() == jobs {
+                break;
+            }
+        }
+        interrupt::check()?;
+        // --resume can skip every package that was left
+        if batch.is_empty() {
+            continue;
+        }
+
+        let mut matchers = Vec::with_capacity(batch.len());
+        for pkg in &batch {
+            if args.only_new_files {
+                matcher.old_files = old_files
+                    .remove(manifest::pkg_name(pkg))
+                    .unwrap_or_default();
+            }
+            if args.select_newest || args.select_oldest {
+                matcher.selected = selected.remove(manifest::pkg_name(pkg));
+            }
+            if jobs > 1 {
+                matchers.push(matcher.clone());
+            }
+        }
+
+        for pkg in &batch {
+            let name = json::string(manifest::pkg_name(pkg));
+            progress::event("scan_started", &[("package", name)]);
+        }
+
+        // with --jobs or --group-by-package each package is read into its
+        // own buffer which is printed in order once all of the batch is read
+        let mut results = Vec::with_capacity(batch.len());
+        if jobs == 1 {
+            let pkg = batch.pop().unwrap();
+            let mut buffered = if args.group_by_package {
+                Some(Output::new(Vec::new(), &args)?)
+            } else {
+                print_context(&args, &context, &pkg, output.out())?;
+                None
+            };
+            let mut stats = Stats {
+                matched_before: matched,
+                ..Stats::default()
+            };
+            let start = Instant::now();
+            let res = match &mut buffered {
+                Some(buffered) => {
+                    read_pkg(&pkg, &mut matcher, &noextract, &args, buffered, &mut stats)?
+                }
+                None => read_pkg(
+                    &pkg,
+                    &mut matcher,
+                    &noextract,
+                    &args,
+                    &mut output,
+                    &mut stats,
+                )?,
+            };
+            results.push((pkg, buffered, res, stats, start.elapsed()));
+        } else {
+            let (args, noextract, archives) = (&args, &noextract, &archives);
+            let read = thread::scope(|scope| {
+                let handles = batch
+                    .into_iter()
+                    .zip(matchers)
+                    .map(|(pkg, mut matcher)| {
+                        scope.spawn(move || -> Result<_> {
+                            let mut output = Output::new(Vec::new(), args)?;
+                            let mut stats = Stats::default();
+                            let _permit = archives.acquire();
+                            let start = Instant::now();
+                            let res = read_pkg(
+                                &pkg,
+                                &mut matcher,
+                                noextract,
+                                args,
+                                &mut output,
+                                &mut stats,
+                            )?;
+                            stats.matched_patterns = matcher.matched;
+                            Ok((pkg, Some(output), res, stats, start.elapsed()))
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("a thread reading a package panicked"))
+                    .collect::<Vec<_>>()
+            });
+            for res in read {
+                results.push(res?);
+            }
+        }
+
+        for (pkg, buffered, res, mut stats, elapsed) in results {
+            matcher
+                .matched
+                .extend(std::mem::take(&mut stats.matched_patterns));
+            if let Some(buffered) = buffered {
+                print_context(&args, &context, &pkg, output.out())?;
+                output.append(buffered)?;
+            }
+            let res = match res {
+                Some(res) => res,
+                None => {
+                    ret |= 1;
+                    continue;
+                }
+            };
+
+            // with --all the patterns are never used up so res is always 1
+            ret |= if args.strict_exit && !args.files.is_empty() {
+                strict_status(&matcher, &pkg, &stats)
+            } else {
+                res
+            };
+            progress::event(
+                "scan_finished",
+                &[
+                    ("package", json::string(manifest::pkg_name(&pkg))),
+                    ("matched", stats.found.to_string()),
+                    ("bytes_read", stats.bytes_read.to_string()),
+                ],
+            );
+            summary.record_file(&pkg, stats.found);
+            if stats.found != 0 {
+                summary_rows.push((manifest::pkg_name(&pkg).to_string(), stats.found));
+            }
+            if let Some(sums) = &mut sums {
+                for line in &stats.sums {
+                    writeln!(sums, "{}", line)?;
+                }
+            }
+            if let Some(resume) = &mut resume {
+                resume.record(manifest::pkg_name(&pkg))?;
+            }
+            bytes_read += stats.bytes_read;
+            matched += stats.found;
+            hash_mismatch |= stats.hash_mismatch;
+            extract_mismatch |= stats.extract_mismatch;
+            check_max_matches(&args, matched)?;
+
+            if args.count_bytes {
+                let bytes = output.take_bytes();
+                total_bytes += bytes;
+                writeln!(output.out(), "{}\t{}", manifest::pkg_name(&pkg), bytes)?;
+            }
+
+            if args.verbose {
+                let rate = stats.bytes_read as f64 / elapsed.as_secs_f64() / (1024.0 * 1024.0);
+                eprintln!(
+                    "{}: scanned {} bytes in {:.2?} ({:.1} MiB/s)",
+                    manifest::pkg_name(&pkg),
+                    stats.bytes_read,
+                    elapsed,
+                    rate
+                );
+            }
+
+            if args.first && res == 0 {
+                ret = 0;
+                break 'batches;
+            }
+        }
+    }
+
+    if args.count_bytes {
+        writeln!(output.out(), "total\t{}", total_bytes)?;
+    }
+
+    output.flush_sorted()?;
+    if let Some(sums) = &mut sums {
+        sums.flush()?;
+    }
+
+    output.finish_tar()?;
+
+    if args.summary_only {
+        output.out().flush()?;
+        let stdout = io::stdout();
+        summary::Summary::print_table(&mut stdout.lock(), &summary_rows, args.json)?;
+    }
+
+    if args.match_report && !args.files.is_empty() {
+        output.out().flush()?;
+        let unmatched = matcher.unmatched();
+        for pattern in &unmatched {
+            warning!("no file matched '{}' (--match-report)", pattern);
+        }
+        if unmatched.is_empty() && args.verbose {
+            eprintln!("every pattern matched a file");
+        }
+    }
+
+    if args.show_misses {
+        output.out().flush()?;
+        for (target, read) in summary.misses() {
+            let packages = if read == 1 { "package" } else { "packages" };
+            warning!(
+                "{}: read {} {} but no files matched",
+                target,
+                read,
+                packages
+            );
+        }
+    }
+
+    if args.json {
+        summary.print(output.out())?;
+        output.out().flush()?;
+    }
+
+    if output.differs() || output.failed_binary() || extract_mismatch {
+        ret = 1;
+    }
+
+    if args.expect_hash.is_some() {
+        if matched > 1 {
+            bail!(
+                "--expect-hash needs one matched file but {} matched",
+                matched
+            );
+        }
+        if hash_mismatch {
+            ret = 4;
+        }
+    }
+
+    let expect = if args.expect_one {
+        Some(1)
+    } else {
+        args.expect
+    };
+    if let Some(expect) = expect {
+        if matched != expect {
+            eprintln!(
+                "error: expected {} matching files but found {}",
+                expect, matched
+            );
+            ret = 3;
+        }
+    }
+
+    drop(output);
+    if let Some(mut pager) = pager {
+        pager.wait().context("failed to wait for the pager")?;
+    }
+    if let Some(mut compressor) = compressor {
+        let status = compressor
+            .wait()
+            .context("failed to wait for the compressor")?;
+        if !status.success() {
+            bail!(
+                "failed to compress the tar, the compressor exited with {}",
+                status
+            );
+        }
+    }
+
+    if let Some(mut report) = report_file(&args)? {
+        summary
+            .report(&mut report, matched, bytes_read, ret)
+            .context("failed to write the report")?;
+    }
+
+    Ok(ret)
+}
+
+/// Prints which target pkg came from to stderr for --context-package.
+fn print_context<W: Write>(
+    args: &Args,
+    context: &HashMap<String, String>,
+    pkg: &str,
+    out: &mut W,
+) -> Result<()> {
+    if args.context_package {
+        out.flush()?;
+        match context.get(manifest::pkg_name(pkg)) {
+            Some(context) => eprintln!("package: {}", context),
+            None => eprintln!("package: {}", pkg),
+        }
+    }
+    Ok(())
+}
+
+/// Prints the matching files of one package. Returns None when the package
+/// was skipped with --keep-going.
+fn read_pkg<W: Write>(
+    pkg: &str,
+    matcher: &mut Match,
+    noextract: &NoExtract,
+    args: &Args,
+    output: &mut Output<W>,
+    stats: &mut Stats,
+) -> Result<Option<i32>> {
+    let mut file = match File::open(pkg) {
+        Ok(file) => file,
+        Err(e) => {
+            skip_unreadable(args, PaccatError::io("open", pkg, e).into())?;
+            return Ok(None);
+        }
+    };
+
+    if args.pick {
+        let names = list_archive_files(&file)?;
+        file.rewind()?;
+        *matcher = Match::new(false, pick::pick_files(&names)?, Some(true), false, false)?;
+    }
+
+    output.set_package(manifest::pkg_name(pkg));
+    if args.include_pkg_meta {
+        print_pkg_meta(output.out(), pkg, args)?;
+    }
+
+    if from_mtree(args, output) {
+        if let Some(res) = dump_mtree(pkg, matcher, noextract, args, output, stats)? {
+            return Ok(Some(res));
+        }
+    }
+
+    let deb = if args.foreign {
+        foreign::deb_data(pkg)?
+    } else {
+        None
+    };
+    let data = format::decompress(pkg, deb, args.archive_format)?;
+
+    let res = if let Some(data) = data {
+        ArchiveIterator::from_read(data)
+            .map_err(Into::into)
+            .and_then(|a| dump_files(a, pkg, matcher, noextract, args, output, stats))
+    } else {
+        ArchiveIterator::from_read(file)
+            .map_err(Into::into)
+            .and_then(|a| dump_files(a, pkg, matcher, noextract, args, output, stats))
+    };
+
+    match res {
+        Ok(res) => Ok(Some(res)),
+        Err(e) => {
+            skip_unreadable(args, archive_error(e, pkg))?;
+            Ok(None)
+        }
+    }
+}
+
+/// Where to write the report for --report-fd or --report-file.
+fn report_file(args: &Args) -> Result<Option<File>> {
+    if let Some(fd) = args.report_fd {
+        if fd < 0 || fcntl(fd, FcntlArg::F_GETFD).is_err() {
+            bail!("--report-fd {} is not an open file descriptor", fd);
+        }
+        return Ok(Some(unsafe { File::from_raw_fd(fd) }));
+    }
+
+    args.report_file
+        .as_deref()
+        .map(|path| File::create(path).with_context(|| format!("failed to create {}", path)))
+        .transpose()
+}
+
+/// Prints the compression of each package from its magic bytes for
+/// --print-compression.
+fn print_compression(pkgs: &[String], args: &Args) -> Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    for pkg in pkgs {
+        let mut magic = Vec::with_capacity(8);
+        File::open(pkg)
+            .and_then(|file| file.take(8).read_to_end(&mut magic))
+            .map_err(|e| PaccatError::io("read", pkg.as_str(), e))?;
+        let compression = format::compression(&magic).unwrap_or("none");
+        let name = manifest::pkg_name(pkg);
+
+        if args.json {
+            writeln!(
+                stdout,
+                "{{\"package\": {}, \"compression\": \"{}\"}}",
+                json::string(name),
+                compression
+            )?;
+        } else {
+            writeln!(stdout, "{}\t{}", name, compression)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints a line for --checksum in the format of sha256sum or as JSON.
+fn print_checksum<W: Write>(
+    out: &mut W,
+    pkg: &str,
+    path: &str,
+    sum: &str,
+    args: &Args,
+) -> Result<()> {
+    let pkg = manifest::pkg_name(pkg);
+
+    if args.json {
+        writeln!(
+            out,
+            "{{\"package\": {}, \"path\": {}, \"{}\": \"{}\"}}",
+            json::string(pkg),
+            json::string(path),
+            args.checksum.map_or("", |c| c.name()),
+            sum
+        )?;
+    } else {
+        writeln!(out, "{}  {}:{}", sum, pkg, path)?;
+    }
+
+    Ok(())
+}
+
+fn print_pkg_meta<W: Write>(out: &mut W, pkg: &str, args: &Args) -> Result<()> {
+    let info = match pkginfo::read_pkginfo(pkg)? {
+        Some(info) => info,
+        None => bail!("{} does not contain a .PKGINFO", pkg),
+    };
+
+    if args.json {
+        writeln!(
+            out,
+            "{{\"package\": {}, \"version\": {}, \"builddate\": {}, \"packager\": {}}}",
+            json::string(&info.name),
+            json::string(&info.version),
+            info.build_date,
+            json::string(&info.packager)
+        )?;
+    } else {
+        writeln!(out, "# package: {}", info.name)?;
+        writeln!(out, "# version: {}", info.version)?;
+        writeln!(out, "# builddate: {}", info.build_date)?;
+        writeln!(out, "# packager: {}", info.packager)?;
+    }
+
+    Ok(())
+}
+
+/// Prints installed files from under the root like dump_files prints them
+/// from a package.
+fn dump_disk_files<W: Write>(
+    files: &[String],
+    args: &Args,
+    output: &mut Output<W>,
+) -> Result<usize> {
+    let root = Path::new(args.root.as_deref().unwrap_or("/"));
+    let mut found = 0;
+
+    for file in files {
+        let path = root.join(file);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                skip_unreadable(
+                    args,
+                    PaccatError::io("read", path.to_string_lossy(), e).into(),
+                )?;
+                continue;
+            }
+        };
+
+        if let Some(file_type) = args.is {
+            if !magic::is_type(&data, file_type) {
+                continue;
+            }
+        }
+
+        if args.quiet && !output.buffered() {
+            writeln!(output.out(), "{}", output::quiet_name(args, file))?;
+            found += 1;
+            continue;
+        }
+
+        output.start(file, None);
+        if first_state(args) == EntryState::FirstChunk
+            && !args.binary
+            && !args.json_lines
+            && !output::transforms_content(args)
+            && has_nul(&data, args.binary_threshold)
+        {
+            output.skip_binary(file)?;
+            continue;
+        }
+        output.write(&data)?;
+        if output.finish(file)? {
+            found += 1;
+        }
+    }
+
+    output.out().flush()?;
+    Ok(found)
+}
+
+/// The package files in dir for --scan-cache.
+fn cache_packages(dir: &str) -> Result<Vec<String>> {
+    let mut pkgs = Vec::new();
+
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read {}", dir))? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+        if name.contains(".pkg.tar") && !name.ends_with(".sig") && !name.ends_with(".part") {
+            pkgs.push(path.to_string_lossy().into_owned());
+        }
+    }
+
+    if pkgs.is_empty() {
+        bail!("{} does not contain any packages", dir);
+    }
+
+    pkgs.sort();
+    Ok(pkgs)
+}
+
+/// Prints err and carries on with the next file or package for
+/// --keep-going. The error says whether the file couldn't be read, such as
+/// from a permission error, or is corrupt.
+fn skip_unreadable(args: &Args, err: anyhow::Error) -> Result<()> {
+    if !args.keep_going {
+        return Err(err);
+    }
+
+    eprintln!("error: {} -- skipping", err);
+    Ok(())
+}
+
+fn archive_error(err: anyhow::Error, pkg: &str) -> anyhow::Error {
+    match err.downcast::<compress_tools::Error>() {
+        Ok(source) => PaccatError::Archive {
+            path: pkg.to_string(),
+            source,
+        }
+        .into(),
+        Err(err) => err,
+    }
+}
+
+fn dump_files<R, W>(
+    archive: ArchiveIterator<R>,
+    pkg: &str,
+    matcher: &mut Match,
+    noextract: &NoExtract,
+    args: &Args,
+    output: &mut Output<W>,
+    stats: &mut Stats,
+) -> Result<i32>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    let mut state = EntryState::Skip;
+    let mut found = 0;
+    let mut cur_file = String::new();
+    let mut cur_pattern = None;
+    let mut hasher = None;
+    let mut sum = None;
+    let mut digests = if args.parallel_hash {
+        Some(hash::Digests::new())
+    } else {
+        None
+    };
+    let mut entries = 0;
+    let filtered = args.contains.is_some()
+        || args.grep.is_some()
+        || args.is.is_some()
+        || args.noextract_only
+        || args.skip_empty
+        || args.min_size.is_some()
+        || args.max_size.is_some();
+    let deferred = output.buffered() || args.is.is_some();
+    let writes_files =
+        args.extract || args.split_output.is_some() || args.output_template.is_some();
+    let modes = if args.preserve_permissions && writes_files {
+        mtree_modes(pkg)?
+    } else {
+        HashMap::new()
+    };
+    let mut restore = None;
+    let mut verify = None;
+    let links = if args.follow_links && !args.foreign {
+        follow_links(pkg, matcher)?
+    } else {
+        Links::default()
+    };
+    // a package without a readable .MTREE just has its hardlinks left empty
+    let mtree =
+        if args.foreign || (args.no_resolve_hardlinks && !args.tar && !args.verify_extracted) {
+            Vec::new()
+        } else {
+            mtree::read_mtree(pkg).ok().flatten().unwrap_or_default()
+        };
+    if args.verify_extracted && mtree.is_empty() {
+        warning!(
+            "warning: {} has no .MTREE, its extracted files can't be verified",
+            manifest::pkg_name(pkg)
+        );
+    }
+    let mtree_entries = if args.tar || args.verify_extracted {
+        mtree.iter().map(|e| (e.path.as_str(), e)).collect()
+    } else {
+        HashMap::new()
+    };
+    let hardlinks = if args.no_resolve_hardlinks {
+        &[]
+    } else {
+        mtree.as_slice()
+    };
+    let archive = Hardlinks::new(archive, hardlinks, matcher);
+    let archive = FirstChunk::new(archive, args.binary_threshold);
+
+    for content in archive {
+        if interrupt::interrupted() {
+            output.out().flush()?;
+            interrupt::check()?;
+        }
+
+        if let Err(e) = check_max_matches(args, stats.matched_before + found) {
+            output.out().flush()?;
+            return Err(e);
+        }
+
+        if let ArchiveContents::DataChunk(v) = &content {
+            stats.bytes_read += v.len() as u64;
+            if let Some(max) = args.max_total_size.filter(|&max| stats.bytes_read > max) {
+                output.out().flush()?;
+                bail!(
+                    "{} decompresses to more than {} bytes (--max-total-size)",
+                    manifest::pkg_name(pkg),
+                    max
+                );
+            }
+        }
+        if let ArchiveContents::StartOfEntry(_) = &content {
+            entries += 1;
+            if let Some(limit) = args.entry_limit.filter(|&limit| entries > limit) {
+                output.out().flush()?;
+                bail!(
+                    "{} has more than {} entries (--entry-limit)",
+                    manifest::pkg_name(pkg),
+                    limit
+                );
+            }
+        }
+
+        match content {
+            ArchiveContents::StartOfEntry(file) => {
+                let file = if args.normalize_paths {
+                    normalize_path(&file)
+                } else if args.foreign {
+                    file.trim_start_matches("./").to_string()
+                } else {
+                    file
+                };
+
+                // libarchive's names are turned into strings lossily so a name
+                // that wasn't UTF-8 could match or be extracted as another file
+                if file.contains(char::REPLACEMENT_CHARACTER) && !args.allow_nonutf8_names {
+                    warning!(
+                        "warning: {}: skipping '{}', its name is not valid UTF-8 (use --allow-nonutf8-names to read it)",
+                        manifest::pkg_name(pkg),
+                        file
+                    );
+                    continue;
+                }
+
+                // a followed link is printed when its target is read
+                if links.followed.contains(&file) {
+                    continue;
+                }
+                let file = links.targets.get(&file).cloned().unwrap_or(file);
+
+                if let Some(path) = matched_path(&file, matcher, noextract, args, filtered) {
+                    // two such names can be replaced with the same one, so one
+                    // would be written over the other
+                    if writes_files && file.contains(char::REPLACEMENT_CHARACTER) {
+                        bail!(
+                            "refusing to write '{}' from {}, its name is not valid UTF-8",
+                            file,
+                            manifest::pkg_name(pkg)
+                        );
+                    }
+                    cur_pattern = matcher.last;
+                    output.set_pattern(cur_pattern.unwrap_or(0));
+                    if !deferred {
+                        found += 1;
+                        stats.patterns.extend(cur_pattern);
+                    }
+
+                    if args.quiet && deferred {
+                        state = first_state(args);
+                        cur_file = path;
+                        output.start(&cur_file, None);
+                    } else if let Some(algo) = args
+                        .checksum
+                        .or_else(|| args.expect_hash.as_ref().map(|e| e.algo))
+                    {
+                        if path.ends_with('/') {
+                            continue;
+                        }
+
+                        state = EntryState::Reading;
+                        cur_file = path;
+                        hasher = Some(if args.parallel_hash {
+                            hash::Hasher::pooled(algo)
+                        } else {
+                            hash::Hasher::new(algo)
+                        });
+                    } else if args.split_output.is_some() || args.output_template.is_some() {
+                        if path.ends_with('/') {
+                            continue;
+                        }
+
+                        let (dest, split_file) = match &args.output_template {
+                            Some(template) => template_output_file(template, pkg, &path)?,
+                            None => {
+                                split_output_file(args.split_output.as_ref().unwrap(), pkg, &path)?
+                            }
+                        };
+                        writeln!(output.out(), "{}", dest.display())?;
+                        sum = sum_entry(args, &dest.to_string_lossy());
+                        restore = preserve_mode(&modes, &file, &dest, &split_file)?;
+                        state = first_state(args);
+                        cur_file = file;
+                        output.start(&cur_file, Some(split_file));
+                    } else if args.quiet || args.extract {
+                        writeln!(output.out(), "{}", output::quiet_name(args, &path))?;
+
+                        if args.extract {
+                            state = first_state(args);
+                            cur_file = file;
+
+                            let filename = if args.strip_components.is_some()
+                                || !args.rewrite.is_empty()
+                            {
+                                if escapes_cwd(&path) {
+                                    bail!(
+                                        "refusing to extract {} outside of the current directory",
+                                        path
+                                    );
+                                }
+                                if path.ends_with('/') {
+                                    fs::create_dir_all(&path)
+                                        .with_context(|| format!("failed to create {}", path))?;
+                                    state = EntryState::Skip;
+                                    continue;
+                                }
+                                if let Some(parent) = Path::new(&path).parent() {
+                                    fs::create_dir_all(parent).with_context(|| {
+                                        format!("failed to create {}", parent.display())
+                                    })?;
+                                }
+                                path.as_str()
+                            } else {
+                                cur_file.rsplit('/').next().unwrap()
+                            };
+
+                            sum = sum_entry(args, filename);
+                            if args.verify_extracted {
+                                verify = Some(filename.to_string());
+                            }
+                            let extract_file = OpenOptions::new()
+                                .write(true)
+                                .create(true)
+                                .truncate(true)
+                                .open(filename)
+                                .with_context(|| format!("failed to open target {}", filename))?;
+                            restore = preserve_mode(
+                                &modes,
+                                &cur_file,
+                                Path::new(filename),
+                                &extract_file,
+                            )?;
+                            output.start(&cur_file, Some(extract_file));
+                        }
+                    } else {
+                        if !path.ends_with('/') {
+                            sum = sum_entry(args, &path);
+                        }
+                        state = first_state(args);
+                        cur_file = file;
+                        output.start(&cur_file, None);
+                        if args.tar {
+                            let entry = mtree_entries.get(cur_file.trim_end_matches('/')).copied();
+                            output.set_tar_entry(&path, entry);
+                        }
+                    }
+                }
+            }
+            ArchiveContents::DataChunk(v) if state == EntryState::FirstChunk => {
+                if let Some(file_type) = args.is {
+                    if !magic::is_type(&v, file_type) {
+                        state = EntryState::Skip;
+                        continue;
+                    }
+
+                    if !output.buffered() {
+                        found += 1;
+                        stats.patterns.extend(cur_pattern);
+
+                        if args.quiet {
+                            writeln!(output.out(), "{}", output::quiet_name(args, &cur_file))?;
+                            state = EntryState::Skip;
+                            continue;
+                        }
+                    }
+                }
+
+                if !args.binary
+                    && !args.json_lines
+                    && !output::transforms_content(args)
+                    && has_nul(&v, args.binary_threshold)
+                {
+                    state = EntryState::Skip;
+                    output.skip_binary(&cur_file)?;
+                } else {
+                    state = EntryState::Reading;
+                    if let Some((_, hasher)) = &mut sum {
+                        hasher.update(&v);
+                    }
+                    output.write(&v)?;
+                }
+            }
+            ArchiveContents::DataChunk(v) if state == EntryState::Reading => match &mut hasher {
+                Some(hasher) => hasher.update(&v),
+                None => {
+                    if let Some((_, hasher)) = &mut sum {
+                        hasher.update(&v);
+                    }
+                    output.write(&v)?
+                }
+            },
+            ArchiveContents::DataChunk(_) => (),
+            ArchiveContents::EndOfEntry if hasher.is_some() && digests.is_some() => {
+                let digests = digests.as_mut().unwrap();
+                digests.submit(cur_file.clone(), hasher.take().unwrap());
+                print_digests(output, pkg, digests.ready(), stats, args)?;
+                state = EntryState::Skip;
+            }
+            ArchiveContents::EndOfEntry if hasher.is_some() => {
+                let sum = hasher.take().unwrap().finish();
+                match &args.expect_hash {
+                    Some(expect) if expect.hex == sum => {
+                        writeln!(output.out(), "{}: OK", cur_file)?
+                    }
+                    Some(expect) => {
+                        eprintln!(
+                            "{}: expected {} {} but it is {}",
+                            cur_file,
+                            expect.algo.name(),
+                            expect.hex,
+                            sum
+                        );
+                        stats.hash_mismatch = true;
+                    }
+                    None => print_checksum(output.out(), pkg, &cur_file, &sum, args)?,
+                }
+                state = EntryState::Skip;
+            }
+            ArchiveContents::EndOfEntry => {
+                let printed = state != EntryState::Skip && output.finish(&cur_file)?;
+                if printed && output.buffered() {
+                    found += 1;
+                    stats.patterns.extend(cur_pattern);
+                }
+                if let (Some((path, hasher)), true) = (sum.take(), printed) {
+                    match &mut digests {
+                        Some(digests) => {
+                            digests.submit(path, hasher);
+                            print_digests(output, pkg, digests.ready(), stats, args)?;
+                        }
+                        None => stats.sums.push(format!("{}  {}", hasher.finish(), path)),
+                    }
+                }
+                if let (Some(dest), true) = (verify.take(), printed) {
+                    let entry = mtree_entries.get(cur_file.as_str()).copied();
+                    stats.extract_mismatch |= !verify_extracted(&dest, entry)?;
+                }
+                if let Some((path, time)) = restore.take() {
+                    let time = TimeVal::seconds(time);
+                    utimes(&path, &time, &time)
+                        .with_context(|| format!("failed to set mtime of {}", path.display()))?;
+                }
+                state = EntryState::Skip;
+            }
+            ArchiveContents::Err(e) => {
+                return Err(e.into());
+            }
+        }
+    }
+
+    if let Some(digests) = digests {
+        print_digests(output, pkg, digests.finish(), stats, args)?;
+    }
+    if args.dump_order == DumpOrder::Pattern {
+        output.flush_sorted()?;
+    }
+    output.out().flush()?;
+    stats.found = found;
+    Ok(match_status(matcher, filtered, found))
+}
+
+/// Checks a file written by --extract against the sha256sum of its .MTREE
+/// entry for --verify-extracted. Entries without one are taken to be fine.
+fn verify_extracted(dest: &str, entry: Option<&mtree::Entry>) -> Result<bool> {
+    let expected = match entry.and_then(|e| e.sha256.as_ref()) {
+        Some(sha256) => sha256,
+        None => return Ok(true),
+    };
+
+    let sha256 = hash::sha256_file(Path::new(dest))?;
+    if &sha256 != expected {
+        eprintln!(
+            "error: {}: the extracted file has sha256sum {} but the .MTREE has {}",
+            dest, sha256, expected
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Starts hashing an entry for --sha256sums, which lists it as path.
+fn sum_entry(args: &Args, path: &str) -> Option<(String, hash::Hasher)> {
+    let hasher = if args.parallel_hash {
+        hash::Hasher::pooled(Checksum::Sha256)
+    } else {
+        hash::Hasher::new(Checksum::Sha256)
+    };
+    args.sha256sums.as_ref().map(|_| (path.to_string(), hasher))
+}
+
+/// Prints the digests of --parallel-hash for --checksum, or keeps them for
+/// --sha256sums, by the path they were hashed for.
+fn print_digests<W: Write>(
+    output: &mut Output<W>,
+    pkg: &str,
+    digests: Vec<(String, String)>,
+    stats: &mut Stats,
+    args: &Args,
+) -> Result<()> {
+    for (path, digest) in digests {
+        if args.checksum.is_some() {
+            print_checksum(output.out(), pkg, &path, &digest, args)?;
+        } else {
+            stats.sums.push(format!("{}  {}", digest, path));
+        }
+    }
+    Ok(())
+}
+
+fn check_max_matches(args: &Args, matched: usize) -> Result<()> {
+    if let Some(max) = args.max_matches.filter(|&max| matched > max) {
+        bail!(
+            "more than {} files matched (--max-matches), the patterns may be too broad",
+            max
+        );
+    }
+    Ok(())
+}
+
+/// The path to print for file if it is matched, after --strip-components.
+fn matched_path(
+    file: &str,
+    matcher: &mut Match,
+    noextract: &NoExtract,
+    args: &Args,
+    filtered: bool,
+) -> Option<String> {
+    let is_noextract = noextract.is_match(file);
+    let matched = if args.noextract_only && !is_noextract {
+        false
+    } else if args.files.is_empty()
+        && (args.is.is_some() || args.noextract_only || args.grep.is_some())
+    {
+        !file.ends_with('/') && (args.include_meta_members || !is_meta_member(file))
+    } else {
+        matcher.is_match(file, !args.all && !filtered)
+    };
+
+    if !matched {
+        return None;
+    }
+
+    if is_noextract && args.report_noextract {
+        warning!("{} is not installed because of NoExtract", file);
+    }
+
+    let path = strip_components(file, args.strip_components)?;
+    if matches!(args.max_depth, Some(d) if manifest::path_depth(path) > d) {
+        return None;
+    }
+
+    // a directory rewritten to nothing has nothing left to print
+    Some(rewrite_path(path, &args.rewrite)).filter(|p| !p.is_empty())
+}
+
+/// Tells which patterns matched no file in pkg for --strict-exit, returning
+/// 1 if there were any.
+fn strict_status(matcher: &Match, pkg: &str, stats: &Stats) -> i32 {
+    let mut ret = 0;
+    for (i, pattern) in matcher.patterns().iter().enumerate() {
+        if !stats.patterns.contains(&i) {
+            eprintln!(
+                "{}: no file matched '{}' (--strict-exit)",
+                manifest::pkg_name(pkg),
+                pattern
+            );
+            ret = 1;
+        }
+    }
+    ret
+}
+
+fn match_status(matcher: &Match, filtered: bool, found: usize) -> i32 {
+    match &matcher.with {
+        _ if filtered && found != 0 => 0,
+        MatchWith::Files(f) if f.is_empty() && !filtered => 0,
+        MatchWith::Regex(_) if found != 0 => 0,
+        _ => 1,
+    }
+}
+
+/// Whether --entries-from-mtree can be used, which is only when the file
+/// contents are not needed.
+fn from_mtree<W: Write>(args: &Args, output: &Output<W>) -> bool {
+    args.entries_from_mtree
+        && !args.foreign
+        && !output.reads_content()
+        && args.is.is_none()
+        && args.contains.is_none()
+        && (args.quiet || (args.count_bytes && args.binary))
+}
+
+/// Lists the matched entries from the package's .MTREE instead of reading
+/// the whole archive. Returns None if the package has no .MTREE.
+fn dump_mtree<W: Write>(
+    pkg: &str,
+    matcher: &mut Match,
+    noextract: &NoExtract,
+    args: &Args,
+    output: &mut Output<W>,
+    stats: &mut Stats,
+) -> Result<Option<i32>> {
+    let entries = match mtree::read_mtree(pkg)? {
+        Some(entries) => entries,
+        None => return Ok(None),
+    };
+    let mut found = 0;
+    let filtered = args.noextract_only;
+
+    for entry in entries {
+        let file = match entry.kind {
+            mtree::Kind::Dir => format!("{}/", entry.path),
+            _ => entry.path,
+        };
+
+        if (args.min_size.is_some() || args.max_size.is_some())
+            && (entry.kind != mtree::Kind::File || !output::in_size_range(args, entry.size))
+        {
+            continue;
+        }
+
+        if let Some(path) = matched_path(&file, matcher, noextract, args, filtered) {
+            found += 1;
+            stats.patterns.extend(matcher.last);
+            if !args.count_bytes {
+                writeln!(output.out(), "{}", output::quiet_name(args, &path))?;
+            } else if entry.kind == mtree::Kind::File {
+                output.count(entry.size);
+            }
+        }
+    }
+
+    output.out().flush()?;
+    stats.found = found;
+    Ok(Some(match_status(matcher, filtered, found)))
+}
+
+#[derive(Default)]
+struct Links {
+    /// The link each target is printed as.
+    targets: HashMap<String, String>,
+    /// The matched links that point at a file in the package.
+    followed: HashSet<String>,
+}
+
+/// Finds the files that the matched symlinks in pkg point to for
+/// --follow-links from its .MTREE.
+fn follow_links(pkg: &str, matcher: &mut Match) -> Result<Links> {
+    let entries = match mtree::read_mtree(pkg)? {
+        Some(entries) => entries,
+        None => bail!("{} does not contain a .MTREE to follow links with", pkg),
+    };
+    let entries = entries
+        .into_iter()
+        .map(|e| (e.path.clone(), e))
+        .collect::<HashMap<_, _>>();
+    let mut links = Links::default();
+
+    for entry in entries.values() {
+        if entry.kind != mtree::Kind::Link || !matcher.is_match(&entry.path, false) {
+            continue;
+        }
+
+        let mut path = entry.path.clone();
+        let mut hops = 0;
+        let target = loop {
+            match entries.get(&path) {
+                Some(e) if e.kind == mtree::Kind::Link && hops < 40 => {
+                    path = resolve_link(&path, e.link.as_deref().unwrap_or_default());
+                    hops += 1;
+                }
+                Some(e) if e.kind == mtree::Kind::File => break Some(path),
+                Some(e) if e.kind == mtree::Kind::Link => {
+                    warning!("{} is a symlink loop -- not following", entry.path);
+                    break None;
+                }
+                _ => {
+                    warning!(
+                        "{} points outside of {} -- not following",
+                        entry.path,
+                        manifest::pkg_name(pkg)
+                    );
+                    break None;
+                }
+            }
+        };
+
+        if let Some(target) = target {
+            links.followed.insert(entry.path.clone());
+            links.targets.insert(target, entry.path.clone());
+        }
+    }
+
+    Ok(links)
+}
+
+/// The path in the package that link points to when it is at path.
+fn resolve_link(path: &str, link: &str) -> String {
+    match (link.starts_with('/'), path.rsplit_once('/')) {
+        (false, Some((dir, _))) => normalize_path(&format!("{}/{}", dir, link)),
+        _ => normalize_path(link),
+    }
+}
+
+/// Resolves the `.` and `..` components of path and collapses repeated
+/// slashes for --normalize-paths. `..` can not go above the root of the
+/// package. A trailing slash is kept.
+fn normalize_path(path: &str) -> String {
+    let mut parts = Vec::new();
+
+    for part in path.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+
+    let mut normalized = parts.join("/");
+    if path.ends_with('/') && !normalized.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
+
+/// The mode and mtime of every file in the .MTREE of pkg for
+/// --preserve-permissions.
+fn mtree_modes(pkg: &str) -> Result<HashMap<String, (u32, i64)>> {
+    let entries = match mtree::read_mtree(pkg)? {
+        Some(entries) => entries,
+        None => bail!("{} does not contain a .MTREE to read permissions from", pkg),
+    };
+
+    Ok(entries
+        .into_iter()
+        .map(|e| (e.path, (e.mode, e.time)))
+        .collect())
+}
+
+/// Gives extracted the mode file has in the package. Returns where to set
+/// the mtime once the file has been written.
+fn preserve_mode(
+    modes: &HashMap<String, (u32, i64)>,
+    file: &str,
+    dest: &Path,
+    extracted: &File,
+) -> Result<Option<(PathBuf, i64)>> {
+    let (mode, time) = match modes.get(file) {
+        Some(&entry) => entry,
+        None => return Ok(None),
+    };
+
+    extracted
+        .set_permissions(fs::Permissions::from_mode(mode & 0o7777))
+        .with_context(|| format!("failed to set mode of {}", dest.display()))?;
+    Ok(Some((dest.to_path_buf(), time)))
+}
+
+/// Creates `<dir>/<pkg>__<path>` with the path flattened, appending a counter
+/// to the name if it already exists.
+fn split_output_file(dir: &str, pkg: &str, path: &str) -> Result<(PathBuf, File)> {
+    let pkg = manifest::pkg_name(pkg);
+    let pkg = pkg.split(".pkg.tar").next().unwrap();
+    let name = format!(
+        "{}__{}",
+        pkg,
+        path.trim_start_matches('/').replace('/', "_")
+    );
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir))?;
+    create_unique(&Path::new(dir).join(name))
+}
+
+/// Expands --output-template for path and creates the file, along with the
+/// directories it is in. The placeholders are {pkg}, {path}, {dir} and
+/// {basename} and the result can't have a `..` component.
+fn template_output_file(template: &str, pkg: &str, path: &str) -> Result<(PathBuf, File)> {
+    let pkg = manifest::pkg_name(pkg);
+    let pkg = pkg.split(".pkg.tar").next().unwrap();
+    let path = path.trim_start_matches('/');
+    let (dir, basename) = match path.rsplit_once('/') {
+        Some((dir, basename)) => (dir, basename),
+        None => ("", path),
+    };
+    let dest = template
+        .replace("{pkg}", pkg)
+        .replace("{path}", path)
+        .replace("{dir}", dir)
+        .replace("{basename}", basename);
+
+    if dest.split('/').any(|c| c == "..") {
+        bail!(
+            "refusing to write {} to {}, which is outside of the template",
+            path,
+            dest
+        );
+    }
+
+    let dest = PathBuf::from(dest);
+    if let Some(parent) = dest.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    create_unique(&dest)
+}
+
+/// Creates dest, adding .1, .2 and so on to its name if it already exists.
+fn create_unique(dest: &Path) -> Result<(PathBuf, File)> {
+    for i in 0.. {
+        let dest = match i {
+            0 => dest.to_path_buf(),
+            i => {
+                let mut name = dest.as_os_str().to_os_string();
+                name.push(format!(".{}", i));
+                PathBuf::from(name)
+            }
+        };
+
+        match OpenOptions::new().write(true).create_new(true).open(&dest) {
+            Ok(file) => return Ok((dest, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to open target {}", dest.display()))
+            }
+        }
+    }
+
+    unreachable!()
+}
+
+/// Whether path could be written outside of the current directory by
+/// --extract, by being absolute or having a `..` or an empty component.
+fn escapes_cwd(path: &str) -> bool {
+    Path::new(path).is_absolute()
+        || path
+            .trim_end_matches('/')
+            .split('/')
+            .any(|c| c.is_empty() || c == "..")
+}
+
+fn strip_components(path: &str, n: Option<usize>) -> Option<&str> {
+    let n = match n {
+        Some(n) => n,
+        None => return Some(path),
+    };
+
+    path.splitn(n + 1, '/').nth(n).filter(|p| !p.is_empty())
+}
+
+/// Applies each --rewrite in order to the leading directories of path.
+fn rewrite_path(path: &str, rules: &[Rewrite]) -> String {
+    let mut path = path.to_string();
+
+    for rule in rules {
+        let rest = match path.strip_prefix(&rule.from) {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+            _ => continue,
+        };
+        path = if rule.to.is_empty() {
+            rest.trim_start_matches('/').to_string()
+        } else {
+            format!("{}{}", rule.to, rest)
+        };
+    }
+
+    path
+}
+
+/// The state an entry starts in. --raw skips the checks on the first chunk
+/// so the bytes are passed through untouched.
+fn first_state(args: &Args) -> EntryState {
+    if args.raw && args.is.is_none() {
+        EntryState::Reading
+    } else {
+        EntryState::FirstChunk
+    }
+}
+
+/// How many bytes is_binary looks at.
+pub const SNIFF_BYTES: usize = 512;
+
+pub fn is_binary(data: &[u8]) -> bool {
+    has_nul(data, SNIFF_BYTES)
+}
+
+/// Whether there is a NUL in the first n bytes of data.
+fn has_nul(data: &[u8], n: usize) -> bool {
+    data.iter().take(n).any(|&b| b == 0)
+}
+
+fn fetch_targets<'a, D: PkgDb<'a>>(
+    db: Option<&'a D>,
+    args: &Args,
+    targets: Targets,
+) -> Result<Vec<String>> {
+    let fetched = fetch_sources(db, args, targets)?;
+    Ok(fetched.into_iter().map(|(file, _)| file).collect())
+}
+
+/// Like fetch_targets, also giving the url each downloaded file was
+/// resolved to, as redirects can save it under another name.
+fn fetch_sources<'a, D: PkgDb<'a>>(
+    db: Option<&'a D>,
+    args: &Args,
+    targets: Targets,
+) -> Result<Vec<(String, Option<String>)>> {
+    let Targets {
+        mut files,
+        download,
+        ..
+    } = targets;
+
+    let (local, download): (Vec<_>, Vec<_>) = download
+        .into_iter()
+        .partition(|urls| file_url_path(&urls[0]).is_some());
+    files.extend(local.iter().filter_map(|urls| file_url_path(&urls[0])));
+
+    let start = Instant::now();
+    let count = download.len();
+    let mut cachedirs = match (db, &args.download_dir) {
+        (_, Some(dir)) => vec![dir.clone()],
+        (Some(db), None) => db.cachedirs(),
+        (None, None) => vec![pacman::cachedir(args).to_string_lossy().into_owned()],
+    };
+    // pacman's cache dirs are often only writable by root so curl falls back
+    // to paccat's own
+    let uses_curl = db.is_none() || download::needs_curl(args);
+    let dir = match cachedirs
+        .iter()
+        .find(|dir| !uses_curl || args.download_dir.is_some() || download::writable(dir))
+    {
+        Some(dir) => dir.clone(),
+        None => pacman::cachedir(args).to_string_lossy().into_owned(),
+    };
+    if !cachedirs.contains(&dir) {
+        cachedirs.push(dir.clone());
+    }
+    let cachedirs = cachedirs.iter().map(String::as_str);
+    let _lock = if !args.no_lock && !download.is_empty() {
+        download::lock(&dir)?
+    } else {
+        None
+    };
+    let partial = interrupt::partial_files(cachedirs.clone());
+    let before = if args.cleanup {
+        interrupt::cache_files(cachedirs.clone())
+            .into_iter()
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    let downloaded = match db {
+        _ if download.is_empty() => Ok(Vec::new()),
+        Some(db) if !download::needs_curl(args) => {
+            download::with_failover(&download, args, |urls| db.fetch(urls))
+        }
+        _ => download::with_failover(&download, args, |urls| download::curl(&urls, &dir, args)),
+    };
+    // only what was downloaded here, other files may be new from a pacman
+    // running at the same time
+    if let (true, Ok(downloaded)) = (args.cleanup, &downloaded) {
+        downloaded
+            .iter()
+            .map(PathBuf::from)
+            .filter(|file| !before.contains(file))
+            .for_each(download::remove_on_exit);
+    }
+    if interrupt::interrupted() {
+        interrupt::remove_new_partial_files(cachedirs, &partial);
+        interrupt::check()?;
+    }
+    let downloaded = downloaded?;
+    if args.verbose && count != 0 {
+        eprintln!("fetched {} packages in {:.2?}", count, start.elapsed());
+    }
+    if args.require_signature {
+        signature::check(&[files.as_slice(), &downloaded].concat(), args)?;
+    }
+
+    let mut fetched = files
+        .into_iter()
+        .map(|file| (file, None))
+        .collect::<Vec<_>>();
+    if downloaded.len() == download.len() {
+        let urls = download.into_iter().map(|urls| urls.into_iter().next());
+        fetched.extend(downloaded.into_iter().zip(urls));
+    } else {
+        fetched.extend(downloaded.into_iter().map(|file| (file, None)));
+    }
+    Ok(fetched)
+}
+
+struct Targets {
+    files: Vec<String>,
+    /// The urls of each package to download, one for each server.
+    download: Vec<Vec<String>>,
+    /// The files of the installed version of each package for
+    /// --only-new-files, by package file name.
+    old_files: HashMap<String, HashSet<String>>,
+    /// The sha256sum the database has for each package for --verify-only,
+    /// by package file name.
+    sha256: HashMap<String, String>,
+    disk: Vec<(String, Vec<String>)>,
+    resolved: Vec<Resolution>,
+}
+
+impl Targets {
+    fn from_files(files: Vec<String>) -> Targets {
+        Targets {
+            files,
+            download: Vec::new(),
+            old_files: HashMap::new(),
+            sha256: HashMap::new(),
+            disk: Vec::new(),
+            resolved: Vec::new(),
+        }
+    }
+
+    /// Splits the targets so each package can be fetched on its own.
+    fn into_batches(self) -> Vec<Targets> {
+        let files = self
+            .files
+            .into_iter()
+            .map(|file| Targets::from_files(vec![file]));
+        let download = self.download.into_iter().map(|urls| Targets {
+            files: Vec::new(),
+            download: vec![urls],
+            old_files: HashMap::new(),
+            sha256: HashMap::new(),
+            disk: Vec::new(),
+            resolved: Vec::new(),
+        });
+        files.chain(download).collect()
+    }
+}
+
+struct Resolution {
+    target: String,
+    kind: &'static str,
+    package: Option<(String, String)>,
+    repo: Option<String>,
+    url: Option<String>,
+    cached: bool,
+}
+
+/// Prints how many files matched in each package of the file lists for
+/// --summary-only with -F or -Q, without downloading anything.
+fn print_db_summary<'a, D: PkgDb<'a>>(db: &'a D, args: &Args, matcher: &mut Match) -> Result<i32> {
+    let pkgs = if args.localdb {
+        db.local_pkgs()
+    } else {
+        db.sync_pkgs()
+    };
+    let mut spinner = Spinner::new(args, pkgs.len());
+    let mut rows = Vec::new();
+
+    for pkg in pkgs {
+        spinner.tick();
+        let mut matched = 0;
+        db.any_file(pkg, &mut |file| {
+            if !file.ends_with('/') && (args.files.is_empty() || matcher.is_match(file, false)) {
+                matched += 1;
+            }
+            false
+        });
+        if matched != 0 {
+            let name = db.name_version(pkg).0;
+            let name = match db.db_name(pkg) {
+                Some(repo) if !args.localdb => format!("{}/{}", repo, name),
+                _ => name,
+            };
+            rows.push((name, matched));
+        }
+    }
+    drop(spinner);
+
+    let stdout = io::stdout();
+    Ok(summary::Summary::print_table(
+        &mut stdout.lock(),
+        &rows,
+        args.json,
+    )?)
+}
+
+/// Prints the files that are in more than one package for
+/// --list-duplicates.
+fn print_duplicates<'a, D: PkgDb<'a>>(db: &'a D, args: &Args, matcher: &mut Match) -> Result<i32> {
+    let pkgs = if args.localdb {
+        db.local_pkgs()
+    } else {
+        db.sync_pkgs()
+    };
+    let mut owners = HashMap::<String, Vec<String>>::new();
+
+    for pkg in pkgs {
+        let name = db.name_version(pkg).0;
+        db.any_file(pkg, &mut |file| {
+            if !file.ends_with('/') && (args.files.is_empty() || matcher.is_match(file, false)) {
+                let owners = owners.entry(file.to_string()).or_default();
+                // the same package can be in more than one repo
+                if !owners.contains(&name) {
+                    owners.push(name.clone());
+                }
+            }
+            false
+        });
+    }
+
+    let mut dups = owners
+        .into_iter()
+        .filter(|(_, pkgs)| pkgs.len() > 1)
+        .collect::<Vec<_>>();
+    dups.sort();
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    if args.json {
+        writeln!(stdout, "[")?;
+        for (i, (path, pkgs)) in dups.iter().enumerate() {
+            let pkgs = pkgs.iter().map(|p| json::string(p)).collect::<Vec<_>>();
+            let sep = if i + 1 == dups.len() { "" } else { "," };
+            writeln!(
+                stdout,
+                "  {{\"path\": {}, \"packages\": [{}]}}{}",
+                json::string(path),
+                pkgs.join(", "),
+                sep
+            )?;
+        }
+        writeln!(stdout, "]")?;
+    } else {
+        for (path, pkgs) in &dups {
+            writeln!(stdout, "{}\t{}", path, pkgs.join(" "))?;
+        }
+    }
+
+    Ok(if dups.is_empty() { 1 } else { 0 })
+}
+
+fn print_repos<'a, D: PkgDb<'a>>(db: &'a D, args: &Args) -> Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let repos = db.repos();
+
+    if args.json {
+        writeln!(stdout, "[")?;
+        for (i, repo) in repos.iter().enumerate() {
+            let servers = db
+                .servers(repo)
+                .iter()
+                .map(|s| json::string(s))
+                .collect::<Vec<_>>();
+            let sep = if i + 1 == repos.len() { "" } else { "," };
+            writeln!(
+                stdout,
+                "  {{\"name\": {}, \"servers\": [{}]}}{}",
+                json::string(repo),
+                servers.join(", "),
+                sep
+            )?;
+        }
+        writeln!(stdout, "]")?;
+    } else {
+        for repo in repos {
+            writeln!(stdout, "{}", repo)?;
+            for server in db.servers(repo) {
+                writeln!(stdout, "    {}", server)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints how old the database and the files database of each repo are for
+/// --db-info, going by when they were last written.
+fn print_db_info<'a, D: PkgDb<'a>>(db: &'a D, args: &Args) -> Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let dbpath = db.dbpath().unwrap_or_default();
+    let repos = db.repos();
+    let now = SystemTime::now();
+    let modified = |repo: &str, ext: &str| {
+        let path = Path::new(&dbpath)
+            .join("sync")
+            .join(format!("{}.{}", repo, ext));
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    };
+
+    if args.json {
+        writeln!(stdout, "[")?;
+    }
+    for (i, repo) in repos.iter().enumerate() {
+        let (db_time, files_time) = (modified(repo, "db"), modified(repo, "files"));
+        if args.json {
+            let epoch = |time: Option<SystemTime>| {
+                time.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map_or("null".to_string(), |d| d.as_secs().to_string())
+            };
+            let sep = if i + 1 == repos.len() { "" } else { "," };
+            writeln!(
+                stdout,
+                "  {{\"name\": {}, \"db_mtime\": {}, \"files_mtime\": {}}}{}",
+                json::string(repo),
+                epoch(db_time),
+                epoch(files_time),
+                sep
+            )?;
+        } else {
+            let status = |time: Option<SystemTime>| match time {
+                Some(time) => format!("updated {} ago", age(now, time)),
+                None => "missing".to_string(),
+            };
+            writeln!(
+                stdout,
+                "{}: db {}, files {}",
+                repo,
+                status(db_time),
+                status(files_time)
+            )?;
+        }
+    }
+    if args.json {
+        writeln!(stdout, "]")?;
+    }
+
+    Ok(())
+}
+
+/// How long ago time was, in the biggest unit that fits.
+fn age(now: SystemTime, time: SystemTime) -> String {
+    let secs = now.duration_since(time).map_or(0, |d| d.as_secs());
+    let (n, unit) = match secs {
+        s if s >= 86400 => (s / 86400, "day"),
+        s if s >= 3600 => (s / 3600, "hour"),
+        s => (s / 60, "minute"),
+    };
+    format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" })
+}
+
+/// The `repo/name version` of each resolved package for --context-package,
+/// by package file name, or by name for packages read from disk.
+fn package_context(resolved: &[Resolution]) -> HashMap<String, String> {
+    resolved
+        .iter()
+        .filter_map(|res| {
+            let (name, version) = res.package.as_ref()?;
+            let key = match &res.url {
+                Some(url) => manifest::pkg_name(url).to_string(),
+                None => name.clone(),
+            };
+            let repo = res.repo.as_deref().unwrap_or("local");
+            Some((key, format!("{}/{} {}", repo, name, version)))
+        })
+        .collect()
+}
+
+/// Prints how each target was resolved and where it would be downloaded
+/// from for --trace-resolve.
+fn print_trace(targets: &Targets) -> Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    for res in &targets.resolved {
+        writeln!(stdout, "{}", res.target)?;
+        writeln!(stdout, "  resolved as: {}", res.kind)?;
+        if let Some(repo) = &res.repo {
+            writeln!(stdout, "  repo: {}", repo)?;
+        }
+        if let Some((name, version)) = &res.package {
+            writeln!(stdout, "  package: {} {}", name, version)?;
+        }
+
+        let urls = targets
+            .download
+            .iter()
+            .find(|urls| Some(&urls[0]) == res.url.as_ref());
+        if let Some(urls) = urls.filter(|_| res.package.is_some()) {
+            writeln!(stdout, "  servers:")?;
+            for url in urls {
+                writeln!(stdout, "    {}", url)?;
+            }
+        }
+
+        match &res.url {
+            Some(url) if res.cached => writeln!(stdout, "  url: {} (cached)", url)?,
+            Some(url) => writeln!(stdout, "  url: {}", url)?,
+            None if res.kind == "disk" => writeln!(stdout, "  read from disk")?,
+            None => writeln!(stdout, "  file: {}", res.target)?,
+        }
+    }
+
+    Ok(())
+}
+
+fn print_resolutions(resolved: &[Resolution]) -> Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    writeln!(stdout, "[")?;
+    for (i, res) in resolved.iter().enumerate() {
+        let (name, version) = match &res.package {
+            Some((name, version)) => (json::string(name), json::string(version)),
+            None => ("null".to_string(), "null".to_string()),
+        };
+        let url = res.url.as_deref().map_or("null".to_string(), json::string);
+        let repo = res.repo.as_deref().map_or("null".to_string(), json::string);
+        let sep = if i + 1 == resolved.len() { "" } else { "," };
+
+        writeln!(
+            stdout,
+            "  {{\"target\": {}, \"kind\": \"{}\", \"package\": {}, \"version\": {}, \"repo\": {}, \"url\": {}, \"cached\": {}}}{}",
+            json::string(&res.target),
+            res.kind,
+            name,
+            version,
+            repo,
+            url,
+            res.cached,
+            sep
+        )?;
+    }
+    writeln!(stdout, "]")?;
+
+    Ok(())
+}
+
+fn resolve_targets<'a, D: PkgDb<'a>>(
+    db: &'a D,
+    args: &Args,
+    matcher: &mut Match,
+) -> Result<Targets> {
+    let mut download = Vec::new();
+    let mut repo = Vec::new();
+    let mut files = Vec::new();
+    let mut disk = Vec::new();
+    let mut resolved = Vec::new();
+    let mut roots = Vec::new();
+    let mut old_files = HashMap::new();
+    let mut sha256 = HashMap::new();
+
+    if args.targets.is_empty() {
+        if args.localdb {
+            let local_pkgs = db.local_pkgs();
+            let mut spinner = Spinner::new(args, local_pkgs.len());
+            let pkgs = local_pkgs
+                .into_iter()
+                .inspect(|_| spinner.tick())
+                .filter(|pkg| want_pkg(db, *pkg, matcher))
+                .filter_map(|p| {
+                    if args.no_sync_db {
+                        Some(p)
+                    } else {
+                        db.sync_pkg(p)
+                    }
+                })
+                .filter(|p| !args.updated || is_updated(db, *p))
+                .map(|p| (db.name_version(p).0, "db", p));
+            repo.extend(pkgs);
+        } else if args.filedb {
+            let sync_pkgs = db.sync_pkgs();
+            let mut spinner = Spinner::new(args, sync_pkgs.len());
+            let pkgs = sync_pkgs
+                .into_iter()
+                .inspect(|_| spinner.tick())
+                .filter(|p| !args.updated || is_updated(db, *p))
+                .filter(|pkg| want_pkg(db, *pkg, matcher))
+                .map(|p| (db.name_version(p).0, "db", p));
+            repo.extend(pkgs);
+        }
+    } else {
+        for target in &args.targets {
+            let (kind, targ) = target_kind(target);
+            let any = kind.is_none();
+
+            if let Some(name) = targ.strip_suffix('/').filter(|r| any && !r.contains('/')) {
+                if !db.repos().contains(&name) {
+                    return Err(PaccatError::resolve(target, "is not a repo").into());
+                }
+                let pkgs = db
+                    .sync_pkgs()
+                    .into_iter()
+                    .filter(|&pkg| db.db_name(pkg) == Some(name))
+                    .filter(|&pkg| {
+                        args.files.is_empty() || !db.has_files(pkg) || want_pkg(db, pkg, matcher)
+                    })
+                    .map(|pkg| (db.name_version(pkg).0, "repo", pkg))
+                    .collect::<Vec<_>>();
+                if args.verbose {
+                    eprintln!("{} matched {} packages", target, pkgs.len());
+                }
+                repo.extend(pkgs);
+            } else if let Some((kind, pkg)) = match kind {
+                None | Some(TargetKind::Pkg) => find_pkg(db, args, targ)?,
+                _ => None,
+            } {
+                roots.push((target.clone(), pkg));
+                if args.files.is_empty() || !db.has_files(pkg) || want_pkg(db, pkg, matcher) {
+                    repo.push((target.clone(), kind, pkg));
+                }
+            } else if matches!(kind, None | Some(TargetKind::File)) && Path::new(targ).exists() {
+                files.push(targ.to_string());
+                resolved.push(Resolution {
+                    target: target.clone(),
+                    kind: "file",
+                    package: None,
+                    repo: None,
+                    url: None,
+                    cached: false,
+                });
+            } else if kind == Some(TargetKind::Url) || (any && targ.contains("://")) {
+                download.push(vec![targ.to_string()]);
+                resolved.push(Resolution {
+                    target: target.clone(),
+                    kind: "url",
+                    package: None,
+                    repo: None,
+                    url: Some(targ.to_string()),
+                    cached: false,
+                });
+            } else if kind == Some(TargetKind::File) {
+                return Err(PaccatError::resolve(target, "is not a file").into());
+            } else if glob::is_glob(targ) {
+                let pkgs = glob_pkgs(db, targ)?;
+                if pkgs.is_empty() {
+                    return Err(PaccatError::resolve(target, "did not match any packages").into());
+                }
+                if args.verbose {
+                    eprintln!("{} matched {} packages", target, pkgs.len());
+                }
+                for pkg in pkgs {
+                    roots.push((target.clone(), pkg));
+                    if args.files.is_empty() || !db.has_files(pkg) || want_pkg(db, pkg, matcher) {
+                        repo.push((target.clone(), "glob", pkg));
+                    }
+                }
+            } else {
+                let names = db
+                    .sync_pkgs()
+                    .into_iter()
+                    .map(|pkg| db.name_version(pkg).0)
+                    .collect::<Vec<_>>();
+                let name = split_target(targ).1;
+                let what = if any {
+                    "a package, file or url"
+                } else {
+                    "a package"
+                };
+                let reason = match suggest::closest(name, names.iter().map(String::as_str)) {
+                    Some(close) => format!("is not {}, did you mean '{}'?", what, close),
+                    None => format!("is not {}", what),
+                };
+                return Err(PaccatError::resolve(target, reason).into());
+            }
+        }
+    }
+
+    if args.deps {
+        let deps = dep_closure(db, args, &roots, &repo);
+        if args.verbose {
+            eprintln!("added {} dependencies", deps.len());
+        }
+        for (target, pkg) in deps {
+            if args.files.is_empty() || !db.has_files(pkg) || want_pkg(db, pkg, matcher) {
+                repo.push((target, "dep", pkg));
+            }
+        }
+    }
+
+    if args.split_siblings {
+        let siblings = split_siblings(db, args, &roots, &repo);
+        for (target, pkg) in siblings {
+            if args.files.is_empty() || !db.has_files(pkg) || want_pkg(db, pkg, matcher) {
+                repo.push((target, "sibling", pkg));
+            }
+        }
+    }
+
+    if args.required_by {
+        let rdeps = required_by(db, &roots, &repo, args);
+        if args.verbose {
+            eprintln!("added {} packages that depend on the targets", rdeps.len());
+        }
+        for (target, pkg) in rdeps {
+            if args.files.is_empty() || !db.has_files(pkg) || want_pkg(db, pkg, matcher) {
+                repo.push((target, "required-by", pkg));
+            }
+        }
+    }
+
+    // todo filter repopkg files
+
+    if !args.exclude_pkg.is_empty() {
+        let exclude = args
+            .exclude_pkg
+            .iter()
+            .map(|name| glob::compile(name))
+            .collect::<Result<Vec<_>>>()?;
+        let before = repo.len();
+        repo.retain(|&(_, _, pkg)| {
+            let name = db.name_version(pkg).0;
+            !exclude.iter().any(|e| e.is_match(&name))
+        });
+        if args.verbose {
+            eprintln!("excluded {} packages", before - repo.len());
+        }
+    }
+
+    for (target, kind, pkg) in repo {
+        if args.no_sync_db {
+            disk.push((
+                db.name_version(pkg).0,
+                present_files(db, pkg, args, matcher),
+            ));
+            resolved.push(Resolution {
+                target,
+                kind: "disk",
+                package: Some(db.name_version(pkg)),
+                repo: None,
+                url: None,
+                cached: false,
+            });
+            continue;
+        }
+
+        if args.from_disk {
+            if let Some(files) = installed_files(db, pkg, args, matcher) {
+                disk.push((db.name_version(pkg).0, files));
+                resolved.push(Resolution {
+                    target,
+                    kind: "disk",
+                    package: Some(db.name_version(pkg)),
+                    repo: db.db_name(pkg).map(String::from),
+                    url: None,
+                    cached: false,
+                });
+                continue;
+            }
+        }
+
+        if args.db_check && !db_check(db, args, pkg)? {
+            continue;
+        }
+
+        if args.prefer_cache {
+            let (name, version) = db.name_version(pkg);
+            let newest = cache::newest(&db.cachedirs(), &name, |a, b| db.vercmp(a, b));
+            if let Some(cached) = newest.filter(|cached| cached.version != version) {
+                warning!(
+                    "warning: {}: using {} from the cache instead of {} (--prefer-cache)",
+                    name,
+                    cached.version,
+                    version
+                );
+                files.push(cached.path.clone());
+                resolved.push(Resolution {
+                    target,
+                    kind,
+                    package: Some((name, cached.version)),
+                    repo: db.db_name(pkg).map(String::from),
+                    url: None,
+                    cached: true,
+                });
+                continue;
+            }
+        }
+
+        let urls = db.download_urls(pkg)?;
+        if let Some(sum) = db.sha256sum(pkg) {
+            sha256.insert(manifest::pkg_name(&urls[0]).to_string(), sum);
+        }
+        if args.only_new_files {
+            let files = installed_file_list(db, pkg, args);
+            old_files.insert(manifest::pkg_name(&urls[0]).to_string(), files);
+        }
+        resolved.push(Resolution {
+            target,
+            kind,
+            package: Some(db.name_version(pkg)),
+            repo: db.db_name(pkg).map(String::from),
+            url: Some(urls[0].clone()),
+            cached: !args.concurrent_target_resolution && db.is_cached(pkg),
+        });
+        download.push(urls);
+    }
+
+    if args.concurrent_target_resolution {
+        check_cached(&mut resolved, &db.cachedirs());
+    }
+
+    Ok(Targets {
+        files,
+        download,
+        old_files,
+        sha256,
+        disk,
+        resolved,
+    })
+}
+
+/// Checks which resolved packages are already in a cache dir for
+/// --concurrent-target-resolution, spread over a thread per cpu. libalpm
+/// can't be used from other threads so only the checks on disk are spread.
+fn check_cached(resolved: &mut [Resolution], cachedirs: &[String]) {
+    let threads = thread::available_parallelism().map_or(4, |n| n.get());
+    let chunk = resolved.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        for chunk in resolved.chunks_mut(chunk) {
+            scope.spawn(move || {
+                for res in chunk.iter_mut().filter(|res| res.package.is_some()) {
+                    if let Some(url) = &res.url {
+                        let file = manifest::pkg_name(url);
+                        res.cached = cachedirs
+                            .iter()
+                            .any(|dir| Path::new(dir).join(file).exists());
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// The files of the installed version of pkg, which are not matched with
+/// --only-new-files.
+fn installed_file_list<'a, D: PkgDb<'a>>(db: &'a D, pkg: D::Pkg, args: &Args) -> HashSet<String> {
+    let mut old = HashSet::new();
+    let local = match db.local_pkg(pkg) {
+        Some(local) => local,
+        None => return old,
+    };
+    db.any_file(local, &mut |file| {
+        old.insert(file.to_string());
+        false
+    });
+
+    if args.verbose && db.has_files(pkg) {
+        let mut added = 0;
+        let mut kept = 0;
+        db.any_file(pkg, &mut |file| {
+            if old.contains(file) {
+                kept += 1;
+            } else {
+                added += 1;
+            }
+            false
+        });
+        warning!(
+            "{}: {} files added, {} removed",
+            db.name_version(pkg).0,
+            added,
+            old.len() - kept
+        );
+    }
+
+    old
+}
+
+/// The matched files of pkg if the same version is installed and every one
+/// of them is under the root as it was installed, which the mtree in the
+/// local database tells.
+fn installed_files<'a, D: PkgDb<'a>>(
+    db: &'a D,
+    pkg: D::Pkg,
+    args: &Args,
+    matcher: &mut Match,
+) -> Option<Vec<String>> {
+    let local = db.local_pkg(pkg)?;
+    let installed = db.name_version(local);
+    if installed != db.name_version(pkg) {
+        return None;
+    }
+    let (name, version) = installed;
+    let sums = mtree::read_local_mtree(&db.dbpath()?, &name, &version)
+        .ok()
+        .flatten()?
+        .into_iter()
+        .filter_map(|e| Some((e.path, (e.size, e.sha256?))))
+        .collect::<HashMap<_, _>>();
+
+    let root = Path::new(args.root.as_deref().unwrap_or("/"));
+    let mut files = Vec::new();
+    let mut changed = None;
+    db.any_file(local, &mut |file| {
+        if !file.ends_with('/') && matcher.is_match(file, false) {
+            if changed.is_none() && !unmodified(&root.join(file), sums.get(file)) {
+                changed = Some(file.to_string());
+            }
+            files.push(file.to_string());
+        }
+        false
+    });
+
+    match changed {
+        Some(file) => {
+            if args.verbose {
+                eprintln!(
+                    "{}: {} is missing or changed, reading the package",
+                    name, file
+                );
+            }
+            None
+        }
+        None if files.is_empty() => None,
+        None => Some(files),
+    }
+}
+
+/// Whether the file at path has the size and sha256sum it was installed
+/// with.
+fn unmodified(path: &Path, installed: Option<&(u64, String)>) -> bool {
+    let (size, sha256) = match installed {
+        Some(installed) => installed,
+        None => return false,
+    };
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_file() && meta.len() == *size => {
+            matches!(hash::sha256_file(path), Ok(sum) if &sum == sha256)
+        }
+        _ => false,
+    }
+}
+
+/// The matched files of the installed pkg that are under the root for
+/// --no-sync-db. Missing files can't be downloaded instead so they are
+/// only reported.
+fn present_files<'a, D: PkgDb<'a>>(
+    db: &'a D,
+    pkg: D::Pkg,
+    args: &Args,
+    matcher: &mut Match,
+) -> Vec<String> {
+    let root = Path::new(args.root.as_deref().unwrap_or("/"));
+    let name = db.name_version(pkg).0;
+    let mut files = Vec::new();
+    db.any_file(pkg, &mut |file| {
+        if !file.ends_with('/') && matcher.is_match(file, false) {
+            if root.join(file).is_file() {
+                files.push(file.to_string());
+            } else {
+                warning!(
+                    "warning: {}: {} is not under {}",
+                    name,
+                    file,
+                    root.display()
+                );
+            }
+        }
+        false
+    });
+    files
+}
+
+/// Every package the roots depend on, directly or not, down to --deps-depth.
+/// Optional dependencies are followed too with --optdeps.
+/// Packages already in repo are left out.
+fn dep_closure<'a, D: PkgDb<'a>>(
+    db: &'a D,
+    args: &Args,
+    roots: &[(String, D::Pkg)],
+    repo: &[(String, &'static str, D::Pkg)],
+) -> Vec<(String, D::Pkg)> {
+    let mut seen = roots
+        .iter()
+        .map(|(_, pkg)| *pkg)
+        .chain(repo.iter().map(|(_, _, pkg)| *pkg))
+        .map(|pkg| db.name_version(pkg).0)
+        .collect::<HashSet<_>>();
+    let mut queue = roots
+        .iter()
+        .map(|(target, pkg)| (target.clone(), *pkg, 0))
+        .collect::<VecDeque<_>>();
+    let mut deps = Vec::new();
+
+    while let Some((target, pkg, depth)) = queue.pop_front() {
+        if matches!(args.deps_depth, Some(max) if depth >= max) {
+            continue;
+        }
+
+        let optdeps = if args.optdeps {
+            db.optdepends(pkg)
+        } else {
+            Vec::new()
+        };
+        let depends = db.depends(pkg).into_iter().map(|dep| (dep, false));
+
+        for (dep, optional) in depends.chain(optdeps.into_iter().map(|dep| (dep, true))) {
+            let dep_pkg = match db.find_pkg(&dep) {
+                Ok(dep_pkg) => dep_pkg,
+                Err(_) if optional => {
+                    let name = db.name_version(pkg).0;
+                    warning!(
+                        "note: optional dependency {} of {} is not in any database -- skipping",
+                        dep,
+                        name
+                    );
+                    continue;
+                }
+                Err(_) => {
+                    let name = db.name_version(pkg).0;
+                    warning!(
+                        "warning: dependency {} of {} is not in any database",
+                        dep,
+                        name
+                    );
+                    continue;
+                }
+            };
+            if seen.insert(db.name_version(dep_pkg).0) {
+                deps.push((target.clone(), dep_pkg));
+                queue.push_back((target.clone(), dep_pkg, depth + 1));
+            }
+        }
+    }
+
+    deps
+}
+
+/// Every package in the sync databases that depends on one of the roots or
+/// something they provide.
+fn required_by<'a, D: PkgDb<'a>>(
+    db: &'a D,
+    roots: &[(String, D::Pkg)],
+    repo: &[(String, &'static str, D::Pkg)],
+    args: &Args,
+) -> Vec<(String, D::Pkg)> {
+    let pkgs = db.sync_pkgs();
+    let mut spinner = Spinner::new(args, pkgs.len());
+    let mut required_by = HashMap::<String, Vec<D::Pkg>>::new();
+
+    for &pkg in &pkgs {
+        spinner.tick();
+        for dep in db.depends(pkg) {
+            let name = dep.split(['<', '>', '=']).next().unwrap();
+            required_by.entry(name.to_string()).or_default().push(pkg);
+        }
+    }
+    drop(spinner);
+
+    let mut seen = roots
+        .iter()
+        .map(|(_, pkg)| *pkg)
+        .chain(repo.iter().map(|(_, _, pkg)| *pkg))
+        .map(|pkg| db.name_version(pkg).0)
+        .collect::<HashSet<_>>();
+    let mut rdeps = Vec::new();
+
+    for (target, pkg) in roots {
+        let names = iter::once(db.name_version(*pkg).0).chain(db.provides(*pkg));
+        for name in names {
+            for &rdep in required_by.get(&name).into_iter().flatten() {
+                if seen.insert(db.name_version(rdep).0) {
+                    rdeps.push((target.clone(), rdep));
+                }
+            }
+        }
+    }
+
+    rdeps
+}
+
+/// The other packages built from the same pkgbase as one of the roots, from
+/// the same repo.
+fn split_siblings<'a, D: PkgDb<'a>>(
+    db: &'a D,
+    args: &Args,
+    roots: &[(String, D::Pkg)],
+    repo: &[(String, &'static str, D::Pkg)],
+) -> Vec<(String, D::Pkg)> {
+    let mut bases = HashMap::<(Option<&str>, String), Vec<D::Pkg>>::new();
+    for pkg in db.sync_pkgs() {
+        if let Some(base) = db.pkgbase(pkg) {
+            bases.entry((db.db_name(pkg), base)).or_default().push(pkg);
+        }
+    }
+
+    let mut seen = roots
+        .iter()
+        .map(|(_, pkg)| *pkg)
+        .chain(repo.iter().map(|(_, _, pkg)| *pkg))
+        .map(|pkg| db.name_version(pkg).0)
+        .collect::<HashSet<_>>();
+    let mut siblings = Vec::new();
+
+    for (target, pkg) in roots {
+        let base = match db.pkgbase(*pkg) {
+            Some(base) => base,
+            None => continue,
+        };
+        let mut added = Vec::new();
+        for &sibling in bases
+            .get(&(db.db_name(*pkg), base.clone()))
+            .into_iter()
+            .flatten()
+        {
+            let name = db.name_version(sibling).0;
+            if seen.insert(name.clone()) {
+                siblings.push((target.clone(), sibling));
+                added.push(name);
+            }
+        }
+        if args.verbose && !added.is_empty() {
+            eprintln!(
+                "{}: added {} from pkgbase {}",
+                target,
+                added.join(" "),
+                base
+            );
+        }
+    }
+
+    siblings
+}
+
+fn is_testing_repo(repo: &str) -> bool {
+    repo == "testing" || repo.ends_with("-testing")
+}
+
+/// Looks the target up in the sync databases, falling back to a case
+/// insensitive search of the package names with --fuzzy-names.
+fn find_pkg<'a, D: PkgDb<'a>>(
+    db: &'a D,
+    args: &Args,
+    targ: &str,
+) -> Result<Option<(&'static str, D::Pkg)>> {
+    let (repo, name) = split_target(targ);
+
+    if args.testing && repo.is_none() {
+        for testing in db.repos().into_iter().filter(|&r| is_testing_repo(r)) {
+            if let Ok(pkg) = db.find_pkg(&format!("{}/{}", testing, targ)) {
+                return Ok(Some(("testing", pkg)));
+            }
+        }
+    }
+
+    let kind = |pkg| {
+        if name == db.name_version(pkg).0 {
+            "db"
+        } else {
+            "provides"
+        }
+    };
+
+    if let (Some(repos), None) = (&args.repo_priority, repo) {
+        for repo in repos.split(',') {
+            if let Ok(pkg) = db.find_pkg(&format!("{}/{}", repo, targ)) {
+                return Ok(Some((kind(pkg), pkg)));
+            }
+        }
+    }
+
+    if let Ok(pkg) = db.find_pkg(targ) {
+        return Ok(Some((kind(pkg), pkg)));
+    }
+
+    if !args.fuzzy_names {
+        return Ok(None);
+    }
+
+    let mut seen = HashSet::new();
+    let pkgs = db
+        .sync_pkgs()
+        .into_iter()
+        .filter(|&pkg| repo.is_none() || db.db_name(pkg) == repo)
+        .filter(|&pkg| {
+            let pkgname = db.name_version(pkg).0;
+            pkgname.eq_ignore_ascii_case(name) && seen.insert(pkgname)
+        })
+        .collect::<Vec<_>>();
+
+    match pkgs.as_slice() {
+        [] => Ok(None),
+        [pkg] => {
+            warning!("using '{}' for '{}'", db.name_version(*pkg).0, targ);
+            Ok(Some(("fuzzy", *pkg)))
+        }
+        pkgs => {
+            let names = pkgs
+                .iter()
+                .map(|&pkg| db.name_version(pkg).0)
+                .collect::<Vec<_>>();
+            let reason = format!("matches several packages: {}", names.join(" "));
+            Err(PaccatError::resolve(targ, reason).into())
+        }
+    }
+}
+
+fn glob_pkgs<'a, D: PkgDb<'a>>(db: &'a D, targ: &str) -> Result<Vec<D::Pkg>> {
+    let (repo, pkg) = split_target(targ);
+    let glob = glob::compile(pkg)?;
+    let mut seen = HashSet::new();
+
+    let pkgs = db
+        .sync_pkgs()
+        .into_iter()
+        .filter(|&pkg| repo.is_none() || db.db_name(pkg) == repo)
+        .filter(|&pkg| {
+            let name = db.name_version(pkg).0;
+            glob.is_match(&name) && seen.insert(name)
+        })
+        .collect();
+    Ok(pkgs)
+}
+
+/// Whether the sync package is newer than the installed version.
+fn is_updated<'a, D: PkgDb<'a>>(db: &'a D, pkg: D::Pkg) -> bool {
+    let local = match db.local_pkg(pkg) {
+        Some(local) => local,
+        None => return false,
+    };
+
+    let (_, sync_ver) = db.name_version(pkg);
+    let (_, local_ver) = db.name_version(local);
+    db.vercmp(&sync_ver, &local_ver) == Ordering::Greater
+}
+
+/// Checks that each file given is in the file list of pkg for --db-check
+/// so the package isn't downloaded for nothing. Regex patterns are not
+/// checked.
+fn db_check<'a, D: PkgDb<'a>>(db: &'a D, args: &Args, pkg: D::Pkg) -> Result<bool> {
+    if args.regex || args.files.is_empty() {
+        return Ok(true);
+    }
+
+    let (name, _) = db.name_version(pkg);
+    if !db.has_files(pkg) {
+        warning!(
+            "warning: {} has no file list to check (use pacman -Fy to download the files databases)",
+            name
+        );
+        return Ok(true);
+    }
+
+    for file in &args.files {
+        let mut matcher = Match::new(
+            false,
+            vec![file.clone()],
+            exact_file(args),
+            args.usrmerge,
+            args.case_fold_paths || args.match_any_component_ci,
+        )?;
+        matcher.component = args.match_any_component_ci;
+        matcher.recursive_dir = args.recursive_dir;
+        if !db.any_file(pkg, &mut |f| matcher.is_match(f, false)) {
+            warning!(
+                "{}: skipping download, {} is not in its file list",
+                name,
+                file
+            );
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn want_pkg<'a, D: PkgDb<'a>>(db: &'a D, pkg: D::Pkg, matcher: &mut Match) -> bool {
+    if matches!(matcher.with, MatchWith::Files(ref f) if f.is_empty()) {
+        return false;
+    }
+    db.any_file(pkg, &mut |f| matcher.is_match(f, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pacman::tests::{pkg, MemDb, MemPkg};
+
+    fn test_db() -> MemDb {
+        MemDb {
+            pkgs: vec![
+                pkg(
+                    Some("core"),
+                    "coreutils",
+                    &["usr/", "usr/bin/", "usr/bin/ls"],
+                ),
+                MemPkg {
+                    provides: vec!["editor"],
+                    ..pkg(Some("extra"), "vim", &["usr/", "usr/bin/", "usr/bin/vim"])
+                },
+                pkg(Some("extra"), "nano", &["usr/", "usr/bin/", "usr/bin/nano"]),
+                pkg(None, "vim", &["usr/", "usr/bin/", "usr/bin/vim"]),
+            ],
+            ..MemDb::default()
+        }
+    }
+
+    fn resolve(db: &MemDb, argv: &[&str]) -> Result<Targets> {
+        let args = Args::parse_from(std::iter::once("paccat").chain(argv.iter().copied()));
+        let mut matcher = Match::new(
+            args.regex,
+            args.files.clone(),
+            exact_file(&args),
+            args.usrmerge,
+            false,
+        )?;
+        resolve_targets(db, &args, &mut matcher)
+    }
+
+    fn resolved_names(targets: &Targets) -> Vec<(&str, &str)> {
+        targets
+            .resolved
+            .iter()
+            .map(|r| (r.package.as_ref().unwrap().0.as_str(), r.kind))
+            .collect()
+    }
+
+    #[test]
+    fn resolves_names_and_provides() {
+        let db = test_db();
+        let targets = resolve(&db, &["vim", "editor", "--", "usr/bin/vim"]).unwrap();
+        assert_eq!(
+            resolved_names(&targets),
+            [("vim", "db"), ("vim", "provides")]
+        );
+        assert_eq!(
+            targets.download[0],
+            ["https://mirror.test/extra/vim-1.0-1-any.pkg.tar.zst"]
+        );
+    }
+
+    #[test]
+    fn resolves_repo_targets() {
+        let db = test_db();
+        let targets = resolve(&db, &["extra/", "--", "usr/bin/nano"]).unwrap();
+        assert_eq!(resolved_names(&targets), [("nano", "repo")]);
+    }
+
+    #[test]
+    fn file_lists_pick_the_packages() {
+        let db = test_db();
+        let targets = resolve(&db, &["-F", "--", "usr/bin/ls"]).unwrap();
+        assert_eq!(resolved_names(&targets), [("coreutils", "db")]);
+        let targets = resolve(&db, &["-Q", "--", "usr/bin/vim"]).unwrap();
+        assert_eq!(resolved_names(&targets), [("vim", "db")]);
+    }
+
+    #[test]
+    fn from_disk_reads_unmodified_files() {
+        let dir = std::env::temp_dir().join(format!("paccat-from-disk-{}", std::process::id()));
+        let (root, dbpath) = (dir.join("root"), dir.join("db"));
+        let mtree = dbpath.join("local/vim-1.0-1/mtree");
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        fs::create_dir_all(mtree.parent().unwrap()).unwrap();
+        fs::write(root.join("usr/bin/vim"), "vim\n").unwrap();
+        fs::write(
+            &mtree,
+            "#mtree\n./usr/bin/vim type=file size=4 \
+             sha256digest=f38b881ac6bc35425daf3e035677a0ff4b18bdc50e17b33cdd7f911091a4f1dc\n",
+        )
+        .unwrap();
+
+        let db = MemDb {
+            dbpath: Some(dbpath.to_string_lossy().into_owned()),
+            ..test_db()
+        };
+        let root = root.to_str().unwrap();
+        let argv = ["--from-disk", "--root", root, "vim", "--", "usr/bin/vim"];
+        let kind = || resolve(&db, &argv).unwrap().resolved[0].kind;
+        assert_eq!(kind(), "disk");
+        fs::write(Path::new(root).join("usr/bin/vim"), "vin\n").unwrap();
+        assert_eq!(kind(), "db");
+        fs::write(Path::new(root).join("usr/bin/vim"), "vim\n").unwrap();
+        fs::remove_file(&mtree).unwrap();
+        assert_eq!(kind(), "db");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_targets_suggest_a_name() {
+        let db = test_db();
+        let err = resolve(&db, &["nan", "--", "x"]).err().unwrap().to_string();
+        assert!(err.contains("did you mean 'nano'"), "{}", err);
+    }
+
+    fn matcher(flags: &[&str], file: &str) -> Match {
+        let args = Args::parse_from([&["paccat"], flags, &["foo", "--", file]].concat());
+        let mut matcher = Match::new(
+            args.regex,
+            args.files.clone(),
+            exact_file(&args),
+            args.usrmerge,
+            args.case_fold_paths || args.match_any_component_ci,
+        )
+        .unwrap();
+        matcher.component = args.match_any_component_ci;
+        matcher
+    }
+
+    #[test]
+    fn case_fold_paths_match_whole_paths() {
+        let mut m = matcher(&["--case-fold-paths"], "Usr/Share/Doc/README");
+        assert!(m.is_match("usr/share/doc/readme", false));
+        assert!(m.is_match("USR/SHARE/DOC/README", false));
+        assert!(!m.is_match("usr/share/doc/README.md", false));
+        assert!(!m.is_match("opt/usr/share/doc/README", false));
+
+        let mut m = matcher(&["--case-fold-paths", "--regex"], r"usr/Share/.*\.TXT");
+        assert!(m.is_match("USR/share/Doc/notes.txt", false));
+        assert!(!m.is_match("usr/share/doc/notes.md", false));
+    }
+
+    #[test]
+    fn any_component_matches_without_case() {
+        let mut m = matcher(&["--match-any-component-ci"], "Python3");
+        assert!(m.is_match("usr/lib/PYTHON3/site.py", false));
+        assert!(m.is_match("usr/bin/python3", false));
+        assert!(!m.is_match("usr/lib/python3.10/site.py", false));
+
+        let mut m = matcher(&["--match-any-component-ci", "--regex"], "^Site-Packages$");
+        assert!(m.is_match("usr/lib/python3.10/site-packages/foo.py", false));
+        assert!(!m.is_match("usr/lib/python3.10/site.py", false));
+    }
+
+    #[test]
+    fn bad_regexes_are_named() {
+        let files = ["usr/bin/.*", "foo[", "bar"].map(String::from);
+        let err = check_regexes(&files).unwrap_err().to_string();
+        assert_eq!(err, "pattern #2 'foo[': unclosed character class");
+        assert!(check_regexes(&files[..1]).is_ok());
+    }
+
+    #[test]
+    fn sizes_with_suffixes() {
+        assert_eq!(args::parse_size("512"), Ok(512));
+        assert_eq!(args::parse_size("2K"), Ok(2048));
+        assert_eq!(args::parse_size("3m"), Ok(3 << 20));
+        assert_eq!(
+            args::parse_size("17179869184G"),
+            Err("size '17179869184G' is too large".to_string())
+        );
+        assert!(args::parse_size("1.5G").is_err());
+    }
+
+    #[test]
+    fn extract_paths_stay_in_cwd() {
+        assert!(!escapes_cwd("usr/bin/ls"));
+        assert!(!escapes_cwd("usr/share/"));
+        assert!(escapes_cwd("/etc/passwd"));
+        assert!(escapes_cwd("usr/../../etc/passwd"));
+        assert!(escapes_cwd("usr//passwd"));
+        // x//etc/passwd with --strip-components 1
+        assert!(escapes_cwd(
+            strip_components("x//etc/passwd", Some(1)).unwrap()
+        ));
     }
-    files
-        .files()
-        .iter()
-        .any(|f| matcher.is_match(f.name(), false))
 }
@@ -0,0 +1,371 @@
+use crate::args::{Args, SortBy, TimeOrder};
+use crate::json;
+use crate::mtree::{read_mtree, Entry, Kind};
+use crate::output::in_size_range;
+use crate::Match;
+use anyhow::{bail, Result};
+use nix::unistd::{Group, User};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::Path;
+
+pub fn print_manifest(pkgs: &[String], matcher: &mut Match, args: &Args) -> Result<i32> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut manifest = Vec::new();
+
+    for pkg in pkgs {
+        let entries = match read_mtree(pkg)? {
+            Some(entries) => entries,
+            None => bail!("{} does not contain a .MTREE", pkg),
+        };
+
+        let name = pkg_name(pkg);
+        let entries = entries
+            .into_iter()
+            .filter(|e| args.files.is_empty() || matcher.is_match(&e.path, false))
+            .collect::<Vec<_>>();
+        let mut entries = match args.max_depth {
+            Some(depth) => truncate_depth(entries, depth),
+            None => entries.into_iter().map(|e| (e, 0)).collect(),
+        };
+        sort_entries(&mut entries, args.sort_by.unwrap_or(SortBy::Size));
+        manifest.extend(entries.into_iter().map(|(e, hidden)| (name, e, hidden)));
+    }
+
+    if args.json {
+        writeln!(stdout, "[")?;
+        for (i, (pkg, entry, hidden)) in manifest.iter().enumerate() {
+            let sep = if i + 1 == manifest.len() { "" } else { "," };
+            let hidden = if args.max_depth.is_some() {
+                format!(", \"hidden\": {}", hidden)
+            } else {
+                String::new()
+            };
+            writeln!(
+                stdout,
+                "  {{\"package\": {}, \"path\": {}, \"type\": \"{}\", \"size\": {}, \"mode\": \"{:04o}\"{}}}{}",
+                json::string(pkg),
+                json::string(&entry.path),
+                entry.kind.as_str(),
+                entry.size,
+                entry.mode,
+                hidden,
+                sep
+            )?;
+        }
+        writeln!(stdout, "]")?;
+    } else {
+        for (pkg, entry, _) in &manifest {
+            writeln!(
+                stdout,
+                "{}\t{}\t{:04o}\t{}\t{}",
+                pkg,
+                entry.size,
+                entry.mode,
+                entry.kind.as_str(),
+                entry.path
+            )?;
+        }
+    }
+
+    Ok(if manifest.is_empty() { 1 } else { 0 })
+}
+
+/// Lists the entries of the packages by their mtime for --timestamp-sort.
+/// --min-size and --max-size only keep the files in range like they do
+/// elsewhere.
+pub fn print_by_time(pkgs: &[String], matcher: &mut Match, args: &Args) -> Result<i32> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let sized = args.min_size.is_some() || args.max_size.is_some();
+    let mut entries = Vec::new();
+
+    for pkg in pkgs {
+        let mtree = match read_mtree(pkg)? {
+            Some(entries) => entries,
+            None => bail!("{} does not contain a .MTREE", pkg),
+        };
+
+        let name = pkg_name(pkg);
+        entries.extend(
+            mtree
+                .into_iter()
+                .filter(|e| args.files.is_empty() || matcher.is_match(&e.path, false))
+                .filter(|e| !sized || (e.kind == Kind::File && in_size_range(args, e.size)))
+                .map(|e| (name, e)),
+        );
+    }
+
+    match args.timestamp_sort {
+        Some(TimeOrder::Oldest) => {
+            entries.sort_by(|(_, a), (_, b)| a.time.cmp(&b.time).then(a.path.cmp(&b.path)))
+        }
+        _ => entries.sort_by(|(_, a), (_, b)| b.time.cmp(&a.time).then(a.path.cmp(&b.path))),
+    }
+
+    if args.json {
+        writeln!(stdout, "[")?;
+        for (i, (pkg, entry)) in entries.iter().enumerate() {
+            let sep = if i + 1 == entries.len() { "" } else { "," };
+            writeln!(
+                stdout,
+                "  {{\"package\": {}, \"path\": {}, \"type\": \"{}\", \"size\": {}, \"mtime\": {}}}{}",
+                json::string(pkg),
+                json::string(&entry.path),
+                entry.kind.as_str(),
+                entry.size,
+                entry.time,
+                sep
+            )?;
+        }
+        writeln!(stdout, "]")?;
+    } else {
+        for (pkg, entry) in &entries {
+            writeln!(
+                stdout,
+                "{}\t{}\t{}\t{}\t{}",
+                pkg,
+                entry.time,
+                entry.size,
+                entry.kind.as_str(),
+                entry.path
+            )?;
+        }
+    }
+
+    Ok(if entries.is_empty() { 1 } else { 0 })
+}
+
+/// Lists the entries that the audit filters like --owned-by-user keep, with
+/// their mode and owner.
+pub fn print_audit(pkgs: &[String], matcher: &mut Match, args: &Args) -> Result<i32> {
+    let user = args
+        .owned_by_user
+        .as_deref()
+        .map(|u| owner(u, user_id))
+        .transpose()?;
+    let group = args
+        .owned_by_group
+        .as_deref()
+        .map(|g| owner(g, group_id))
+        .transpose()?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut found = 0;
+
+    if args.json {
+        writeln!(stdout, "[")?;
+    }
+
+    for pkg in pkgs {
+        let entries = match read_mtree(pkg)? {
+            Some(entries) => entries,
+            None => bail!("{} does not contain a .MTREE", pkg),
+        };
+        let name = pkg_name(pkg);
+
+        for entry in entries {
+            if !(args.files.is_empty() || matcher.is_match(&entry.path, false))
+                || matches!(user, Some((uid, not)) if (entry.uid == uid) == not)
+                || matches!(group, Some((gid, not)) if (entry.gid == gid) == not)
+                || !mode_matches(args, &entry)
+            {
+                continue;
+            }
+
+            if args.json {
+                let sep = if found == 0 { "" } else { ",\n" };
+                write!(
+                    stdout,
+                    "{}  {{\"package\": {}, \"path\": {}, \"type\": \"{}\", \"mode\": \"{:04o}\", \"uid\": {}, \"gid\": {}}}",
+                    sep,
+                    json::string(name),
+                    json::string(&entry.path),
+                    entry.kind.as_str(),
+                    entry.mode,
+                    entry.uid,
+                    entry.gid
+                )?;
+            } else {
+                writeln!(
+                    stdout,
+                    "{}\t{:04o}\t{}:{}\t{}",
+                    name, entry.mode, entry.uid, entry.gid, entry.path
+                )?;
+            }
+            found += 1;
+        }
+    }
+
+    if args.json {
+        if found != 0 {
+            writeln!(stdout)?;
+        }
+        writeln!(stdout, "]")?;
+    }
+
+    Ok(match (found == 0, args.fail_on_match) {
+        (true, false) | (false, true) => 1,
+        _ => 0,
+    })
+}
+
+/// Whether one of the filters for print_audit was given.
+pub fn auditing(args: &Args) -> bool {
+    args.owned_by_user.is_some()
+        || args.owned_by_group.is_some()
+        || args.setuid
+        || args.setgid
+        || args.world_writable
+}
+
+/// Whether entry has one of the mode bits asked for with --setuid, --setgid
+/// and --world-writable, or any mode when none were. Symlinks are always
+/// 0777 so they are never world writable.
+fn mode_matches(args: &Args, entry: &Entry) -> bool {
+    let mode = entry.mode;
+
+    !(args.setuid || args.setgid || args.world_writable)
+        || (args.setuid && mode & 0o4000 != 0)
+        || (args.setgid && mode & 0o2000 != 0)
+        || (args.world_writable && entry.kind != Kind::Link && mode & 0o002 != 0)
+}
+
+/// Parses the id of an owner for --owned-by-user and --owned-by-group. A
+/// leading `!` keeps the entries with any other owner instead.
+fn owner(owner: &str, id: fn(&str) -> Result<u32>) -> Result<(u32, bool)> {
+    match owner.strip_prefix('!') {
+        Some(owner) => Ok((id(owner)?, true)),
+        None => Ok((id(owner)?, false)),
+    }
+}
+
+fn user_id(user: &str) -> Result<u32> {
+    if let Ok(uid) = user.parse() {
+        return Ok(uid);
+    }
+    match User::from_name(user)? {
+        Some(user) => Ok(user.uid.as_raw()),
+        None => bail!("there is no user named {}", user),
+    }
+}
+
+fn group_id(group: &str) -> Result<u32> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    match Group::from_name(group)? {
+        Some(group) => Ok(group.gid.as_raw()),
+        None => bail!("there is no group named {}", group),
+    }
+}
+
+/// Drops the entries deeper than depth. The size of everything below a
+/// directory at that depth is added to it along with how many entries were
+/// hidden.
+fn truncate_depth(entries: Vec<Entry>, depth: usize) -> Vec<(Entry, usize)> {
+    let mut hidden = HashMap::<String, (u64, usize)>::new();
+    let mut kept = Vec::new();
+
+    for entry in entries {
+        if path_depth(&entry.path) <= depth {
+            kept.push(entry);
+        } else {
+            let parent = entry
+                .path
+                .splitn(depth + 1, '/')
+                .take(depth)
+                .collect::<Vec<_>>();
+            let total = hidden.entry(parent.join("/")).or_default();
+            total.0 += entry.size;
+            total.1 += 1;
+        }
+    }
+
+    kept.into_iter()
+        .map(|mut e| match hidden.get(&e.path) {
+            Some(&(size, count)) => {
+                e.size += size;
+                (e, count)
+            }
+            None => (e, 0),
+        })
+        .collect()
+}
+
+pub fn path_depth(path: &str) -> usize {
+    path.trim_end_matches('/').split('/').count()
+}
+
+fn sort_entries(entries: &mut [(Entry, usize)], sort_by: SortBy) {
+    match sort_by {
+        SortBy::Size => {
+            entries.sort_by(|(a, _), (b, _)| b.size.cmp(&a.size).then(a.path.cmp(&b.path)))
+        }
+        SortBy::Path => entries.sort_by(|(a, _), (b, _)| a.path.cmp(&b.path)),
+        SortBy::Name => entries.sort_by(|(a, _), (b, _)| basename(&a.path).cmp(basename(&b.path))),
+    }
+}
+
+/// For --select-newest and --select-oldest, keeps the matched file with the
+/// newest or oldest mtime out of the files in all packages with the same
+/// name. Returns the paths kept in each package by package file name.
+/// Packages without a .MTREE have no mtimes to compare, so they are left out
+/// of the map and all of their matched files are printed.
+pub fn select_by_time(
+    pkgs: &[String],
+    matcher: &mut Match,
+    newest: bool,
+) -> Result<HashMap<String, HashSet<String>>> {
+    let mut best = HashMap::<String, (i64, &str, String)>::new();
+    let mut selected = HashMap::<String, HashSet<String>>::new();
+
+    for pkg in pkgs {
+        let entries = match read_mtree(pkg)? {
+            Some(entries) => entries,
+            None => {
+                warning!(
+                    "warning: {} does not contain a .MTREE, printing all of its matched files",
+                    pkg
+                );
+                continue;
+            }
+        };
+        let name = pkg_name(pkg);
+        selected.entry(name.to_string()).or_default();
+
+        for entry in entries {
+            if entry.kind == Kind::Dir || !matcher.is_match(&entry.path, false) {
+                continue;
+            }
+
+            let base = basename(&entry.path).to_string();
+            let replace = match best.get(&base) {
+                Some(&(time, _, _)) if newest => entry.time > time,
+                Some(&(time, _, _)) => entry.time < time,
+                None => true,
+            };
+            if replace {
+                best.insert(base, (entry.time, name, entry.path));
+            }
+        }
+    }
+
+    for (_, pkg, path) in best.into_values() {
+        selected.get_mut(pkg).unwrap().insert(path);
+    }
+
+    Ok(selected)
+}
+
+pub fn pkg_name(pkg: &str) -> &str {
+    Path::new(pkg)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(pkg)
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap()
+}
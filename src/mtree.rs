@@ -0,0 +1,185 @@
+use crate::format::compression;
+use anyhow::{Context, Result};
+use compress_tools::{uncompress_archive_file, uncompress_data};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Dir,
+    Link,
+}
+
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::File => "file",
+            Kind::Dir => "dir",
+            Kind::Link => "link",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: String,
+    pub kind: Kind,
+    pub size: u64,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub time: i64,
+    pub sha256: Option<String>,
+    pub link: Option<String>,
+}
+
+impl Default for Entry {
+    fn default() -> Self {
+        Entry {
+            path: String::new(),
+            kind: Kind::File,
+            size: 0,
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+            time: 0,
+            sha256: None,
+            link: None,
+        }
+    }
+}
+
+pub fn read_mtree(pkg: &str) -> Result<Option<Vec<Entry>>> {
+    let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+    let mut compressed = Vec::new();
+    if uncompress_archive_file(file, &mut compressed, ".MTREE").is_err() {
+        return Ok(None);
+    }
+
+    let mtree = decompress(compressed, &format!(".MTREE in {}", pkg))?;
+    Ok(Some(parse(&mtree)))
+}
+
+/// The mtree pacman keeps of an installed package in its local database,
+/// which has the size and sha256sum each file was installed with.
+pub fn read_local_mtree(dbpath: &str, name: &str, version: &str) -> Result<Option<Vec<Entry>>> {
+    let path = Path::new(dbpath)
+        .join("local")
+        .join(format!("{}-{}", name, version))
+        .join("mtree");
+    let compressed = match fs::read(&path) {
+        Ok(compressed) => compressed,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+
+    let mtree = decompress(compressed, &path.to_string_lossy())?;
+    Ok(Some(parse(&mtree)))
+}
+
+fn decompress(compressed: Vec<u8>, what: &str) -> Result<String> {
+    // pacman uses gzip but nothing stops that from changing
+    let mtree = match compression(&compressed) {
+        Some(format) => {
+            let mut mtree = Vec::new();
+            uncompress_data(compressed.as_slice(), &mut mtree)
+                .with_context(|| format!("failed to decompress {} {}", format, what))?;
+            mtree
+        }
+        None => compressed,
+    };
+    Ok(String::from_utf8_lossy(&mtree).into_owned())
+}
+
+pub fn parse(mtree: &str) -> Vec<Entry> {
+    let mut defaults = Entry::default();
+    let mut entries = Vec::new();
+
+    for line in mtree.lines() {
+        let mut words = line.split_whitespace();
+        let first = match words.next() {
+            Some(first) if !first.starts_with('#') => first,
+            _ => continue,
+        };
+
+        match first {
+            "/set" => words.for_each(|w| set_keyword(&mut defaults, w)),
+            "/unset" => words.for_each(|w| unset_keyword(&mut defaults, w)),
+            path => {
+                let mut entry = defaults.clone();
+                entry.path = unescape(path.trim_start_matches("./"));
+                words.for_each(|w| set_keyword(&mut entry, w));
+                if !entry.path.is_empty() && entry.path != "." {
+                    entries.push(entry);
+                }
+            }
+        }
+    }
+
+    entries
+}
+
+fn set_keyword(entry: &mut Entry, word: &str) {
+    let (key, val) = match word.split_once('=') {
+        Some(kv) => kv,
+        None => return,
+    };
+
+    match key {
+        "type" => {
+            entry.kind = match val {
+                "dir" => Kind::Dir,
+                "link" => Kind::Link,
+                _ => Kind::File,
+            }
+        }
+        "size" => entry.size = val.parse().unwrap_or(0),
+        "mode" => entry.mode = u32::from_str_radix(val, 8).unwrap_or(0),
+        "uid" => entry.uid = val.parse().unwrap_or(0),
+        "gid" => entry.gid = val.parse().unwrap_or(0),
+        "time" => entry.time = val.split('.').next().unwrap().parse().unwrap_or(0),
+        "sha256digest" => entry.sha256 = Some(val.to_string()),
+        "link" => entry.link = Some(unescape(val)),
+        _ => (),
+    }
+}
+
+fn unset_keyword(entry: &mut Entry, key: &str) {
+    let default = Entry::default();
+
+    match key {
+        "all" => *entry = default,
+        "type" => entry.kind = default.kind,
+        "size" => entry.size = default.size,
+        "mode" => entry.mode = default.mode,
+        "uid" => entry.uid = default.uid,
+        "gid" => entry.gid = default.gid,
+        "time" => entry.time = default.time,
+        "sha256digest" => entry.sha256 = default.sha256,
+        "link" => entry.link = default.link,
+        _ => (),
+    }
+}
+
+fn unescape(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 4 <= bytes.len() {
+            let oct = std::str::from_utf8(&bytes[i + 1..i + 4]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(oct, 8) {
+                out.push(b);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,32 @@
+use crate::glob;
+use anyhow::Result;
+use regex::Regex;
+
+/// The NoExtract patterns from pacman.conf. Like pacman, the last pattern
+/// that matches a path decides and a leading `!` negates the pattern.
+pub struct NoExtract {
+    patterns: Vec<(bool, Regex)>,
+}
+
+impl NoExtract {
+    pub fn new<'a, I: IntoIterator<Item = &'a str>>(patterns: I) -> Result<Self> {
+        let patterns = patterns
+            .into_iter()
+            .map(|p| match p.strip_prefix('!') {
+                Some(p) => Ok((false, glob::compile(p)?)),
+                None => Ok((true, glob::compile(p)?)),
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(NoExtract { patterns })
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|(_, re)| re.is_match(path))
+            .map(|(extract, _)| *extract)
+            .unwrap_or(false)
+    }
+}
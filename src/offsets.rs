@@ -0,0 +1,177 @@
+use crate::args::{ArchiveFormat, Args};
+use crate::format;
+use crate::json;
+use crate::manifest::pkg_name;
+use crate::Match;
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::{self, Read, Write};
+
+/// An entry of a tar stream. offset is where its first header starts,
+/// including any pax or GNU long name headers before the real one.
+struct TarEntry {
+    path: String,
+    offset: u64,
+    data_offset: u64,
+    size: u64,
+}
+
+/// Prints where each entry is in the decompressed tar of the packages for
+/// --print-entry-offsets. The compressed stream has no boundaries between
+/// entries so only the compressed size of the whole package is printed, to
+/// stderr.
+pub fn print_offsets(pkgs: &[String], matcher: &mut Match, args: &Args) -> Result<i32> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut found = 0;
+
+    for pkg in pkgs {
+        let data = fs::read(pkg).with_context(|| format!("failed to read {}", pkg))?;
+        let compression = format::compression(&data);
+        let archive_format = match (args.archive_format, compression) {
+            (ArchiveFormat::Auto, Some("gzip")) => ArchiveFormat::Gzip,
+            (ArchiveFormat::Auto, Some("bzip2")) => ArchiveFormat::Bzip2,
+            (ArchiveFormat::Auto, Some("xz")) => ArchiveFormat::Xz,
+            (ArchiveFormat::Auto, Some("zstd")) => ArchiveFormat::Zstd,
+            (ArchiveFormat::Auto, _) => ArchiveFormat::Tar,
+            (format, _) => format,
+        };
+        let compressed = data.len();
+        let mut tar = Vec::new();
+        if let Some(mut stream) = format::decompress(pkg, Some(data), archive_format)? {
+            stream.read_to_end(&mut tar)?;
+        }
+        let name = pkg_name(pkg);
+
+        eprintln!(
+            "{}: {} bytes {} from {} bytes of tar",
+            name,
+            compressed,
+            compression.map_or("uncompressed".to_string(), |c| format!("{} compressed", c)),
+            tar.len()
+        );
+
+        for entry in tar_entries(&tar).with_context(|| format!("failed to read {}", pkg))? {
+            if !(args.files.is_empty() || matcher.is_match(&entry.path, false)) {
+                continue;
+            }
+
+            if args.json {
+                writeln!(
+                    stdout,
+                    "{{\"package\": {}, \"path\": {}, \"offset\": {}, \"data_offset\": {}, \"size\": {}}}",
+                    json::string(name),
+                    json::string(&entry.path),
+                    entry.offset,
+                    entry.data_offset,
+                    entry.size
+                )?;
+            } else {
+                writeln!(
+                    stdout,
+                    "{}\t{}\t{}\t{}\t{}",
+                    name, entry.offset, entry.data_offset, entry.size, entry.path
+                )?;
+            }
+            found += 1;
+        }
+    }
+
+    Ok(if found == 0 { 1 } else { 0 })
+}
+
+fn tar_entries(tar: &[u8]) -> Result<Vec<TarEntry>> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    let mut start = None;
+    let mut long_name = None;
+
+    while pos + 512 <= tar.len() {
+        let header = &tar[pos..pos + 512];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+
+        let size = octal(&header[124..136])?;
+        let data = pos + 512;
+        let end = data + size as usize;
+        if end > tar.len() {
+            bail!("the entry at offset {} is truncated", pos);
+        }
+        let start_pos = *start.get_or_insert(pos);
+
+        match header[156] {
+            b'L' => long_name = Some(c_str(&tar[data..end])),
+            b'x' => long_name = pax_path(&tar[data..end]).or(long_name),
+            b'g' => start = None,
+            _ => {
+                let path = long_name.take().unwrap_or_else(|| {
+                    let name = c_str(&header[..100]);
+                    match c_str(&header[345..500]) {
+                        prefix if &header[257..262] == b"ustar" && !prefix.is_empty() => {
+                            format!("{}/{}", prefix, name)
+                        }
+                        _ => name,
+                    }
+                });
+                entries.push(TarEntry {
+                    path,
+                    offset: start_pos as u64,
+                    data_offset: data as u64,
+                    size,
+                });
+                start = None;
+            }
+        }
+
+        pos = data + ((size as usize + 511) & !511);
+    }
+
+    Ok(entries)
+}
+
+fn c_str(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Parses a numeric field of a tar header, which is octal or big endian
+/// binary when the high bit of the first byte is set.
+fn octal(field: &[u8]) -> Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold((field[0] & 0x7f) as u64, |n, &b| n << 8 | b as u64));
+    }
+
+    let text = c_str(field);
+    let text = text.trim_matches(|c: char| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).with_context(|| format!("invalid size '{}' in tar header", text))
+}
+
+/// The path in the records of a pax header, which look like
+/// `<length> <key>=<value>\n`.
+fn pax_path(mut data: &[u8]) -> Option<String> {
+    let mut path = None;
+
+    while let Some(space) = data.iter().position(|&b| b == b' ') {
+        let len = std::str::from_utf8(&data[..space])
+            .ok()?
+            .parse::<usize>()
+            .ok()?;
+        if len <= space || len > data.len() {
+            break;
+        }
+        let record = &data[space + 1..len];
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(value) = record.strip_prefix(b"path=") {
+            path = Some(String::from_utf8_lossy(value).into_owned());
+        }
+        data = &data[len..];
+    }
+
+    path
+}
@@ -0,0 +1,682 @@
+use crate::args::{Args, ColorWhen, DumpOrder, SortBy};
+use crate::diff;
+use crate::encoding;
+use crate::filter;
+use crate::highlight;
+use crate::is_binary;
+use crate::json;
+use crate::magic;
+use crate::mtree::Entry;
+use crate::tar;
+use crate::wrap;
+use anyhow::{bail, Context, Result};
+use nix::unistd::isatty;
+use regex::bytes::Regex;
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+pub struct Sink<W> {
+    out: W,
+    extract: Option<File>,
+    separator: Vec<u8>,
+    begun: bool,
+    written: bool,
+    line_number: bool,
+    line: usize,
+    line_start: bool,
+    show_name: bool,
+    name: String,
+    chomp: bool,
+    /// Whether the last byte written was a newline held back for --chomp.
+    held: bool,
+}
+
+impl<W: Write> Sink<W> {
+    fn start(&mut self, name: &str) {
+        self.name.clear();
+        self.name.push_str(name);
+        self.begun = false;
+        self.line = 1;
+        self.line_start = true;
+        self.held = false;
+    }
+
+    fn begin(&mut self) -> io::Result<()> {
+        if self.begun || self.extract.is_some() {
+            return Ok(());
+        }
+
+        if self.written {
+            self.out.write_all(&self.separator)?;
+        }
+
+        if self.show_name {
+            writeln!(self.out, "==> {} <==", self.name)?;
+        }
+
+        self.begun = true;
+        self.written = true;
+        Ok(())
+    }
+
+    fn write(&mut self, mut data: &[u8]) -> io::Result<()> {
+        if let Some(extract) = &mut self.extract {
+            return extract.write_all(data);
+        }
+
+        self.begin()?;
+
+        if self.chomp {
+            if std::mem::take(&mut self.held) {
+                self.write_lines(b"\n")?;
+            }
+            if let Some(rest) = data.strip_suffix(b"\n") {
+                self.held = true;
+                data = rest;
+            }
+        }
+
+        self.write_lines(data)
+    }
+
+    fn write_lines(&mut self, data: &[u8]) -> io::Result<()> {
+        if !self.line_number {
+            return self.out.write_all(data);
+        }
+
+        for line in data.split_inclusive(|&b| b == b'\n') {
+            if self.line_start {
+                write!(self.out, "{:>6}\t", self.line)?;
+                self.line += 1;
+            }
+            self.out.write_all(line)?;
+            self.line_start = line.ends_with(b"\n");
+        }
+
+        Ok(())
+    }
+}
+
+/// How much sorted output --sort-by and --dump-order hold before noting
+/// the memory it takes.
+const SORT_NOTE_BYTES: usize = 256 << 20;
+
+pub struct Output<'a, W> {
+    sink: Sink<W>,
+    args: &'a Args,
+    contains: Option<Regex>,
+    grep: Option<Regex>,
+    line_filter: Option<Regex>,
+    line_reject: Option<Regex>,
+    buf: Vec<u8>,
+    /// The entries held back for --sort-by and --dump-order with the
+    /// pattern they matched.
+    sorted: Vec<(String, Vec<u8>, usize)>,
+    /// How many bytes are held in sorted, to note once it gets large.
+    sorted_bytes: usize,
+    pattern: usize,
+    bytes: u64,
+    differs: bool,
+    /// Whether a binary file was matched with --fail-on-binary.
+    binary: bool,
+    wrap: Option<usize>,
+    highlight: bool,
+    colour_diff: bool,
+    package: String,
+    /// The path --tar writes the next entry as and its .MTREE entry.
+    tar_entry: Option<(String, Option<Entry>)>,
+}
+
+impl<'a, W: Write> Output<'a, W> {
+    pub fn new(out: W, args: &'a Args) -> Result<Self> {
+        let contains = args.contains.as_deref().map(Regex::new).transpose()?;
+        let grep = args.grep.as_deref().map(Regex::new).transpose()?;
+        let line_filter = args.line_filter.as_deref().map(Regex::new).transpose()?;
+        let line_reject = args.line_reject.as_deref().map(Regex::new).transpose()?;
+        let sink = Sink {
+            out,
+            extract: None,
+            separator: parse_separator(&args.content_separator),
+            begun: false,
+            written: false,
+            line_number: args.line_number && !args.raw,
+            line: 1,
+            line_start: true,
+            show_name: args.show_name,
+            name: String::new(),
+            chomp: args.chomp,
+            held: false,
+        };
+
+        Ok(Output {
+            sink,
+            args,
+            contains,
+            grep,
+            line_filter,
+            line_reject,
+            buf: Vec::new(),
+            sorted: Vec::new(),
+            sorted_bytes: 0,
+            pattern: 0,
+            bytes: 0,
+            differs: false,
+            binary: false,
+            wrap: args
+                .wrap
+                .map(|width| width.or_else(wrap::terminal_width).unwrap_or(80)),
+            highlight: args.highlight && use_color(args),
+            colour_diff: args.color_diff && use_color(args),
+            package: String::new(),
+            tar_entry: None,
+        })
+    }
+
+    pub fn out(&mut self) -> &mut W {
+        &mut self.sink.out
+    }
+
+    pub fn buffered(&self) -> bool {
+        self.reads_content() || self.args.min_size.is_some() || self.args.max_size.is_some()
+    }
+
+    /// Whether the content of files is needed before they can be printed.
+    pub fn reads_content(&self) -> bool {
+        self.contains.is_some()
+            || self.grep.is_some()
+            || self.line_filter.is_some()
+            || self.line_reject.is_some()
+            || self.args.sort_by.is_some()
+            || self.args.text_only
+            || self.args.compare_installed
+            || self.args.wrap.is_some()
+            || self.highlight
+            || transforms_content(self.args)
+            || self.args.skip_empty
+            || self.args.json_lines
+            || self.args.tar
+            || self.args.dump_order == DumpOrder::Pattern
+    }
+
+    /// Sets the package that --json-lines prints for the entries after this.
+    pub fn set_package(&mut self, package: &str) {
+        self.package.clear();
+        self.package.push_str(package);
+    }
+
+    /// Sets the index of the pattern the next entry matched for
+    /// --dump-order.
+    pub fn set_pattern(&mut self, pattern: usize) {
+        self.pattern = pattern;
+    }
+
+    pub fn start(&mut self, name: &str, extract: Option<File>) {
+        self.sink.extract = extract;
+        self.sink.start(name);
+        self.buf.clear();
+        self.tar_entry = None;
+    }
+
+    /// Sets the path and .MTREE entry --tar writes the entry started last
+    /// with, when they aren't its name.
+    pub fn set_tar_entry(&mut self, path: &str, mtree: Option<&Entry>) {
+        self.tar_entry = Some((path.to_string(), mtree.cloned()));
+    }
+
+    /// Ends the tar of --tar.
+    pub fn finish_tar(&mut self) -> io::Result<()> {
+        if self.args.tar {
+            self.sink.out.write_all(&tar::END)?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.buffered() {
+            self.buf.extend_from_slice(data);
+            Ok(())
+        } else if self.args.count_bytes {
+            self.bytes += data.len() as u64;
+            Ok(())
+        } else {
+            self.sink.write(data)
+        }
+    }
+
+    /// Called at the end of every entry that was read. Returns false if the
+    /// entry was filtered out by its content.
+    pub fn finish(&mut self, name: &str) -> Result<bool> {
+        if !self.buffered() {
+            if !self.args.count_bytes {
+                self.sink.begin()?;
+            }
+            return Ok(true);
+        }
+
+        let sized = self.args.min_size.is_some() || self.args.max_size.is_some();
+        if sized && (name.ends_with('/') || !in_size_range(self.args, self.buf.len() as u64)) {
+            return Ok(false);
+        }
+
+        if self.args.skip_empty && self.buf.is_empty() {
+            return Ok(false);
+        }
+
+        if let Some(cmd) = &self.args.filter_cmd {
+            self.buf = filter::filter(cmd, name, std::mem::take(&mut self.buf))?;
+        }
+
+        if let Some(text) = self
+            .args
+            .encoding
+            .and_then(|enc| encoding::to_utf8(&self.buf, enc))
+        {
+            self.buf = text.into_bytes();
+        }
+
+        if self.args.json_lines {
+            return self.json_line(name);
+        }
+
+        if transforms_content(self.args) && !self.args.binary && is_binary(&self.buf) {
+            self.skip_binary(name)?;
+            return Ok(false);
+        }
+
+        if self.args.text_only && std::str::from_utf8(&self.buf).is_err() {
+            warning!("{} is not valid UTF-8 -- skipping", name);
+            return Ok(false);
+        }
+
+        if let Some(contains) = &self.contains {
+            if !contains.is_match(&self.buf) {
+                return Ok(false);
+            }
+        }
+
+        if self.args.count_matches {
+            // --grep is the one matched against lines when both are given
+            let regex = self.grep.as_ref().or(self.contains.as_ref()).unwrap();
+            let count = count_lines(regex, &self.buf);
+            if count == 0 {
+                return Ok(false);
+            }
+            writeln!(self.sink.out, "{}:{}: {}", self.package, name, count)?;
+            return Ok(true);
+        }
+
+        if let Some(grep) = &self.grep {
+            if !grep.is_match(&self.buf) {
+                return Ok(false);
+            }
+            if !self.args.quiet {
+                grep_lines(&mut self.sink.out, grep, name, &self.buf)?;
+                return Ok(true);
+            }
+        }
+
+        if (self.line_filter.is_some() || self.line_reject.is_some()) && !is_binary(&self.buf) {
+            self.buf = self.filter_lines();
+        }
+
+        if let Some(width) = self.wrap {
+            if !is_binary(&self.buf) {
+                if let Ok(text) = std::str::from_utf8(&self.buf) {
+                    self.buf = wrap::wrap(text, width).into_bytes();
+                }
+            }
+        }
+
+        if self.highlight && !is_binary(&self.buf) {
+            if let Some(text) = std::str::from_utf8(&self.buf)
+                .ok()
+                .and_then(|text| highlight::highlight(name, text))
+            {
+                self.buf = text.into_bytes();
+            }
+        }
+
+        if self.args.compare_installed {
+            self.differs |= self.compare_installed(name)?;
+        } else if self.args.tar {
+            let (path, mtree) = self.tar_entry.take().unwrap_or((name.to_string(), None));
+            tar::entry(&mut self.sink.out, &path, mtree.as_ref(), &self.buf)?;
+        } else if self.args.count_bytes {
+            self.bytes += self.buf.len() as u64;
+        } else if self.args.sort_by.is_some() || self.args.dump_order == DumpOrder::Pattern {
+            let data = std::mem::take(&mut self.buf);
+            let before = self.sorted_bytes;
+            self.sorted_bytes += data.len();
+            if before < SORT_NOTE_BYTES && self.sorted_bytes >= SORT_NOTE_BYTES {
+                warning!(
+                    "note: sorting is holding more than {} MiB of output in memory",
+                    SORT_NOTE_BYTES >> 20
+                );
+            }
+            self.sorted.push((name.to_string(), data, self.pattern));
+        } else {
+            emit(
+                &mut self.sink,
+                self.args,
+                self.contains.as_ref(),
+                name,
+                &self.buf,
+            )?;
+        }
+
+        Ok(true)
+    }
+
+    /// The lines of the entry that --line-filter keeps and --line-reject
+    /// doesn't leave out.
+    fn filter_lines(&self) -> Vec<u8> {
+        self.buf
+            .split_inclusive(|&b| b == b'\n')
+            .filter(|line| {
+                let line = line.strip_suffix(b"\n").unwrap_or(line);
+                !matches!(&self.line_filter, Some(r) if !r.is_match(line))
+                    && !matches!(&self.line_reject, Some(r) if r.is_match(line))
+            })
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    fn json_line(&mut self, name: &str) -> Result<bool> {
+        if let Some(contains) = &self.contains {
+            if !contains.is_match(&self.buf) {
+                return Ok(false);
+            }
+        }
+
+        let binary = is_binary(&self.buf);
+        let content = match std::str::from_utf8(&self.buf) {
+            Ok(text) if !binary => json::string(text),
+            _ => "null".to_string(),
+        };
+        let out = &mut self.sink.out;
+        writeln!(
+            out,
+            "{{\"package\": {}, \"path\": {}, \"size\": {}, \"binary\": {}, \"type\": \"{}\", \"sniffed\": {}, \"content\": {}}}",
+            json::string(&self.package),
+            json::string(name),
+            self.buf.len(),
+            binary,
+            magic::detect(&self.buf).name(),
+            self.buf.len().min(crate::SNIFF_BYTES),
+            content
+        )?;
+        out.flush()?;
+        Ok(true)
+    }
+
+    /// Prints what was written to other for --jobs as if it was written
+    /// here, followed by the entries it held back for --sort-by.
+    pub fn append(&mut self, other: Output<Vec<u8>>) -> io::Result<()> {
+        if self.sink.written && other.sink.written {
+            self.sink.out.write_all(&self.sink.separator)?;
+        }
+        self.sink.out.write_all(&other.sink.out)?;
+        self.sink.written |= other.sink.written;
+        self.sorted.extend(other.sorted);
+        self.bytes += other.bytes;
+        self.differs |= other.differs;
+        self.binary |= other.binary;
+        Ok(())
+    }
+
+    /// Whether --compare-installed found any file that differs from the
+    /// installed one.
+    pub fn differs(&self) -> bool {
+        self.differs
+    }
+
+    /// Skips a binary file, which is an error with --fail-on-binary.
+    pub fn skip_binary(&mut self, name: &str) -> Result<()> {
+        if !self.args.fail_on_binary {
+            skip_binary(self.args, name);
+            return Ok(());
+        }
+
+        if !self.args.keep_going {
+            self.sink.out.flush()?;
+            bail!("{} is a binary file (--fail-on-binary)", name);
+        }
+        eprintln!(
+            "error: {} is a binary file (--fail-on-binary) -- skipping",
+            name
+        );
+        self.binary = true;
+        Ok(())
+    }
+
+    /// Whether a binary file was skipped with --fail-on-binary and
+    /// --keep-going.
+    pub fn failed_binary(&self) -> bool {
+        self.binary
+    }
+
+    fn compare_installed(&mut self, name: &str) -> Result<bool> {
+        let root = self.args.root.as_deref().unwrap_or("/");
+        let path = Path::new(root).join(name.trim_start_matches('/'));
+        let out = &mut self.sink.out;
+
+        let installed = match fs::read(&path) {
+            Ok(installed) => installed,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                writeln!(out, "Only in package: {}", name)?;
+                return Ok(true);
+            }
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+
+        if installed == self.buf {
+            return Ok(false);
+        }
+
+        let old_name = format!("{} (package)", name);
+        let new_name = path.display().to_string();
+        let printed = match (
+            std::str::from_utf8(&self.buf),
+            std::str::from_utf8(&installed),
+        ) {
+            (Ok(old), Ok(new)) if !is_binary(&self.buf) && !is_binary(&installed) => {
+                diff::unified(out, &old_name, &new_name, old, new, self.colour_diff)?
+            }
+            _ => false,
+        };
+
+        if !printed {
+            writeln!(out, "Files {} and {} differ", old_name, new_name)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Adds bytes to --count-bytes for an entry that was not read.
+    pub fn count(&mut self, bytes: u64) {
+        self.bytes += bytes;
+    }
+
+    /// Returns the bytes counted by --count-bytes since the last call.
+    pub fn take_bytes(&mut self) -> u64 {
+        std::mem::take(&mut self.bytes)
+    }
+
+    /// Prints the entries held back by --sort-by, or by --dump-order at the
+    /// end of each package.
+    pub fn flush_sorted(&mut self) -> Result<()> {
+        let mut sorted = std::mem::take(&mut self.sorted);
+        self.sorted_bytes = 0;
+
+        match self.args.sort_by {
+            Some(SortBy::Size) => sorted.sort_by_key(|e| Reverse(e.1.len())),
+            Some(SortBy::Path) => sorted.sort_by(|a, b| a.0.cmp(&b.0)),
+            Some(SortBy::Name) => sorted.sort_by(|a, b| basename(&a.0).cmp(basename(&b.0))),
+            None => sorted.sort_by_key(|e| e.2),
+        }
+
+        for (name, data, _) in &sorted {
+            self.sink.start(name);
+            emit(
+                &mut self.sink,
+                self.args,
+                self.contains.as_ref(),
+                name,
+                data,
+            )?;
+        }
+
+        self.sink.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes everything to out and also to a file for --tee.
+pub struct Tee<W> {
+    out: W,
+    file: Option<File>,
+}
+
+impl<W: Write> Tee<W> {
+    pub fn new(out: W, path: Option<&str>) -> Result<Self> {
+        let file = path
+            .map(|path| File::create(path).with_context(|| format!("failed to create {}", path)))
+            .transpose()?;
+        Ok(Tee { out, file })
+    }
+}
+
+impl<W: Write> Write for Tee<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write_all(buf)?;
+        if let Some(file) = &mut self.file {
+            file.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()?;
+        if let Some(file) = &mut self.file {
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn emit<W: Write>(
+    sink: &mut Sink<W>,
+    args: &Args,
+    contains: Option<&Regex>,
+    name: &str,
+    data: &[u8],
+) -> io::Result<()> {
+    if args.quiet {
+        writeln!(sink.out, "{}", quiet_name(args, name))?;
+    } else if let (true, Some(contains)) = (args.only_matching, contains) {
+        sink.begin()?;
+        // the lines are counted on from the last match, not from the start
+        let (mut line, mut counted) = (1, 0);
+        for m in contains.find_iter(data) {
+            if sink.line_number {
+                line += data[counted..m.start()]
+                    .iter()
+                    .filter(|&&b| b == b'\n')
+                    .count();
+                counted = m.start();
+                write!(sink.out, "{}:", line)?;
+            }
+            sink.out.write_all(m.as_bytes())?;
+            sink.out.write_all(b"\n")?;
+        }
+    } else {
+        sink.begin()?;
+        sink.write(data)?;
+    }
+
+    Ok(())
+}
+
+/// Whether --color allows colours.
+pub fn use_color(args: &Args) -> bool {
+    match args.color {
+        ColorWhen::Always => true,
+        ColorWhen::Never => false,
+        ColorWhen::Auto => {
+            std::env::var_os("NO_COLOR").is_none()
+                && isatty(io::stdout().as_raw_fd()).unwrap_or(false)
+        }
+    }
+}
+
+/// Whether a file of size bytes is within --min-size and --max-size.
+pub fn in_size_range(args: &Args, size: u64) -> bool {
+    !matches!(args.min_size, Some(min) if size < min)
+        && !matches!(args.max_size, Some(max) if size > max)
+}
+
+/// Prints the lines of data that match grep prefixed by name and their line
+/// number.
+fn grep_lines<W: Write>(out: &mut W, grep: &Regex, name: &str, data: &[u8]) -> io::Result<()> {
+    let data = data.strip_suffix(b"\n").unwrap_or(data);
+    for (i, line) in data.split(|&b| b == b'\n').enumerate() {
+        if grep.is_match(line) {
+            write!(out, "{}:{}:", name, i + 1)?;
+            out.write_all(line)?;
+            out.write_all(b"\n")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// How many lines of data match regex, for --count-matches.
+fn count_lines(regex: &Regex, data: &[u8]) -> usize {
+    let data = data.strip_suffix(b"\n").unwrap_or(data);
+    data.split(|&b| b == b'\n')
+        .filter(|line| regex.is_match(line))
+        .count()
+}
+
+/// Tells the user that a binary file is not printed. --grep skips them
+/// quietly like grep does.
+pub fn skip_binary(args: &Args, name: &str) {
+    if args.grep.is_none() {
+        warning!("{} is a binary file -- use --binary to print", name);
+    }
+}
+
+/// Whether files are changed before being printed, in which case the
+/// binary check is done on the result instead of as the file is read.
+pub fn transforms_content(args: &Args) -> bool {
+    args.filter_cmd.is_some() || args.encoding.is_some()
+}
+
+/// The name --quiet prints for path.
+pub fn quiet_name<'a>(args: &Args, path: &'a str) -> &'a str {
+    if args.name_only {
+        basename(path.trim_end_matches('/'))
+    } else {
+        path
+    }
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap()
+}
+
+fn parse_separator(sep: &str) -> Vec<u8> {
+    match sep {
+        "none" => Vec::new(),
+        "nul" => vec![0],
+        sep => sep
+            .replace("\\n", "\n")
+            .replace("\\t", "\t")
+            .replace("\\0", "\0")
+            .into_bytes(),
+    }
+}
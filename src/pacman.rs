@@ -1,84 +1,624 @@
 use crate::args::Args;
-use alpm::{
-    Alpm, AnyDownloadEvent, AnyEvent, DownloadEvent, DownloadResult, Event, LogLevel, Package,
-};
-use alpm_utils::DbListExt;
-use alpm_utils::Targ;
-use anyhow::{Context, Result};
-use std::iter;
-use std::os::unix::ffi::OsStrExt;
-
-pub fn alpm_init(args: &Args) -> Result<Alpm> {
-    let conf = pacmanconf::Config::with_opts(None, args.config.as_deref(), args.root.as_deref())?;
-    let dbpath = args
-        .dbpath
-        .as_deref()
-        .unwrap_or_else(|| conf.db_path.as_str());
-    let mut alpm = Alpm::new(conf.root_dir.as_str(), dbpath)?;
-
-    if args.filedb {
-        alpm.set_dbext(".files");
-    }
-
-    alpm.set_dl_cb((), download_cb);
-    alpm.set_log_cb((), log_cb);
-    alpm.set_event_cb((), event_cb);
-
-    alpm_utils::configure_alpm(&mut alpm, &conf)?;
-
-    if let Some(dir) = args.cachedir.as_deref() {
-        alpm.set_cachedirs(iter::once(dir))?;
+use crate::error::PaccatError;
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+/// The package database operations paccat needs. This is implemented for
+/// Alpm but can be implemented by anything that can provide packages and
+/// their file lists.
+pub trait PkgDb<'a> {
+    type Pkg: Copy;
+
+    fn find_pkg(&'a self, target: &str) -> Result<Self::Pkg>;
+    fn sync_pkgs(&'a self) -> Vec<Self::Pkg>;
+    fn local_pkgs(&'a self) -> Vec<Self::Pkg>;
+    fn sync_pkg(&'a self, pkg: Self::Pkg) -> Option<Self::Pkg>;
+    fn local_pkg(&'a self, pkg: Self::Pkg) -> Option<Self::Pkg>;
+    fn has_files(&'a self, pkg: Self::Pkg) -> bool;
+    fn any_file(&'a self, pkg: Self::Pkg, f: &mut dyn FnMut(&str) -> bool) -> bool;
+    fn download_urls(&'a self, pkg: Self::Pkg) -> Result<Vec<String>>;
+    fn sha256sum(&'a self, pkg: Self::Pkg) -> Option<String>;
+    fn name_version(&'a self, pkg: Self::Pkg) -> (String, String);
+    fn depends(&'a self, pkg: Self::Pkg) -> Vec<String>;
+    fn optdepends(&'a self, pkg: Self::Pkg) -> Vec<String>;
+    fn provides(&'a self, pkg: Self::Pkg) -> Vec<String>;
+    fn pkgbase(&'a self, pkg: Self::Pkg) -> Option<String>;
+    /// The backup files of an installed package with the md5sum they were
+    /// installed with.
+    fn backup(&'a self, pkg: Self::Pkg) -> Vec<(String, String)>;
+    fn is_cached(&'a self, pkg: Self::Pkg) -> bool;
+    fn db_name(&'a self, pkg: Self::Pkg) -> Option<&'a str>;
+    fn repos(&'a self) -> Vec<&'a str>;
+    fn servers(&'a self, repo: &str) -> Vec<String>;
+    fn vercmp(&'a self, a: &str, b: &str) -> Ordering;
+    fn noextracts(&'a self) -> Vec<String>;
+    fn cachedirs(&'a self) -> Vec<String>;
+    fn dbpath(&'a self) -> Option<String>;
+    fn fetch(&'a self, urls: Vec<String>) -> Result<Vec<String>>;
+}
+
+/// Used when every target is a file or url so no database is needed.
+pub struct NoDb;
+
+impl<'a> PkgDb<'a> for NoDb {
+    type Pkg = ();
+
+    fn find_pkg(&'a self, target: &str) -> Result<()> {
+        Err(PaccatError::resolve(target, "is not in any database").into())
+    }
+
+    fn sync_pkgs(&'a self) -> Vec<()> {
+        Vec::new()
+    }
+
+    fn local_pkgs(&'a self) -> Vec<()> {
+        Vec::new()
+    }
+
+    fn sync_pkg(&'a self, _: ()) -> Option<()> {
+        None
+    }
+
+    fn local_pkg(&'a self, _: ()) -> Option<()> {
+        None
+    }
+
+    fn has_files(&'a self, _: ()) -> bool {
+        false
+    }
+
+    fn any_file(&'a self, _: (), _: &mut dyn FnMut(&str) -> bool) -> bool {
+        false
+    }
+
+    fn download_urls(&'a self, _: ()) -> Result<Vec<String>> {
+        bail!("no database")
+    }
+
+    fn sha256sum(&'a self, _: ()) -> Option<String> {
+        None
+    }
+
+    fn name_version(&'a self, _: ()) -> (String, String) {
+        (String::new(), String::new())
+    }
+
+    fn depends(&'a self, _: ()) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn optdepends(&'a self, _: ()) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn provides(&'a self, _: ()) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn pkgbase(&'a self, _: ()) -> Option<String> {
+        None
+    }
+
+    fn backup(&'a self, _: ()) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    fn is_cached(&'a self, _: ()) -> bool {
+        false
+    }
+
+    fn db_name(&'a self, _: ()) -> Option<&'a str> {
+        None
+    }
+
+    fn repos(&'a self) -> Vec<&'a str> {
+        Vec::new()
+    }
+
+    fn servers(&'a self, _: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn vercmp(&'a self, a: &str, b: &str) -> Ordering {
+        a.cmp(b)
+    }
+
+    fn noextracts(&'a self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn cachedirs(&'a self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn dbpath(&'a self) -> Option<String> {
+        None
+    }
+
+    fn fetch(&'a self, _: Vec<String>) -> Result<Vec<String>> {
+        bail!("no database to download from")
+    }
+}
+
+/// Whether the targets can be resolved without the pacman databases.
+pub fn needs_alpm(args: &Args) -> bool {
+    args.localdb
+        || args.filedb
+        || args.list_repos
+        || args.db_info
+        || args.list_duplicates
+        || args.list_changed_configs
+        || args.require_files_db
+        || args.targets.iter().any(|t| !is_file_target(t))
+}
+
+/// Whether what was asked for reads the file lists of the sync databases,
+/// which come from the files databases that pacman -Fy downloads.
+pub fn needs_files_db(args: &Args) -> bool {
+    !args.no_files_db_check
+        && (args.require_files_db
+            || args.db_check
+            || (!args.localdb && (args.filedb || args.list_duplicates)))
+}
+
+/// Fails unless every sync database has file lists, so a scan of them
+/// doesn't come up empty without saying why.
+pub fn check_files_db<'a, D: PkgDb<'a>>(db: &'a D) -> Result<()> {
+    if db.repos().is_empty() {
+        bail!(
+            "there are no sync databases to read the file lists of, check the repos in pacman.conf"
+        );
+    }
+    let pkgs = db.sync_pkgs();
+    let missing = db
+        .repos()
+        .into_iter()
+        .filter(|&repo| {
+            !pkgs
+                .iter()
+                .any(|&pkg| db.db_name(pkg) == Some(repo) && db.has_files(pkg))
+        })
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        bail!(
+            "the sync databases of {} have no file lists, run 'pacman -Fy' to download them \
+             (or pass --no-files-db-check to go on without them)",
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+pub fn is_file_target(target: &str) -> bool {
+    match target_kind(target) {
+        (Some(kind), _) => kind != TargetKind::Pkg,
+        (None, target) => {
+            target.contains("://") || (Path::new(target).is_file() && target.contains(['/', '.']))
+        }
+    }
+}
+
+/// Compares two package versions without a database.
+#[cfg(feature = "pacman")]
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    alpm::vercmp(a, b)
+}
+
+/// pacman's alpm_pkg_vercmp: the epoch, version and pkgrel are compared in
+/// turn, and the pkgrel only when both have one.
+#[cfg(not(feature = "pacman"))]
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (a, b) = (parse_evr(a), parse_evr(b));
+    rpmvercmp(a.0, b.0)
+        .then_with(|| rpmvercmp(a.1, b.1))
+        .then_with(|| match (a.2, b.2) {
+            (Some(a), Some(b)) => rpmvercmp(a, b),
+            _ => Ordering::Equal,
+        })
+}
+
+#[cfg(not(feature = "pacman"))]
+fn trim_zeros(s: &[u8]) -> &[u8] {
+    &s[s.iter().take_while(|&&c| c == b'0').count()..]
+}
+
+/// Splits [epoch:]version[-pkgrel], with an epoch of 0 when there is none.
+#[cfg(not(feature = "pacman"))]
+fn parse_evr(evr: &str) -> (&str, &str, Option<&str>) {
+    let digits = evr.len() - evr.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (epoch, rest) = match evr[digits..].strip_prefix(':') {
+        Some(rest) if digits != 0 => (&evr[..digits], rest),
+        Some(rest) => ("0", rest),
+        None => ("0", evr),
+    };
+    match rest.rsplit_once('-') {
+        Some((version, rel)) => (epoch, version, Some(rel)),
+        None => (epoch, rest, None),
+    }
+}
+
+/// Compares versions a segment at a time like rpm does: runs of digits as
+/// numbers and runs of letters as strings, with the characters between them
+/// only counting by how many there are.
+#[cfg(not(feature = "pacman"))]
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (mut one, mut two) = (a.as_bytes(), b.as_bytes());
+
+    while !one.is_empty() && !two.is_empty() {
+        let skip = |s: &[u8]| s.iter().take_while(|c| !c.is_ascii_alphanumeric()).count();
+        let (sep1, sep2) = (skip(one), skip(two));
+        one = &one[sep1..];
+        two = &two[sep2..];
+        if one.is_empty() || two.is_empty() {
+            break;
+        }
+        if sep1 != sep2 {
+            return sep1.cmp(&sep2);
+        }
+
+        let numeric = one[0].is_ascii_digit();
+        let class = |c: &u8| {
+            if numeric {
+                c.is_ascii_digit()
+            } else {
+                c.is_ascii_alphabetic()
+            }
+        };
+        let len1 = one.iter().take_while(|c| class(c)).count();
+        let len2 = two.iter().take_while(|c| class(c)).count();
+        let (seg1, seg2) = (&one[..len1], &two[..len2]);
+        one = &one[len1..];
+        two = &two[len2..];
+
+        // a number is newer than letters
+        if seg2.is_empty() {
+            return if numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+        let ord = if numeric {
+            let (seg1, seg2) = (trim_zeros(seg1), trim_zeros(seg2));
+            seg1.len().cmp(&seg2.len()).then_with(|| seg1.cmp(seg2))
+        } else {
+            seg1.cmp(seg2)
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+
+    if one.is_empty() && two.is_empty() {
+        return Ordering::Equal;
+    }
+    // what is left of the longer version makes it newer unless it is letters,
+    // so 1.0 is newer than 1.0rc1
+    let alpha = |s: &[u8]| matches!(s.first(), Some(c) if c.is_ascii_alphabetic());
+    if (one.is_empty() && !alpha(two)) || alpha(one) {
+        Ordering::Less
     } else {
-        alpm.add_cachedir(std::env::temp_dir().join("paccat").as_os_str().as_bytes())?;
+        Ordering::Greater
     }
-    Ok(alpm)
 }
 
-pub fn get_dbpkg<'a>(alpm: &'a Alpm, target_str: &str) -> Result<Package<'a>> {
-    let target = Targ::from(target_str);
-    let pkg = alpm
-        .syncdbs()
-        .find_target_satisfier(target)
-        .with_context(|| format!("could not find package: {}", target_str))?;
-    Ok(pkg)
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    Url,
+    File,
+    Pkg,
 }
 
-pub fn get_download_url(pkg: Package) -> Result<String> {
-    let server = pkg
-        .db()
-        .unwrap()
-        .servers()
-        .first()
-        .ok_or(alpm::Error::ServerNone)?;
-    let url = format!("{}/{}", server, pkg.filename());
-    Ok(url)
+/// Splits the `url:`, `file:` or `pkg:` prefix that says what a target is
+/// off it. `file://` urls are left alone.
+pub fn target_kind(target: &str) -> (Option<TargetKind>, &str) {
+    if let Some(url) = target.strip_prefix("url:") {
+        (Some(TargetKind::Url), url)
+    } else if let Some(path) = target
+        .strip_prefix("file:")
+        .filter(|p| !p.starts_with("//"))
+    {
+        (Some(TargetKind::File), path)
+    } else if let Some(pkg) = target.strip_prefix("pkg:") {
+        (Some(TargetKind::Pkg), pkg)
+    } else {
+        (None, target)
+    }
 }
 
-fn download_cb(file: &str, event: AnyDownloadEvent, _: &mut ()) {
-    match event.event() {
-        DownloadEvent::Init(_) => eprintln!("downloading {}...", file),
-        DownloadEvent::Completed(e) => match e.result {
-            DownloadResult::Failed => eprintln!("{} failed to download", file),
-            DownloadResult::UpToDate => eprintln!("{} is up to date", file),
-            _ => (),
-        },
-        _ => (),
+pub fn cachedir(args: &Args) -> PathBuf {
+    match args.cachedir.as_deref() {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::temp_dir().join("paccat"),
     }
 }
 
-fn log_cb(level: LogLevel, msg: &str, _: &mut ()) {
-    match level {
-        LogLevel::WARNING => eprint!("warning: {}", msg),
-        LogLevel::ERROR => eprint!("error: {}", msg),
-        _ => (),
+#[cfg(not(feature = "pacman"))]
+pub fn alpm_init(_: &Args) -> Result<NoDb> {
+    bail!("paccat was built without pacman support, only package files and urls can be used")
+}
+
+/// Splits a target into its repo and package name like alpm_utils::Targ.
+pub fn split_target(target: &str) -> (Option<&str>, &str) {
+    match target.split_once('/') {
+        Some((repo, pkg)) => (Some(repo), pkg.split('/').next().unwrap()),
+        None => (None, target),
     }
 }
 
-fn event_cb(event: AnyEvent, _: &mut ()) {
-    if let Event::DatabaseMissing(e) = event.event() {
-        eprintln!(
-            "database file for {} does not exist (use pacman to download)",
-            e.dbname()
-        );
+/// Returns the local path of a file:// url so it can be read in place
+/// instead of being copied into the cache.
+pub fn file_url_path(url: &str) -> Option<String> {
+    let path = url.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// A package of MemDb. Packages without a repo are installed ones.
+    #[derive(Default)]
+    pub struct MemPkg {
+        pub repo: Option<&'static str>,
+        pub name: &'static str,
+        pub version: &'static str,
+        pub files: Vec<&'static str>,
+        pub depends: Vec<&'static str>,
+        pub provides: Vec<&'static str>,
+    }
+
+    /// A PkgDb held in memory so what uses it can be tested without libalpm.
+    /// Packages are referred to by their index.
+    #[derive(Default)]
+    pub struct MemDb {
+        pub pkgs: Vec<MemPkg>,
+        pub dbpath: Option<String>,
+    }
+
+    impl MemDb {
+        fn find(&self, repo: Option<&str>, name: &str) -> Option<usize> {
+            let in_repo = |p: &MemPkg| p.repo.is_some() && (repo.is_none() || p.repo == repo);
+            let pkgs = || self.pkgs.iter().enumerate().filter(|(_, p)| in_repo(p));
+            pkgs()
+                .find(|(_, p)| p.name == name)
+                .or_else(|| pkgs().find(|(_, p)| p.provides.contains(&name)))
+                .map(|(i, _)| i)
+        }
+    }
+
+    impl<'a> PkgDb<'a> for MemDb {
+        type Pkg = usize;
+
+        fn find_pkg(&'a self, target: &str) -> Result<usize> {
+            let (repo, name) = split_target(target);
+            match self.find(repo, name) {
+                Some(pkg) => Ok(pkg),
+                None => Err(PaccatError::resolve(target, "is not in any database").into()),
+            }
+        }
+
+        fn sync_pkgs(&'a self) -> Vec<usize> {
+            (0..self.pkgs.len())
+                .filter(|&i| self.pkgs[i].repo.is_some())
+                .collect()
+        }
+
+        fn local_pkgs(&'a self) -> Vec<usize> {
+            (0..self.pkgs.len())
+                .filter(|&i| self.pkgs[i].repo.is_none())
+                .collect()
+        }
+
+        fn sync_pkg(&'a self, pkg: usize) -> Option<usize> {
+            self.sync_pkgs()
+                .into_iter()
+                .find(|&i| self.pkgs[i].name == self.pkgs[pkg].name)
+        }
+
+        fn local_pkg(&'a self, pkg: usize) -> Option<usize> {
+            self.local_pkgs()
+                .into_iter()
+                .find(|&i| self.pkgs[i].name == self.pkgs[pkg].name)
+        }
+
+        fn has_files(&'a self, pkg: usize) -> bool {
+            !self.pkgs[pkg].files.is_empty()
+        }
+
+        fn any_file(&'a self, pkg: usize, f: &mut dyn FnMut(&str) -> bool) -> bool {
+            self.pkgs[pkg].files.iter().any(|file| f(file))
+        }
+
+        fn download_urls(&'a self, pkg: usize) -> Result<Vec<String>> {
+            let pkg = &self.pkgs[pkg];
+            match pkg.repo {
+                Some(repo) => Ok(vec![format!(
+                    "https://mirror.test/{}/{}-{}-any.pkg.tar.zst",
+                    repo, pkg.name, pkg.version
+                )]),
+                None => bail!("{} is not in a repo", pkg.name),
+            }
+        }
+
+        fn sha256sum(&'a self, _: usize) -> Option<String> {
+            None
+        }
+
+        fn name_version(&'a self, pkg: usize) -> (String, String) {
+            let pkg = &self.pkgs[pkg];
+            (pkg.name.to_string(), pkg.version.to_string())
+        }
+
+        fn depends(&'a self, pkg: usize) -> Vec<String> {
+            self.pkgs[pkg]
+                .depends
+                .iter()
+                .map(|d| d.to_string())
+                .collect()
+        }
+
+        fn optdepends(&'a self, _: usize) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn provides(&'a self, pkg: usize) -> Vec<String> {
+            self.pkgs[pkg]
+                .provides
+                .iter()
+                .map(|p| p.to_string())
+                .collect()
+        }
+
+        fn pkgbase(&'a self, _: usize) -> Option<String> {
+            None
+        }
+
+        fn backup(&'a self, _: usize) -> Vec<(String, String)> {
+            Vec::new()
+        }
+
+        fn is_cached(&'a self, _: usize) -> bool {
+            false
+        }
+
+        fn db_name(&'a self, pkg: usize) -> Option<&'a str> {
+            self.pkgs[pkg].repo
+        }
+
+        fn repos(&'a self) -> Vec<&'a str> {
+            let mut repos = Vec::new();
+            for repo in self.pkgs.iter().filter_map(|p| p.repo) {
+                if !repos.contains(&repo) {
+                    repos.push(repo);
+                }
+            }
+            repos
+        }
+
+        fn servers(&'a self, repo: &str) -> Vec<String> {
+            vec![format!("https://mirror.test/{}", repo)]
+        }
+
+        fn vercmp(&'a self, a: &str, b: &str) -> Ordering {
+            vercmp(a, b)
+        }
+
+        fn noextracts(&'a self) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn cachedirs(&'a self) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn dbpath(&'a self) -> Option<String> {
+            self.dbpath.clone()
+        }
+
+        fn fetch(&'a self, _: Vec<String>) -> Result<Vec<String>> {
+            bail!("MemDb can't download")
+        }
+    }
+
+    pub fn pkg(repo: Option<&'static str>, name: &'static str, files: &[&'static str]) -> MemPkg {
+        MemPkg {
+            repo,
+            name,
+            version: "1.0-1",
+            files: files.to_vec(),
+            ..MemPkg::default()
+        }
+    }
+
+    #[test]
+    fn files_db_with_file_lists() {
+        let db = MemDb {
+            pkgs: vec![
+                pkg(Some("core"), "coreutils", &["usr/bin/ls"]),
+                pkg(Some("extra"), "vim", &["usr/bin/vim"]),
+            ],
+            ..MemDb::default()
+        };
+        assert!(check_files_db(&db).is_ok());
+    }
+
+    #[test]
+    fn files_db_names_repos_without_file_lists() {
+        let db = MemDb {
+            pkgs: vec![
+                pkg(Some("core"), "coreutils", &["usr/bin/ls"]),
+                pkg(Some("extra"), "vim", &[]),
+                pkg(Some("multilib"), "lib32-glibc", &[]),
+            ],
+            ..MemDb::default()
+        };
+        let err = check_files_db(&db).unwrap_err().to_string();
+        assert!(err.contains("extra, multilib"), "{}", err);
+        assert!(err.contains("pacman -Fy"), "{}", err);
+    }
+
+    #[test]
+    fn files_db_without_repos() {
+        let db = MemDb {
+            pkgs: vec![pkg(None, "coreutils", &["usr/bin/ls"])],
+            ..MemDb::default()
+        };
+        assert!(check_files_db(&db).is_err());
+    }
+
+    // from pacman's vercmptest.sh
+    #[cfg(not(feature = "pacman"))]
+    #[test]
+    fn vercmp_like_pacman() {
+        let cases = [
+            ("1.5.0", "1.5.0", Ordering::Equal),
+            ("1.5.1", "1.5.0", Ordering::Greater),
+            ("1.9-1", "1.10-1", Ordering::Less),
+            ("1.001", "1.1", Ordering::Equal),
+            ("1.5.0-1", "1.5.0-2", Ordering::Less),
+            ("1.5.0-2", "1.5.1-1", Ordering::Less),
+            ("1.5.0-1", "1.5.0", Ordering::Equal),
+            ("1.0", "1.0.0", Ordering::Less),
+            ("1.5b", "1.5", Ordering::Less),
+            ("1.0a", "1.0alpha", Ordering::Less),
+            ("1.0beta", "1.0rc", Ordering::Less),
+            ("1.0rc", "1.0", Ordering::Less),
+            ("1.5.a", "1.5", Ordering::Greater),
+            ("1.5.1", "1.5.b", Ordering::Greater),
+            ("1.5.b-1", "1.5.a-2", Ordering::Greater),
+            ("1.0..1", "1.0.1", Ordering::Greater),
+            ("1:1.0", "2.0", Ordering::Greater),
+            ("0:1.0", "1.0", Ordering::Equal),
+            ("1:1.0-1", "2:1.0-1", Ordering::Less),
+        ];
+        for (a, b, ord) in cases {
+            assert_eq!(vercmp(a, b), ord, "{} {}", a, b);
+            assert_eq!(vercmp(b, a), ord.reverse(), "{} {}", b, a);
+        }
     }
 }
@@ -0,0 +1,33 @@
+use crate::args::Args;
+use anyhow::{Context, Result};
+use nix::unistd::isatty;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::process::{Child, Command, Stdio};
+
+/// Starts the pager for --pager when printing files to a terminal.
+pub fn spawn(args: &Args) -> Result<Option<Child>> {
+    let cmd = match &args.pager {
+        Some(cmd) if !args.quiet && isatty(io::stdout().as_raw_fd()).unwrap_or(false) => cmd,
+        _ => return Ok(None),
+    };
+    let cmd = match cmd {
+        Some(cmd) => cmd.clone(),
+        None => std::env::var("PAGER")
+            .ok()
+            .filter(|p| !p.is_empty())
+            .unwrap_or_else(|| "less".to_string()),
+    };
+
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(&cmd).stdin(Stdio::piped());
+    // like git, quit if it fits on one screen and let colours through
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+
+    command
+        .spawn()
+        .map(Some)
+        .with_context(|| format!("failed to run pager '{}'", cmd))
+}
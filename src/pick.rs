@@ -0,0 +1,93 @@
+use crate::interrupt;
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+const MAX_SHOWN: usize = 50;
+
+pub fn pick_files(names: &[String]) -> Result<Vec<String>> {
+    let mut tty = File::open("/dev/tty").context("failed to open /dev/tty")?;
+    let stderr = std::io::stderr();
+    let mut stderr = stderr.lock();
+
+    let names = names
+        .iter()
+        .filter(|n| !n.ends_with('/'))
+        .collect::<Vec<_>>();
+
+    if names.is_empty() {
+        bail!("no files to pick from");
+    }
+
+    let mut query = String::new();
+
+    loop {
+        let matches = names
+            .iter()
+            .filter(|n| fuzzy_match(&query, n))
+            .collect::<Vec<_>>();
+
+        if matches.is_empty() {
+            writeln!(stderr, "no files match '{}'", query)?;
+        } else {
+            for (i, name) in matches.iter().take(MAX_SHOWN).enumerate() {
+                writeln!(stderr, "{:>3} {}", i + 1, name)?;
+            }
+            if matches.len() > MAX_SHOWN {
+                writeln!(stderr, "... and {} more", matches.len() - MAX_SHOWN)?;
+            }
+        }
+
+        write!(stderr, "select numbers, or type to filter: ")?;
+        stderr.flush()?;
+
+        let line = match read_line(&mut tty)? {
+            Some(line) => line,
+            None => bail!("no files selected"),
+        };
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        match parse_selection(line, matches.len().min(MAX_SHOWN)) {
+            Some(sel) => return Ok(sel.into_iter().map(|i| matches[i].to_string()).collect()),
+            None => query = line.to_string(),
+        }
+    }
+}
+
+/// Reads a line from the terminal, which gives a whole line to each read.
+/// Unlike BufRead::read_line a SIGINT isn't retried but gives up the pick.
+fn read_line(tty: &mut File) -> Result<Option<String>> {
+    let mut line = Vec::new();
+    let mut buf = [0; 1024];
+
+    while !line.ends_with(b"\n") {
+        match tty.read(&mut buf) {
+            Ok(0) if line.is_empty() => return Ok(None),
+            Ok(0) => break,
+            Ok(n) => line.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => interrupt::check()?,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
+
+fn parse_selection(line: &str, max: usize) -> Option<Vec<usize>> {
+    line.split_whitespace()
+        .map(|n| n.parse::<usize>().ok().filter(|&n| n >= 1 && n <= max))
+        .map(|n| n.map(|n| n - 1))
+        .collect()
+}
+
+fn fuzzy_match(query: &str, name: &str) -> bool {
+    let mut name = name.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|q| name.any(|c| c == q))
+}
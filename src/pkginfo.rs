@@ -0,0 +1,44 @@
+use anyhow::{Context, Result};
+use compress_tools::uncompress_archive_file;
+use std::fs::File;
+
+#[derive(Debug, Default)]
+pub struct PkgInfo {
+    pub name: String,
+    pub version: String,
+    pub build_date: i64,
+    pub packager: String,
+    pub backup: Vec<String>,
+}
+
+pub fn read_pkginfo(pkg: &str) -> Result<Option<PkgInfo>> {
+    let file = File::open(pkg).with_context(|| format!("failed to open {}", pkg))?;
+    let mut data = Vec::new();
+    if uncompress_archive_file(file, &mut data, ".PKGINFO").is_err() {
+        return Ok(None);
+    }
+
+    Ok(Some(parse(&String::from_utf8_lossy(&data))))
+}
+
+pub fn parse(pkginfo: &str) -> PkgInfo {
+    let mut info = PkgInfo::default();
+
+    for line in pkginfo.lines() {
+        let (key, val) = match line.split_once(" = ") {
+            Some(kv) if !line.starts_with('#') => kv,
+            _ => continue,
+        };
+
+        match key {
+            "pkgname" => info.name = val.to_string(),
+            "pkgver" => info.version = val.to_string(),
+            "builddate" => info.build_date = val.parse().unwrap_or(0),
+            "packager" => info.packager = val.to_string(),
+            "backup" => info.backup.push(val.to_string()),
+            _ => (),
+        }
+    }
+
+    info
+}
@@ -0,0 +1,48 @@
+use crate::args::Args;
+use anyhow::{bail, Result};
+use nix::fcntl::{fcntl, FcntlArg};
+use nix::unistd::dup;
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::FromRawFd;
+use std::sync::Mutex;
+
+/// Where --progress-json writes its events. It is global so the download
+/// callbacks of libalpm can write to it too.
+static PROGRESS: Mutex<Option<File>> = Mutex::new(None);
+
+/// Opens the fd given with --progress-fd, stderr by default, if
+/// --progress-json was given.
+pub fn init(args: &Args) -> Result<()> {
+    if !args.progress_json {
+        return Ok(());
+    }
+
+    let fd = args.progress_fd;
+    if fd < 0 || fcntl(fd, FcntlArg::F_GETFD).is_err() {
+        bail!("--progress-fd {} is not an open file descriptor", fd);
+    }
+    // dup so closing the file doesn't close stderr
+    let file = unsafe { File::from_raw_fd(dup(fd)?) };
+    *PROGRESS.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+/// Writes an event as a line of JSON. fields are each a key and a value
+/// that is already JSON.
+pub fn event(event: &str, fields: &[(&str, String)]) {
+    let mut progress = PROGRESS.lock().unwrap();
+    let file = match progress.as_mut() {
+        Some(file) => file,
+        None => return,
+    };
+
+    let mut line = format!("{{\"event\": \"{}\"", event);
+    for (key, value) in fields {
+        line.push_str(&format!(", \"{}\": {}", key, value));
+    }
+    line.push_str("}\n");
+
+    // progress is best effort and not worth failing over
+    let _ = file.write_all(line.as_bytes());
+}
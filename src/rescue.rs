@@ -0,0 +1,110 @@
+use crate::args::Args;
+use crate::cache;
+use crate::pacman::{self, split_target, target_kind};
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The database path of the system at --root.
+fn dbpath(args: &Args, root: &str) -> PathBuf {
+    match &args.dbpath {
+        Some(dbpath) => PathBuf::from(dbpath),
+        None => Path::new(root).join("var/lib/pacman"),
+    }
+}
+
+/// Whether --root points at a system without sync databases, such as one
+/// mounted for a rescue, where targets are read from its package cache.
+pub fn degraded(args: &Args) -> bool {
+    let root = match &args.root {
+        Some(root) => root,
+        None => return false,
+    };
+
+    let sync = dbpath(args, root).join("sync");
+    let has_db = |entry: &fs::DirEntry| entry.path().extension().is_some_and(|ext| ext == "db");
+    !fs::read_dir(sync)
+        .map(|mut entries| entries.any(|e| matches!(e, Ok(e) if has_db(&e))))
+        .unwrap_or(false)
+}
+
+/// Turns the package targets into the newest of their files in the cache of
+/// the root and reads -Q from the local database alone. Options that need
+/// the sync databases are an error.
+pub fn resolve(args: &mut Args) -> Result<()> {
+    let root = args.root.clone().unwrap_or_default();
+    let dbpath = dbpath(args, &root);
+    let sync = dbpath.join("sync");
+
+    let needs_sync = [
+        (args.filedb, "-F"),
+        (args.deps, "--deps"),
+        (args.required_by, "--required-by"),
+        (args.split_siblings, "--split-siblings"),
+        (args.updated, "--updated"),
+        (args.testing, "--testing"),
+        (args.db_check, "--db-check"),
+        (args.only_new_files, "--only-new-files"),
+        (args.list_repos, "--list-repos"),
+        (args.db_info, "--db-info"),
+        (args.list_duplicates, "--list-duplicates"),
+        (args.require_files_db, "--require-files-db"),
+        (args.repo_priority.is_some(), "--repo-priority"),
+    ];
+    if let Some((_, flag)) = needs_sync.iter().find(|(given, _)| *given) {
+        bail!(
+            "{} needs the sync databases, which are missing from {}",
+            flag,
+            sync.display()
+        );
+    }
+
+    if args.localdb {
+        let local = dbpath.join("local");
+        if !local.is_dir() {
+            bail!(
+                "-Q needs the local database, which is missing from {}",
+                local.display()
+            );
+        }
+        if !args.targets.is_empty() {
+            bail!(
+                "only -Q without targets works without the sync databases, which are missing from {}",
+                sync.display()
+            );
+        }
+        args.no_sync_db = true;
+    }
+
+    let cachedirs = match &args.cachedir {
+        Some(dir) => vec![dir.clone()],
+        None => vec![Path::new(&root)
+            .join("var/cache/pacman/pkg")
+            .to_string_lossy()
+            .into_owned()],
+    };
+
+    for target in &mut args.targets {
+        if pacman::is_file_target(target) {
+            continue;
+        }
+
+        let (_, name) = split_target(target_kind(target).1);
+        match cache::newest(&cachedirs, name, pacman::vercmp) {
+            Some(cached) => {
+                if args.verbose {
+                    eprintln!("{}: using {} from the cache", target, cached.path);
+                }
+                *target = format!("file:{}", cached.path);
+            }
+            None => bail!(
+                "{} is not in the cache {} and the sync databases are missing from {}",
+                target,
+                cachedirs.join(" "),
+                sync.display()
+            ),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+
+const HEADER: &str = "# paccat resume state";
+
+/// The packages that were already read for --resume.
+pub struct Resume {
+    done: HashSet<String>,
+    file: File,
+}
+
+impl Resume {
+    /// Reads the state from path. A file that is not a state file is
+    /// started over.
+    pub fn open(path: &str) -> Result<Self> {
+        let done = match fs::read(path) {
+            Ok(data) => match parse(&data) {
+                Some(done) => done,
+                None => {
+                    warning!(
+                        "warning: {} is not a resume state file -- starting over",
+                        path
+                    );
+                    HashSet::new()
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path)),
+        };
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .with_context(|| format!("failed to open {}", path))?;
+        writeln!(file, "{}", HEADER)?;
+        for pkg in &done {
+            writeln!(file, "{}", pkg)?;
+        }
+
+        Ok(Resume { done, file })
+    }
+
+    pub fn is_done(&self, pkg: &str) -> bool {
+        self.done.contains(pkg)
+    }
+
+    /// Records that pkg was read.
+    pub fn record(&mut self, pkg: &str) -> Result<()> {
+        writeln!(self.file, "{}", pkg)
+            .and_then(|_| self.file.sync_data())
+            .context("failed to write the resume state")?;
+        self.done.insert(pkg.to_string());
+        Ok(())
+    }
+}
+
+fn parse(data: &[u8]) -> Option<HashSet<String>> {
+    let data = std::str::from_utf8(data).ok()?;
+    let mut lines = data.lines();
+    if lines.next()? != HEADER {
+        return None;
+    }
+
+    Some(
+        lines
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect(),
+    )
+}
@@ -0,0 +1,37 @@
+use std::sync::{Condvar, Mutex};
+
+/// Limits how many threads can hold a permit at once, used to bound how
+/// many archives --jobs decompresses together.
+pub struct Semaphore {
+    permits: Mutex<usize>,
+    freed: Condvar,
+}
+
+pub struct Permit<'a>(&'a Semaphore);
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Semaphore {
+            permits: Mutex::new(permits.max(1)),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Waits until a permit is free. It is given back when the Permit is
+    /// dropped.
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut permits = self.permits.lock().unwrap();
+        while *permits == 0 {
+            permits = self.freed.wait(permits).unwrap();
+        }
+        *permits -= 1;
+        Permit(self)
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.0.permits.lock().unwrap() += 1;
+        self.0.freed.notify_one();
+    }
+}
@@ -0,0 +1,100 @@
+use crate::args::Args;
+use crate::manifest::pkg_name;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Checks the detached signature next to each package against the pacman
+/// keyring with gpg for --require-signature and fails on the first package
+/// that isn't signed by a trusted key. Packages without a .sig are only let
+/// through with --allow-unsigned.
+pub fn check(pkgs: &[String], args: &Args) -> Result<()> {
+    let gpgdir = gpgdir(args);
+    if !pkgs.is_empty() && !gpgdir.is_dir() {
+        bail!(
+            "there is no keyring at {} to verify signatures with (use --gpgdir)",
+            gpgdir.display()
+        );
+    }
+
+    for pkg in pkgs {
+        let name = pkg_name(pkg);
+        let sig = format!("{}.sig", pkg);
+        if !Path::new(&sig).is_file() {
+            if args.allow_unsigned {
+                warning!("warning: {} has no signature, reading it anyway", name);
+                continue;
+            }
+            bail!(
+                "refusing to read {}: it has no signature (use --allow-unsigned to read it anyway)",
+                name
+            );
+        }
+
+        if let Some(problem) = verify(&gpgdir, &sig, pkg)? {
+            bail!("refusing to read {}: {}", name, problem);
+        }
+        if args.verbose {
+            eprintln!("{}: the signature is good", name);
+        }
+    }
+
+    Ok(())
+}
+
+fn gpgdir(args: &Args) -> PathBuf {
+    match &args.gpgdir {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(args.root.as_deref().unwrap_or("/")).join("etc/pacman.d/gnupg"),
+    }
+}
+
+/// Returns why the signature of pkg isn't good enough, if it isn't. Like
+/// pacman's TrustedOnly the key has to be fully trusted by the keyring.
+fn verify(gpgdir: &Path, sig: &str, pkg: &str) -> Result<Option<String>> {
+    let output = Command::new("gpg")
+        .arg("--homedir")
+        .arg(gpgdir)
+        .args([
+            "--batch",
+            "--no-auto-key-retrieve",
+            "--status-fd",
+            "1",
+            "--verify",
+        ])
+        .arg(sig)
+        .arg(pkg)
+        .stdin(Stdio::null())
+        .output()
+        .context("failed to run gpg")?;
+    let status = String::from_utf8_lossy(&output.stdout);
+    let has = |keyword| {
+        status
+            .lines()
+            .any(|line| line.split_whitespace().nth(1) == Some(keyword))
+    };
+
+    let problem = if has("NO_PUBKEY") {
+        "it is signed by a key that is not in the keyring"
+    } else if has("BADSIG") {
+        "the signature is bad, it may have been tampered with"
+    } else if has("EXPKEYSIG") {
+        "it is signed by an expired key"
+    } else if has("REVKEYSIG") {
+        "it is signed by a revoked key"
+    } else if !output.status.success() || !has("GOODSIG") {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr
+            .lines()
+            .last()
+            .unwrap_or("")
+            .trim_start_matches("gpg: ");
+        return Ok(Some(format!("gpg could not verify it: {}", reason)));
+    } else if !(has("TRUST_FULLY") || has("TRUST_ULTIMATE")) {
+        "it is signed by a key that is not trusted by the keyring"
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(problem.to_string()))
+}
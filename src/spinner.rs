@@ -0,0 +1,66 @@
+use crate::args::Args;
+use crate::progress;
+use nix::unistd::isatty;
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+const FRAMES: &[char] = &['|', '/', '-', '\\'];
+
+/// Shows how far the scan of the file lists has got on stderr. Nothing is
+/// shown with --quiet or when stderr is not a terminal, but the progress is
+/// still sent to --progress-json.
+pub struct Spinner {
+    enabled: bool,
+    total: usize,
+    done: usize,
+    frame: usize,
+    last: Option<Instant>,
+}
+
+impl Spinner {
+    pub fn new(args: &Args, total: usize) -> Self {
+        let enabled = !args.quiet && isatty(io::stderr().as_raw_fd()).unwrap_or(false);
+        Spinner {
+            enabled,
+            total,
+            done: 0,
+            frame: 0,
+            last: None,
+        }
+    }
+
+    /// Counts one more package, redrawing at most every 100ms.
+    pub fn tick(&mut self) {
+        self.done += 1;
+        if matches!(self.last, Some(last) if last.elapsed() < Duration::from_millis(100)) {
+            return;
+        }
+
+        self.last = Some(Instant::now());
+        progress::event(
+            "db_scan",
+            &[
+                ("done", self.done.to_string()),
+                ("total", self.total.to_string()),
+            ],
+        );
+        if !self.enabled {
+            return;
+        }
+        self.frame = (self.frame + 1) % FRAMES.len();
+        eprint!(
+            "\r{} scanning package {}/{}",
+            FRAMES[self.frame], self.done, self.total
+        );
+        let _ = io::stderr().flush();
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        if self.enabled && self.last.is_some() {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::json;
+use crate::manifest::pkg_name;
+use crate::Resolution;
+use std::io::{self, Write};
+
+/// What happened to each target, printed at the end with --json or to
+/// --report-fd.
+pub struct Summary {
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    target: String,
+    file: Option<String>,
+    package: Option<String>,
+    read: bool,
+    matched: usize,
+}
+
+impl Summary {
+    pub fn new(resolved: &[Resolution]) -> Self {
+        let entries = resolved
+            .iter()
+            .map(|res| Entry {
+                target: res.target.clone(),
+                file: match res.kind {
+                    "disk" => None,
+                    _ => Some(res.url.as_deref().unwrap_or(&res.target).to_string()),
+                },
+                package: res.package.as_ref().map(|(name, _)| name.clone()),
+                read: false,
+                matched: 0,
+            })
+            .collect();
+
+        Summary { entries }
+    }
+
+    /// Records that the package at url was downloaded to path, which can
+    /// have another name when the url redirected.
+    pub fn downloaded(&mut self, url: &str, path: &str) {
+        for entry in &mut self.entries {
+            if entry.file.as_deref() == Some(url) {
+                entry.file = Some(path.to_string());
+            }
+        }
+    }
+
+    /// Records the matches in a package file.
+    pub fn record_file(&mut self, path: &str, matched: usize) {
+        let name = pkg_name(path);
+        for entry in &mut self.entries {
+            if entry.file.as_deref().map(pkg_name) == Some(name) {
+                entry.read = true;
+                entry.matched += matched;
+            }
+        }
+    }
+
+    /// Records the matches in a package read from disk.
+    pub fn record_disk(&mut self, pkg: &str, matched: usize) {
+        for entry in &mut self.entries {
+            if entry.file.is_none() && entry.package.as_deref() == Some(pkg) {
+                entry.read = true;
+                entry.matched += matched;
+            }
+        }
+    }
+
+    /// Prints how many files matched in each package that had any and in
+    /// total for --summary-only. Returns 1 if nothing matched.
+    pub fn print_table<W: Write>(
+        out: &mut W,
+        rows: &[(String, usize)],
+        json: bool,
+    ) -> io::Result<i32> {
+        let total = rows.iter().map(|(_, matched)| matched).sum::<usize>();
+
+        if json {
+            let packages = rows
+                .iter()
+                .map(|(pkg, matched)| {
+                    format!(
+                        "{{\"package\": {}, \"matched\": {}}}",
+                        json::string(pkg),
+                        matched
+                    )
+                })
+                .collect::<Vec<_>>();
+            writeln!(
+                out,
+                "{{\"packages\": [{}], \"matched\": {}}}",
+                packages.join(", "),
+                total
+            )?;
+        } else {
+            for (pkg, matched) in rows {
+                writeln!(out, "{}\t{}", pkg, matched)?;
+            }
+            let packages = if rows.len() == 1 {
+                "package"
+            } else {
+                "packages"
+            };
+            writeln!(out, "total\t{} in {} {}", total, rows.len(), packages)?;
+        }
+
+        Ok(if total == 0 { 1 } else { 0 })
+    }
+
+    pub fn print<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{{\"summary\": {{{}}}}}", self.targets())
+    }
+
+    /// Prints the report for --report-fd and --report-file.
+    pub fn report<W: Write>(
+        &self,
+        out: &mut W,
+        matched: usize,
+        bytes_read: u64,
+        status: i32,
+    ) -> io::Result<()> {
+        writeln!(
+            out,
+            "{{\"targets\": {{{}}}, \"matched\": {}, \"bytes_read\": {}, \"status\": {}}}",
+            self.targets(),
+            matched,
+            bytes_read,
+            status
+        )
+    }
+
+    /// The targets whose packages were all read without matching anything
+    /// for --show-misses.
+    pub fn misses(&self) -> Vec<(&str, usize)> {
+        self.tally()
+            .into_iter()
+            .filter(|&(_, packages, read, matched)| read != 0 && read == packages && matched == 0)
+            .map(|(target, _, read, _)| (target, read))
+            .collect()
+    }
+
+    fn targets(&self) -> String {
+        let targets = self
+            .tally()
+            .iter()
+            .map(|(target, packages, read, matched)| {
+                format!(
+                    "{}: {{\"packages\": {}, \"read\": {}, \"matched\": {}}}",
+                    json::string(target),
+                    packages,
+                    read,
+                    matched
+                )
+            })
+            .collect::<Vec<_>>();
+
+        targets.join(", ")
+    }
+
+    /// How many packages each target resolved to, how many of them were
+    /// read and the files matched in them.
+    fn tally(&self) -> Vec<(&str, usize, usize, usize)> {
+        let mut targets: Vec<(&str, usize, usize, usize)> = Vec::new();
+
+        for entry in &self.entries {
+            let i = match targets.iter().position(|t| t.0 == entry.target) {
+                Some(i) => i,
+                None => {
+                    targets.push((&entry.target, 0, 0, 0));
+                    targets.len() - 1
+                }
+            };
+            targets[i].1 += 1;
+            targets[i].2 += entry.read as usize;
+            targets[i].3 += entry.matched;
+        }
+
+        targets
+    }
+}
@@ -0,0 +1,194 @@
+use crate::args::{Args, Compression};
+use crate::mtree::{Entry, Kind};
+use anyhow::{bail, Context, Result};
+use nix::unistd::isatty;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::process::{Child, Command, Stdio};
+
+/// The two zero blocks that end a tar.
+pub const END: [u8; 1024] = [0; 1024];
+
+/// Writes an entry of the tar for --tar. The type, mode, owner and mtime
+/// come from the .MTREE entry if there is one, otherwise the entry is a
+/// file or a directory owned by root.
+pub fn entry<W: io::Write>(
+    out: &mut W,
+    path: &str,
+    mtree: Option<&Entry>,
+    data: &[u8],
+) -> io::Result<()> {
+    let dir = path.ends_with('/') || matches!(mtree, Some(e) if e.kind == Kind::Dir);
+    let (kind, mode) = match mtree {
+        Some(e) if e.kind == Kind::Link => (b'2', e.mode),
+        Some(e) => (if dir { b'5' } else { b'0' }, e.mode),
+        None if dir => (b'5', 0o755),
+        None => (b'0', 0o644),
+    };
+    let link = match mtree {
+        Some(e) if kind == b'2' => e.link.as_deref().unwrap_or(""),
+        _ => "",
+    };
+    let data = if kind == b'0' { data } else { &[] };
+    let path = if dir && !path.ends_with('/') {
+        format!("{}/", path)
+    } else {
+        path.to_string()
+    };
+
+    let mut header = Header {
+        path: &path,
+        kind,
+        size: data.len() as u64,
+        mode,
+        uid: mtree.map_or(0, |e| e.uid),
+        gid: mtree.map_or(0, |e| e.gid),
+        mtime: mtree.map_or(0, |e| e.time),
+        link,
+    };
+
+    // GNU long name records for what doesn't fit in the header
+    if link.len() > 100 {
+        long_name(out, b'K', link)?;
+        header.link = truncate(link, 100);
+    }
+    if path.len() > 100 {
+        long_name(out, b'L', &path)?;
+        header.path = truncate(&path, 100);
+    }
+
+    out.write_all(&header.encode())?;
+    out.write_all(data)?;
+    out.write_all(&END[..padding(data.len())])
+}
+
+struct Header<'a> {
+    path: &'a str,
+    kind: u8,
+    size: u64,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    mtime: i64,
+    link: &'a str,
+}
+
+impl Header<'_> {
+    fn encode(&self) -> [u8; 512] {
+        let mut block = [0; 512];
+        put(&mut block[..100], self.path.as_bytes());
+        octal(&mut block[100..108], self.mode as u64 & 0o7777);
+        octal(&mut block[108..116], self.uid as u64);
+        octal(&mut block[116..124], self.gid as u64);
+        octal(&mut block[124..136], self.size);
+        octal(&mut block[136..148], self.mtime.max(0) as u64);
+        block[156] = self.kind;
+        put(&mut block[157..257], self.link.as_bytes());
+        put(&mut block[257..265], b"ustar\x0000");
+        if self.uid == 0 {
+            put(&mut block[265..297], b"root");
+        }
+        if self.gid == 0 {
+            put(&mut block[297..329], b"root");
+        }
+
+        // the checksum is summed with its own field as spaces
+        block[148..156].copy_from_slice(b"        ");
+        let sum = block.iter().map(|&b| b as u32).sum::<u32>();
+        put(&mut block[148..156], format!("{:06o}\0 ", sum).as_bytes());
+        block
+    }
+}
+
+fn long_name<W: io::Write>(out: &mut W, kind: u8, name: &str) -> io::Result<()> {
+    let mut data = name.as_bytes().to_vec();
+    data.push(0);
+    let header = Header {
+        path: "././@LongLink",
+        kind,
+        size: data.len() as u64,
+        mode: 0o644,
+        uid: 0,
+        gid: 0,
+        mtime: 0,
+        link: "",
+    };
+    out.write_all(&header.encode())?;
+    out.write_all(&data)?;
+    out.write_all(&END[..padding(data.len())])
+}
+
+fn truncate(s: &str, len: usize) -> &str {
+    let mut end = len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn put(field: &mut [u8], value: &[u8]) {
+    let len = value.len().min(field.len());
+    field[..len].copy_from_slice(&value[..len]);
+}
+
+/// Writes n as zero padded octal ending in a NUL, or as big endian binary
+/// with the high bit set when it doesn't fit.
+fn octal(field: &mut [u8], n: u64) {
+    let digits = field.len() - 1;
+    if n < 1 << (3 * digits) {
+        put(
+            field,
+            format!("{:0width$o}\0", n, width = digits).as_bytes(),
+        );
+    } else {
+        let bytes = n.to_be_bytes();
+        field.iter_mut().for_each(|b| *b = 0);
+        let start = field.len() - bytes.len();
+        field[start..].copy_from_slice(&bytes);
+        field[0] |= 0x80;
+    }
+}
+
+fn padding(len: usize) -> usize {
+    (512 - len % 512) % 512
+}
+
+/// Starts the compressor for --compress that the tar is written through.
+/// A tar is never written to a terminal, compressed or not.
+pub fn compressor(args: &Args) -> Result<Option<Child>> {
+    if args.tar && isatty(io::stdout().as_raw_fd()).unwrap_or(false) {
+        bail!("refusing to write a tar to a terminal, redirect stdout to a file");
+    }
+
+    let compression = match args.compress {
+        Some(compression) => compression,
+        None => return Ok(None),
+    };
+    let (program, levels) = match compression {
+        Compression::Gzip => ("gzip", 1..=9),
+        Compression::Bzip2 => ("bzip2", 1..=9),
+        Compression::Xz => ("xz", 0..=9),
+        Compression::Zstd => ("zstd", 1..=19),
+    };
+
+    let mut cmd = Command::new(program);
+    cmd.arg("-c").stdin(Stdio::piped());
+    if let Some(level) = args.compress_level {
+        if !levels.contains(&level) {
+            bail!(
+                "--compress-level for {} must be from {} to {}",
+                program,
+                levels.start(),
+                levels.end()
+            );
+        }
+        cmd.arg(format!("-{}", level));
+    }
+    if compression == Compression::Zstd {
+        cmd.arg("-q");
+    }
+
+    cmd.spawn()
+        .map(Some)
+        .with_context(|| format!("failed to run {}", program))
+}
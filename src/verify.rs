@@ -0,0 +1,76 @@
+use crate::args::{Args, Checksum};
+use crate::hash::Hasher;
+use crate::json;
+use crate::manifest::pkg_name;
+use anyhow::Result;
+use compress_tools::{ArchiveContents, ArchiveIterator};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+/// Checks each package against the sha256sum from its database and reads
+/// it to the end to make sure it isn't truncated, for --verify-only.
+/// Packages that aren't from a database only get the second check.
+pub fn verify(pkgs: &[String], sums: &HashMap<String, String>, args: &Args) -> Result<i32> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut ret = 0;
+
+    for pkg in pkgs {
+        let name = pkg_name(pkg);
+        let problem = match check(pkg, sums.get(name)) {
+            Ok(problem) => problem,
+            Err(e) => Some(e.to_string()),
+        };
+        if problem.is_some() {
+            ret = 1;
+        }
+
+        if args.json {
+            writeln!(
+                stdout,
+                "{{\"package\": {}, \"ok\": {}, \"error\": {}}}",
+                json::string(name),
+                problem.is_none(),
+                problem.as_deref().map_or("null".to_string(), json::string)
+            )?;
+        } else {
+            match problem {
+                Some(problem) => writeln!(stdout, "{}\tFAILED\t{}", name, problem)?,
+                None => writeln!(stdout, "{}\tOK", name)?,
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+fn check(pkg: &str, sum: Option<&String>) -> Result<Option<String>> {
+    if let Some(sum) = sum {
+        let mut file = File::open(pkg)?;
+        let mut hasher = Hasher::new(Checksum::Sha256);
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            match file.read(&mut buf)? {
+                0 => break,
+                n => hasher.update(&buf[..n]),
+            }
+        }
+
+        let hex = hasher.finish();
+        if &hex != sum {
+            return Ok(Some(format!(
+                "sha256sum is {} but the database has {}",
+                hex, sum
+            )));
+        }
+    }
+
+    for content in ArchiveIterator::from_read(File::open(pkg)?)? {
+        if let ArchiveContents::Err(e) = content {
+            return Ok(Some(format!("the archive is corrupt: {}", e)));
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,14 @@
+use crate::args::Args;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether --quiet-errors was given. It is global like --progress-json so
+/// the warning! macro works anywhere, including in libalpm's callbacks.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+pub fn init(args: &Args) {
+    QUIET.store(args.quiet_errors, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    !QUIET.load(Ordering::Relaxed)
+}
@@ -0,0 +1,68 @@
+use nix::libc;
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+nix::ioctl_read_bad!(tiocgwinsz, libc::TIOCGWINSZ, libc::winsize);
+
+/// The width of the terminal on stdout, falling back to $COLUMNS.
+pub fn terminal_width() -> Option<usize> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+
+    match unsafe { tiocgwinsz(io::stdout().as_raw_fd(), &mut size) } {
+        Ok(_) if size.ws_col != 0 => Some(size.ws_col as usize),
+        _ => std::env::var("COLUMNS").ok()?.parse().ok(),
+    }
+}
+
+/// Wraps lines longer than width at the last space that fits, splitting
+/// words that are longer than the width on their own.
+pub fn wrap(text: &str, width: usize) -> String {
+    let width = width.max(1);
+    let mut out = String::with_capacity(text.len());
+
+    for line in text.split_inclusive('\n') {
+        let (mut rest, end) = match line.strip_suffix('\n') {
+            Some(line) => (line, "\n"),
+            None => (line, ""),
+        };
+
+        while rest.chars().count() > width {
+            let (limit, c) = rest.char_indices().nth(width).unwrap();
+            let (head, tail) = match rest[..limit + c.len_utf8()].rfind(' ') {
+                Some(i) if i != 0 => (&rest[..i], &rest[i + 1..]),
+                _ => (&rest[..limit], &rest[limit..]),
+            };
+            out.push_str(head.trim_end());
+            out.push('\n');
+            rest = tail;
+        }
+
+        out.push_str(rest);
+        out.push_str(end);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_at_spaces() {
+        assert_eq!(wrap("aaa bb cc\n", 6), "aaa bb\ncc\n");
+        assert_eq!(wrap("aaaaaaaa bb", 3), "aaa\naaa\naa\nbb");
+    }
+
+    #[test]
+    fn wraps_multibyte_text() {
+        assert_eq!(wrap("aaaé bb", 3), "aaa\né\nbb");
+        assert_eq!(wrap("ééé ééé", 4), "ééé\nééé");
+        assert_eq!(wrap("日本語のテキスト", 3), "日本語\nのテキ\nスト");
+    }
+}
@@ -0,0 +1,205 @@
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A directory under the temp dir that is removed again on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::SeqCst);
+        let dir = env::temp_dir().join(format!(
+            "paccat-test-{}-{}-{}",
+            name,
+            std::process::id(),
+            count
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+struct Member {
+    name: Vec<u8>,
+    kind: u8,
+    data: Vec<u8>,
+    link: Vec<u8>,
+    mode: u32,
+}
+
+/// A package archive for the tests, written as an uncompressed tar with a
+/// .PKGINFO like makepkg makes.
+pub struct Pkg {
+    members: Vec<Member>,
+}
+
+impl Pkg {
+    pub fn new(name: &str) -> Self {
+        let pkginfo = format!("pkgname = {}\npkgver = 1.0-1\n", name);
+        let mut pkg = Pkg {
+            members: Vec::new(),
+        };
+        pkg.push(b".PKGINFO", b'0', pkginfo.as_bytes(), b"", 0o644);
+        pkg
+    }
+
+    /// An archive without a .PKGINFO, like a sync db.
+    pub fn bare() -> Self {
+        Pkg {
+            members: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, name: &[u8], kind: u8, data: &[u8], link: &[u8], mode: u32) {
+        self.members.push(Member {
+            name: name.to_vec(),
+            kind,
+            data: data.to_vec(),
+            link: link.to_vec(),
+            mode,
+        });
+    }
+
+    pub fn file(mut self, path: &str, data: &[u8]) -> Self {
+        self.push(path.as_bytes(), b'0', data, b"", 0o644);
+        self
+    }
+
+    /// A file whose name is any bytes, not just UTF-8.
+    pub fn raw_file(mut self, path: &[u8], data: &[u8]) -> Self {
+        self.push(path, b'0', data, b"", 0o644);
+        self
+    }
+
+    pub fn dir(mut self, path: &str) -> Self {
+        self.push(path.as_bytes(), b'5', b"", b"", 0o755);
+        self
+    }
+
+    /// A hardlink, which carries no data in a tar.
+    pub fn hardlink(mut self, path: &str, target: &str) -> Self {
+        self.push(path.as_bytes(), b'1', b"", target.as_bytes(), 0o644);
+        self
+    }
+
+    /// A .MTREE, compressed with the given command if there is one.
+    pub fn mtree(mut self, mtree: &str, compress: Option<&str>) -> Self {
+        let data = match compress {
+            Some(cmd) => compress_with(cmd, mtree.as_bytes()),
+            None => mtree.as_bytes().to_vec(),
+        };
+        self.push(b".MTREE", b'0', &data, b"", 0o644);
+        self
+    }
+
+    pub fn tar(&self) -> Vec<u8> {
+        let mut tar = Vec::new();
+        for member in &self.members {
+            tar.extend_from_slice(&header(member));
+            tar.extend_from_slice(&member.data);
+            tar.resize(tar.len() + (512 - member.data.len() % 512) % 512, 0);
+        }
+        tar.resize(tar.len() + 1024, 0);
+        tar
+    }
+
+    /// Writes the package to dir as name, compressed by the command if there
+    /// is one.
+    pub fn write(&self, dir: &Path, name: &str, compress: Option<&str>) -> PathBuf {
+        let path = dir.join(name);
+        let tar = self.tar();
+        let data = match compress {
+            Some(cmd) => compress_with(cmd, &tar),
+            None => tar,
+        };
+        fs::write(&path, data).unwrap();
+        path
+    }
+}
+
+fn header(member: &Member) -> [u8; 512] {
+    let mut block = [0; 512];
+    let put = |block: &mut [u8; 512], at: usize, value: &[u8]| {
+        block[at..at + value.len()].copy_from_slice(value);
+    };
+
+    assert!(member.name.len() <= 100 && member.link.len() <= 100);
+    put(&mut block, 0, &member.name);
+    put(&mut block, 100, format!("{:07o}\0", member.mode).as_bytes());
+    put(&mut block, 108, b"0000000\0");
+    put(&mut block, 116, b"0000000\0");
+    put(
+        &mut block,
+        124,
+        format!("{:011o}\0", member.data.len()).as_bytes(),
+    );
+    put(&mut block, 136, b"00000000000\0");
+    block[156] = member.kind;
+    put(&mut block, 157, &member.link);
+    put(&mut block, 257, b"ustar\x0000");
+
+    put(&mut block, 148, b"        ");
+    let sum = block.iter().map(|&b| b as u32).sum::<u32>();
+    put(&mut block, 148, format!("{:06o}\0 ", sum).as_bytes());
+    block
+}
+
+/// Runs a compressor like gzip or zstd over data.
+pub fn compress_with(cmd: &str, data: &[u8]) -> Vec<u8> {
+    let mut child = Command::new(cmd)
+        .arg("-c")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap_or_else(|e| panic!("failed to run {}: {}", cmd, e));
+    child.stdin.take().unwrap().write_all(data).unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success(), "{} failed", cmd);
+    out.stdout
+}
+
+/// Runs paccat in dir without PACCAT_OPTS from the environment.
+pub fn paccat<I, S>(dir: &Path, args: I) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    Command::new(env!("CARGO_BIN_EXE_paccat"))
+        .args(args)
+        .current_dir(dir)
+        .env_remove("PACCAT_OPTS")
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+pub fn stdout(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stdout).into_owned()
+}
+
+pub fn stderr(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stderr).into_owned()
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use common::{compress_with, paccat, stderr, stdout, Pkg, TempDir};
+use std::fs;
+
+fn pkg() -> Pkg {
+    Pkg::new("foo")
+        .dir("usr/")
+        .file("usr/a", &vec![b'a'; 200_000])
+        .file("usr/b", b"b\n")
+}
+
+#[test]
+fn reads_a_whole_zstd_package() {
+    let dir = TempDir::new("corrupt");
+    pkg().write(dir.path(), "foo.pkg.tar.zst", Some("zstd"));
+    let out = paccat(dir.path(), ["foo.pkg.tar.zst", "--", "usr/b"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(stdout(&out), "b\n");
+}
+
+#[test]
+fn names_a_truncated_package() {
+    let dir = TempDir::new("corrupt");
+    let data = compress_with("zstd", &pkg().tar());
+    fs::write(dir.join("foo.pkg.tar.zst"), &data[..data.len() / 2]).unwrap();
+
+    let out = paccat(dir.path(), ["foo.pkg.tar.zst", "--", "usr/b"]);
+    assert!(!out.status.success());
+    let err = stderr(&out);
+    assert!(err.contains("failed to read foo.pkg.tar.zst"), "{}", err);
+    assert!(err.contains("may be truncated or corrupt"), "{}", err);
+}
+
+#[test]
+fn streams_through_the_archive_format_decompressor() {
+    let dir = TempDir::new("corrupt");
+    pkg().write(dir.path(), "foo.pkg.tar.zst", Some("zstd"));
+    let out = paccat(
+        dir.path(),
+        [
+            "--archive-format",
+            "zstd",
+            "foo.pkg.tar.zst",
+            "--",
+            "usr/a",
+            "usr/b",
+        ],
+    );
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(out.stdout, [&vec![b'a'; 200_000][..], b"b\n"].concat());
+}
+
+#[test]
+fn names_a_package_the_decompressor_rejects() {
+    let dir = TempDir::new("corrupt");
+    pkg().write(dir.path(), "foo.pkg.tar.zst", Some("zstd"));
+    let out = paccat(
+        dir.path(),
+        ["--archive-format", "gzip", "foo.pkg.tar.zst", "--", "usr/b"],
+    );
+    assert!(!out.status.success());
+    let err = stderr(&out);
+    assert!(
+        err.contains("failed to decompress foo.pkg.tar.zst as gzip"),
+        "{}",
+        err
+    );
+}
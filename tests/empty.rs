@@ -0,0 +1,51 @@
+mod common;
+
+use common::{paccat, stderr, stdout, Pkg, TempDir};
+
+fn fixture() -> TempDir {
+    let dir = TempDir::new("empty");
+    Pkg::new("foo")
+        .dir("etc/")
+        .file("etc/empty.conf", b"")
+        .file("etc/foo.conf", b"foo\n")
+        .write(dir.path(), "foo.pkg.tar", None);
+    dir
+}
+
+#[test]
+fn empty_files_match_by_default() {
+    let dir = fixture();
+    for flags in [&[][..], &["--include-empty"][..]] {
+        let out = paccat(
+            dir.path(),
+            [flags, &["-q", "foo.pkg.tar", "--", "etc/empty.conf"]].concat(),
+        );
+        assert!(out.status.success(), "{:?}: {}", flags, stderr(&out));
+        assert_eq!(stdout(&out), "etc/empty.conf\n");
+    }
+}
+
+#[test]
+fn skip_empty_does_not_count_empty_files() {
+    let dir = fixture();
+    let out = paccat(
+        dir.path(),
+        ["--skip-empty", "-q", "foo.pkg.tar", "--", "etc/empty.conf"],
+    );
+    assert_eq!(out.status.code(), Some(1), "{}", stderr(&out));
+    assert_eq!(stdout(&out), "");
+
+    let out = paccat(
+        dir.path(),
+        [
+            "--skip-empty",
+            "-q",
+            "foo.pkg.tar",
+            "--regex",
+            "--",
+            "etc/.*",
+        ],
+    );
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(stdout(&out), "etc/foo.conf\n");
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::{paccat, stderr, stdout, Pkg, TempDir};
+
+// the sha256 only has to be the same for both paths
+const MTREE: &str = "#mtree
+/set type=file uid=0 gid=0 mode=644
+./usr time=1600000000.0 mode=755 type=dir
+./usr/bin time=1600000000.0 mode=755 type=dir
+./usr/bin/a time=1600000000.0 size=6 sha256digest=1111111111111111111111111111111111111111111111111111111111111111
+./usr/bin/b time=1600000000.0 size=6 sha256digest=1111111111111111111111111111111111111111111111111111111111111111
+";
+
+fn fixture() -> TempDir {
+    let dir = TempDir::new("hardlink");
+    Pkg::new("foo")
+        .mtree(MTREE, None)
+        .dir("usr/")
+        .dir("usr/bin/")
+        .file("usr/bin/a", b"hello\n")
+        .hardlink("usr/bin/b", "usr/bin/a")
+        .write(dir.path(), "foo.pkg.tar", None);
+    dir
+}
+
+#[test]
+fn prints_every_link_path() {
+    let dir = fixture();
+    for files in [&["usr/bin/b"][..], &["usr/bin/a", "usr/bin/b"][..]] {
+        let out = paccat(dir.path(), [&["foo.pkg.tar", "--"][..], files].concat());
+        assert!(out.status.success(), "{:?}: {}", files, stderr(&out));
+        assert_eq!(stdout(&out), "hello\n".repeat(files.len()), "{:?}", files);
+    }
+}
+
+#[test]
+fn no_resolve_hardlinks_leaves_links_empty() {
+    let dir = fixture();
+    let out = paccat(
+        dir.path(),
+        ["--no-resolve-hardlinks", "foo.pkg.tar", "--", "usr/bin/b"],
+    );
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(stdout(&out), "");
+}
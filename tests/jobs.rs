@@ -0,0 +1,71 @@
+mod common;
+
+use common::{paccat, stderr, Pkg, TempDir};
+use std::path::Path;
+
+/// Packages with a few files each whose content names the package.
+fn fixture(dir: &Path, count: usize, size: usize) -> Vec<String> {
+    (0..count)
+        .map(|i| {
+            let name = format!("pkg{}", i);
+            let mut pkg = Pkg::new(&name).dir("usr/").dir("usr/share/");
+            for f in 0..3 {
+                let line = format!("{} file {}\n", name, f);
+                let data = line.repeat(size / line.len() + 1);
+                pkg = pkg.file(&format!("usr/share/{}", f), data.as_bytes());
+            }
+            let file = format!("{}.pkg.tar", name);
+            pkg.write(dir, &file, None);
+            file
+        })
+        .collect()
+}
+
+fn read(dir: &Path, flags: &[&str], pkgs: &[String]) -> Vec<u8> {
+    let pkgs = pkgs.iter().map(String::as_str).collect::<Vec<_>>();
+    let out = paccat(
+        dir,
+        [flags, &pkgs, &["--regex", "--", "usr/share/.*"]].concat(),
+    );
+    assert!(out.status.success(), "{:?}: {}", flags, stderr(&out));
+    out.stdout
+}
+
+#[test]
+fn jobs_print_what_one_job_prints() {
+    let dir = TempDir::new("jobs");
+    let pkgs = fixture(dir.path(), 7, 1000);
+    let sequential = read(dir.path(), &[], &pkgs);
+    assert!(sequential.starts_with(b"pkg0 file 0\n"));
+    assert!(sequential.ends_with(b"pkg6 file 2\n"));
+    for jobs in ["2", "3", "8"] {
+        let parallel = read(dir.path(), &["--jobs", jobs], &pkgs);
+        assert!(
+            parallel == sequential,
+            "--jobs {} printed another order",
+            jobs
+        );
+    }
+}
+
+#[test]
+fn resume_with_nothing_left() {
+    let dir = TempDir::new("jobs");
+    let pkgs = fixture(dir.path(), 2, 10);
+    for jobs in ["1", "2"] {
+        let state = format!("state{}", jobs);
+        let flags = ["--jobs", jobs, "--resume", &state];
+        assert!(!read(dir.path(), &flags, &pkgs).is_empty());
+        assert!(read(dir.path(), &flags, &pkgs).is_empty());
+    }
+}
+
+#[test]
+fn max_concurrent_archives_prints_what_one_job_prints() {
+    let dir = TempDir::new("jobs");
+    let pkgs = fixture(dir.path(), 8, 700_000);
+    let sequential = read(dir.path(), &[], &pkgs);
+    assert!(sequential.len() > 8 * (2 << 20));
+    let flags = ["--jobs", "6", "--max-concurrent-archives", "2"];
+    assert!(read(dir.path(), &flags, &pkgs) == sequential);
+}
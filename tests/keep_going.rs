@@ -0,0 +1,57 @@
+mod common;
+
+use common::{compress_with, paccat, stderr, stdout, Pkg, TempDir};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+fn pkg(name: &str) -> Pkg {
+    Pkg::new(name).dir("usr/").file("usr/a", b"a\n")
+}
+
+#[test]
+fn keep_going_skips_a_corrupt_package() {
+    let dir = TempDir::new("keep-going");
+    let data = compress_with("zstd", &pkg("bad").tar());
+    fs::write(dir.join("bad.pkg.tar.zst"), &data[..data.len() / 2]).unwrap();
+    pkg("good").write(dir.path(), "good.pkg.tar", None);
+
+    let args = ["bad.pkg.tar.zst", "good.pkg.tar", "--", "usr/a"];
+    let out = paccat(dir.path(), args);
+    assert!(!out.status.success());
+    assert_eq!(stdout(&out), "");
+
+    let out = paccat(dir.path(), [&["--keep-going"][..], &args].concat());
+    assert_eq!(stdout(&out), "a\n");
+    let err = stderr(&out);
+    assert!(
+        err.contains("error: failed to read bad.pkg.tar.zst"),
+        "{}",
+        err
+    );
+    assert!(err.contains("-- skipping"), "{}", err);
+}
+
+#[test]
+fn keep_going_skips_an_unreadable_package() {
+    if nix::unistd::geteuid().is_root() {
+        eprintln!("root can read any file, skipping");
+        return;
+    }
+
+    let dir = TempDir::new("keep-going");
+    let bad = pkg("bad").write(dir.path(), "bad.pkg.tar", None);
+    fs::set_permissions(&bad, fs::Permissions::from_mode(0o000)).unwrap();
+    pkg("good").write(dir.path(), "good.pkg.tar", None);
+
+    let out = paccat(
+        dir.path(),
+        ["--keep-going", "bad.pkg.tar", "good.pkg.tar", "--", "usr/a"],
+    );
+    assert_eq!(stdout(&out), "a\n");
+    let err = stderr(&out);
+    assert!(
+        err.contains("error: failed to open bad.pkg.tar: Permission denied"),
+        "{}",
+        err
+    );
+}
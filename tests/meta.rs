@@ -0,0 +1,31 @@
+mod common;
+
+use common::{paccat, stderr, stdout, Pkg, TempDir};
+
+fn list(flags: &[&str]) -> String {
+    let dir = TempDir::new("meta");
+    Pkg::new("foo")
+        .mtree("#mtree\n", None)
+        .file(".BUILDINFO", b"format = 2\n")
+        .dir("usr/")
+        .file("usr/foo", b"foo\n")
+        .write(dir.path(), "foo.pkg.tar", None);
+
+    let args = [flags, &["-q", "foo.pkg.tar", "--regex", "--", ".*"]].concat();
+    let out = paccat(dir.path(), args);
+    assert!(out.status.success(), "{}", stderr(&out));
+    stdout(&out)
+}
+
+#[test]
+fn regex_skips_meta_members() {
+    assert_eq!(list(&[]), "usr/foo\n");
+}
+
+#[test]
+fn include_meta_members_matches_them() {
+    assert_eq!(
+        list(&["--include-meta-members"]),
+        ".PKGINFO\n.MTREE\n.BUILDINFO\nusr/foo\n"
+    );
+}
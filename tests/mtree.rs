@@ -0,0 +1,70 @@
+mod common;
+
+use common::{paccat, stderr, stdout, Pkg, TempDir};
+
+const MTREE: &str = "#mtree
+/set type=file uid=0 gid=0 mode=644
+./.PKGINFO time=1600000000.0 size=28
+./usr time=1600000000.0 mode=755 type=dir
+./usr/foo time=1600000000.0 size=4
+";
+
+fn manifest(compress: Option<&str>) -> String {
+    let dir = TempDir::new("mtree");
+    Pkg::new("foo")
+        .mtree(MTREE, compress)
+        .dir("usr/")
+        .file("usr/foo", b"foo\n")
+        .write(dir.path(), "foo.pkg.tar", None);
+
+    let out = paccat(dir.path(), ["--manifest", "foo.pkg.tar"]);
+    assert!(out.status.success(), "{:?}: {}", compress, stderr(&out));
+    stdout(&out)
+}
+
+#[test]
+fn reads_the_mtree_however_it_is_compressed() {
+    let expected = "foo.pkg.tar\t28\t0644\tfile\t.PKGINFO\n\
+                    foo.pkg.tar\t4\t0644\tfile\tusr/foo\n\
+                    foo.pkg.tar\t0\t0755\tdir\tusr\n";
+    assert_eq!(manifest(None), expected);
+    for compress in ["gzip", "zstd", "xz"] {
+        assert_eq!(manifest(Some(compress)), expected, "{}", compress);
+    }
+}
+
+#[test]
+fn select_newest_prints_all_files_of_packages_without_an_mtree() {
+    let dir = TempDir::new("mtree");
+    for (name, time) in [("old", 1600000000), ("new", 1700000000)] {
+        let mtree = format!("#mtree\n./usr/foo time={}.0 size=4 type=file\n", time);
+        Pkg::new(name)
+            .mtree(&mtree, None)
+            .dir("usr/")
+            .file("usr/foo", format!("{}\n", name).as_bytes())
+            .write(dir.path(), &format!("{}.pkg.tar", name), None);
+    }
+    Pkg::new("bare")
+        .dir("usr/")
+        .file("usr/foo", b"bare\n")
+        .write(dir.path(), "bare.pkg.tar", None);
+
+    let out = paccat(
+        dir.path(),
+        [
+            "--select-newest",
+            "-a",
+            "old.pkg.tar",
+            "new.pkg.tar",
+            "bare.pkg.tar",
+            "--",
+            "usr/foo",
+        ],
+    );
+    assert_eq!(stdout(&out), "new\nbare\n", "{}", stderr(&out));
+    assert!(
+        stderr(&out).contains("bare.pkg.tar does not contain a .MTREE"),
+        "{}",
+        stderr(&out)
+    );
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{paccat, stderr, stdout, Pkg, TempDir};
+
+fn read(flags: &[&str]) -> (String, String) {
+    let dir = TempDir::new("nonutf8");
+    Pkg::new("foo")
+        .dir("usr/")
+        .raw_file(b"usr/caf\xe9", b"latin-1\n")
+        .file("usr/cafe", b"utf-8\n")
+        .write(dir.path(), "foo.pkg.tar", None);
+
+    let args = [flags, &["foo.pkg.tar", "--regex", "--", "usr/caf.*"]].concat();
+    let out = paccat(dir.path(), args);
+    assert!(out.status.success(), "{}", stderr(&out));
+    (stdout(&out), stderr(&out))
+}
+
+#[test]
+fn skips_names_that_are_not_utf8() {
+    let (out, err) = read(&[]);
+    assert_eq!(out, "utf-8\n");
+    assert!(err.contains("its name is not valid UTF-8"), "{}", err);
+}
+
+#[test]
+fn allow_nonutf8_names_reads_them() {
+    let (out, err) = read(&["--allow-nonutf8-names"]);
+    assert_eq!(out, "latin-1\nutf-8\n");
+    assert_eq!(err, "");
+}
+
+#[test]
+fn does_not_extract_names_that_are_not_utf8() {
+    let dir = TempDir::new("nonutf8");
+    Pkg::new("foo")
+        .dir("usr/")
+        .raw_file(b"usr/caf\xe9", b"latin-1\n")
+        .raw_file(b"usr/caf\xe8", b"also latin-1\n")
+        .write(dir.path(), "foo.pkg.tar", None);
+
+    let out = paccat(
+        dir.path(),
+        [
+            "--allow-nonutf8-names",
+            "-e",
+            "foo.pkg.tar",
+            "--regex",
+            "--",
+            "usr/caf.*",
+        ],
+    );
+    assert!(!out.status.success());
+    assert!(
+        stderr(&out).contains("refusing to write 'usr/caf\u{fffd}'"),
+        "{}",
+        stderr(&out)
+    );
+    assert!(!dir.join("caf\u{fffd}").exists());
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "pacman")]
+
+mod common;
+
+use common::{compress_with, paccat, stderr, stdout, Pkg, TempDir};
+use std::fs;
+use std::process::Command;
+
+/// A sync db with one package, like repo-add makes.
+fn repo_db(filename: &str) -> Vec<u8> {
+    let desc = format!(
+        "%FILENAME%\n{}\n\n%NAME%\nfoo\n\n%VERSION%\n1.0-1\n\n%ARCH%\nany\n\n",
+        filename
+    );
+    let db = Pkg::bare()
+        .dir("foo-1.0-1/")
+        .file("foo-1.0-1/desc", desc.as_bytes())
+        .tar();
+    compress_with("gzip", &db)
+}
+
+#[test]
+fn reads_packages_from_a_file_server_in_place() {
+    if Command::new("pacman-conf")
+        .arg("--version")
+        .output()
+        .is_err()
+    {
+        eprintln!("pacman-conf is not installed, skipping");
+        return;
+    }
+
+    let dir = TempDir::new("repo");
+    let (repo, dbpath, cache) = (dir.join("repo"), dir.join("db"), dir.join("cache"));
+    for d in [&repo, &dbpath.join("sync"), &dbpath.join("local"), &cache] {
+        fs::create_dir_all(d).unwrap();
+    }
+    fs::write(dbpath.join("local/ALPM_DB_VERSION"), "9\n").unwrap();
+
+    let filename = "foo-1.0-1-any.pkg.tar";
+    Pkg::new("foo")
+        .dir("usr/")
+        .dir("usr/share/")
+        .file("usr/share/foo", b"from the fixture repo\n")
+        .write(&repo, filename, None);
+    fs::write(dbpath.join("sync/fixture.db"), repo_db(filename)).unwrap();
+
+    let conf = dir.join("pacman.conf");
+    fs::write(
+        &conf,
+        format!(
+            "[options]\nDBPath = {}/\nCacheDir = {}/\nArchitecture = auto\nSigLevel = Never\n\n\
+             [fixture]\nServer = file://{}\n",
+            dbpath.display(),
+            cache.display(),
+            repo.display()
+        ),
+    )
+    .unwrap();
+
+    let out = paccat(
+        dir.path(),
+        [
+            "--config",
+            conf.to_str().unwrap(),
+            "fixture/foo",
+            "--",
+            "usr/share/foo",
+        ],
+    );
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(stdout(&out), "from the fixture repo\n");
+    // read from the repo, not copied into the cache
+    assert_eq!(fs::read_dir(&cache).unwrap().count(), 0);
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::{paccat, stderr, stdout, Pkg, TempDir};
+use std::fs;
+
+/// A root with a package cache but without sync databases, like a system
+/// mounted for a rescue.
+fn root() -> TempDir {
+    let dir = TempDir::new("rescue");
+    let cache = dir.join("root/var/cache/pacman/pkg");
+    fs::create_dir_all(&cache).unwrap();
+    fs::create_dir_all(dir.join("root/var/lib/pacman/sync")).unwrap();
+    for version in ["1.9-1", "1.10-1"] {
+        Pkg::new("foo")
+            .dir("etc/")
+            .file("etc/foo.conf", format!("version {}\n", version).as_bytes())
+            .write(&cache, &format!("foo-{}-any.pkg.tar", version), None);
+    }
+    dir
+}
+
+#[test]
+fn reads_the_newest_package_in_the_cache() {
+    let dir = root();
+    let out = paccat(dir.path(), ["--root", "root", "foo", "--", "etc/foo.conf"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(stdout(&out), "version 1.10-1\n");
+}
+
+#[test]
+fn options_that_need_the_sync_dbs_fail() {
+    let dir = root();
+    let out = paccat(
+        dir.path(),
+        ["--root", "root", "--deps", "foo", "--", "etc/foo.conf"],
+    );
+    assert!(!out.status.success());
+    let err = stderr(&out);
+    assert!(
+        err.contains(
+            "--deps needs the sync databases, which are missing from root/var/lib/pacman/sync"
+        ),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn packages_missing_from_the_cache_fail() {
+    let dir = root();
+    let out = paccat(dir.path(), ["--root", "root", "bar", "--", "etc/bar.conf"]);
+    assert!(!out.status.success());
+    let err = stderr(&out);
+    assert!(err.contains("bar is not in the cache"), "{}", err);
+}